@@ -0,0 +1,86 @@
+//! Sparse corner runs against the dense `K × corners` baseline in `sr::dense`, on the same 10-minute maps as
+//! `benches/sr.rs`. Prints SR and peak heap of each path, then times both:
+//!
+//! ```bash
+//! cargo bench --bench dense --features dense-reference
+//! ```
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rust_sr_calculator::config::SrConfig;
+use rust_sr_calculator::sr::{dense, SRCalculator};
+use rust_sr_calculator::synthetic::synthetic_map;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+// Counts live heap bytes and the highest value since the last reset
+struct PeakAlloc;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(live, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+const MAPS: [(i32, i32); 3] = [(7, 10), (10, 10), (18, 10)];
+
+fn map(key_count: i32, minutes: i32) -> Vec<rust_sr_calculator::note::Note> {
+    // About 8 notes a second per 4 keys, as in benches/sr.rs
+    synthetic_map(key_count, minutes, (key_count + 3) / 4, key_count as u64)
+}
+
+// SR and peak heap above what was live before the call
+fn measure(calculate: impl FnOnce() -> f64) -> (f64, usize) {
+    let base = LIVE.load(Ordering::Relaxed);
+    PEAK.store(base, Ordering::Relaxed);
+    let sr = calculate();
+    (sr, PEAK.load(Ordering::Relaxed) - base)
+}
+
+fn bench_dense_vs_sparse(c: &mut Criterion) {
+    let config = SrConfig::default();
+    println!("| Map | Dense SR | Sparse SR | Dense peak | Sparse peak |");
+    println!("|---|---|---|---|---|");
+    for (key_count, minutes) in MAPS {
+        let notes = map(key_count, minutes);
+        let (dense_sr, dense_peak) = measure(|| dense::calculate_sr_with_config(&notes, key_count, 8.0, &config).unwrap());
+        let (sparse_sr, sparse_peak) = measure(|| SRCalculator::calculate_sr_with_config(&notes, key_count, 8.0, &config).unwrap());
+        assert_eq!(dense_sr, sparse_sr, "{}K", key_count);
+        let mib = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+        println!("| {}K {}min | {} | {} | {:.1} MiB | {:.1} MiB |", key_count, minutes, dense_sr, sparse_sr, mib(dense_peak), mib(sparse_peak));
+    }
+
+    let mut group = c.benchmark_group("dense vs sparse");
+    group.sample_size(10).measurement_time(Duration::from_secs(20));
+    for (key_count, minutes) in MAPS {
+        let notes = map(key_count, minutes);
+        let id = format!("{}K {}min", key_count, minutes);
+        group.bench_with_input(BenchmarkId::new("dense", &id), &notes, |b, notes| {
+            b.iter(|| dense::calculate_sr_with_config(black_box(notes), key_count, 8.0, &config).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("sparse", &id), &notes, |b, notes| {
+            b.iter(|| SRCalculator::calculate_sr_with_config(black_box(notes), key_count, 8.0, &config).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_dense_vs_sparse);
+criterion_main!(benches);
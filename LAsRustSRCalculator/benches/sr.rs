@@ -0,0 +1,35 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rust_sr_calculator::config::SrConfig;
+use rust_sr_calculator::sr::SRCalculator;
+use rust_sr_calculator::synthetic::synthetic_map;
+use rust_sr_calculator::SRAPI;
+use std::hint::black_box;
+use std::time::Duration;
+
+const SHORT_MAP: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/Resource/Jumpstream - Happy Hardcore Synthesizer (SK_la) [7k-1].osu");
+
+fn bench_short_map(c: &mut Criterion) {
+    let content = std::fs::read_to_string(SHORT_MAP).expect("test resource missing");
+    c.bench_function("short 7K", |b| b.iter(|| SRAPI::calculate_sr_from_str(black_box(&content)).unwrap()));
+}
+
+fn bench_long_maps(c: &mut Criterion) {
+    let mut group = c.benchmark_group("marathon");
+    group.sample_size(10).measurement_time(Duration::from_secs(20));
+    for (key_count, minutes) in [(7, 10), (10, 10), (18, 10)] {
+        // About 8 notes a second per 4 keys
+        let notes = synthetic_map(key_count, minutes, (key_count + 3) / 4, key_count as u64);
+        let id = format!("{}K {}min", key_count, minutes);
+        group.bench_with_input(BenchmarkId::new("sequential", &id), &notes, |b, notes| {
+            b.iter(|| SRCalculator::calculate_sr(black_box(notes), key_count, 8.0).unwrap())
+        });
+        let parallel = SrConfig { parallel: true, ..Default::default() };
+        group.bench_with_input(BenchmarkId::new("parallel", &id), &notes, |b, notes| {
+            b.iter(|| SRCalculator::calculate_sr_with_config(black_box(notes), key_count, 8.0, &parallel).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_short_map, bench_long_maps);
+criterion_main!(benches);
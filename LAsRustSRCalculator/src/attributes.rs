@@ -0,0 +1,72 @@
+/// Aggregate of one skill curve over the whole map.
+///
+/// Both values use the same weights as the final SR (note density × time), so
+/// sections without notes do not pull the numbers down.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[repr(C)]
+pub struct SkillSummary {
+    pub mean: f64,
+    // Average of the 91.5-94.5% weighted quantiles, the same cut the SR uses
+    pub percentile93: f64,
+}
+
+/// SR together with the per-skill breakdown it was computed from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DifficultyAttributes {
+    pub star_rating: f64,
+    pub key_count: i32,
+    pub od: f64,
+    pub note_count: usize,
+    pub hold_count: usize,
+    // Jbar: same-column repetition
+    pub jack: SkillSummary,
+    // Xbar: alternation between neighbouring columns
+    pub cross_column: SkillSummary,
+    // Pbar: pressing intensity, including LN bodies
+    pub pressing_intensity: SkillSummary,
+    // Abar: rhythm differences between neighbouring columns
+    pub unevenness: SkillSummary,
+    // Rbar: LN release difficulty
+    pub release: SkillSummary,
+    // C: notes within ±500ms
+    pub density: SkillSummary,
+    // Ks: columns in use
+    pub active_keys: SkillSummary,
+}
+
+/// C layout of [`DifficultyAttributes`] for `calculate_attributes_from_osu_file`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct SrDifficultyAttributes {
+    pub star_rating: f64,
+    pub key_count: i32,
+    pub od: f64,
+    pub note_count: u64,
+    pub hold_count: u64,
+    pub jack: SkillSummary,
+    pub cross_column: SkillSummary,
+    pub pressing_intensity: SkillSummary,
+    pub unevenness: SkillSummary,
+    pub release: SkillSummary,
+    pub density: SkillSummary,
+    pub active_keys: SkillSummary,
+}
+
+impl From<&DifficultyAttributes> for SrDifficultyAttributes {
+    fn from(attributes: &DifficultyAttributes) -> Self {
+        SrDifficultyAttributes {
+            star_rating: attributes.star_rating,
+            key_count: attributes.key_count,
+            od: attributes.od,
+            note_count: attributes.note_count as u64,
+            hold_count: attributes.hold_count as u64,
+            jack: attributes.jack,
+            cross_column: attributes.cross_column,
+            pressing_intensity: attributes.pressing_intensity,
+            unevenness: attributes.unevenness,
+            release: attributes.release,
+            density: attributes.density,
+            active_keys: attributes.active_keys,
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use crate::note::Note;
+use crate::parser::ParsedData;
+
+/// The minimum a mania beatmap needs for SR calculation.
+///
+/// Can be built directly from generated notes, without going through an .osu file.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ManiaBeatmap {
+    pub key_count: i32,
+    pub od: f64,
+    // Any order; sorted before calculation
+    pub notes: Vec<Note>,
+}
+
+impl ManiaBeatmap {
+    pub fn new(key_count: i32, od: f64, notes: Vec<Note>) -> Self {
+        ManiaBeatmap { key_count, od, notes }
+    }
+}
+
+impl From<&ParsedData> for ManiaBeatmap {
+    fn from(data: &ParsedData) -> Self {
+        let notes = data
+            .hit_objects
+            .iter()
+            .map(|o| Note::new(o.column, o.start_time, if o.is_hold() { o.end_time } else { -1 }))
+            .collect();
+        ManiaBeatmap { key_count: data.column_count, od: data.od, notes }
+    }
+}
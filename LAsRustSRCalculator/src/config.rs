@@ -0,0 +1,243 @@
+use crate::cross_matrix::CrossMatrixProvider;
+use crate::error::SrError;
+use crate::mods::NoteMod;
+use crate::params::SrParams;
+use std::collections::BTreeMap;
+
+/// Speed-changing mods. Nightcore differs from DoubleTime only in pitch, so both use 1.5x.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum RateMod {
+    DoubleTime,
+    Nightcore,
+    HalfTime,
+    Daycore,
+    Custom(f64),
+}
+
+impl RateMod {
+    pub fn rate(&self) -> f64 {
+        match self {
+            RateMod::DoubleTime | RateMod::Nightcore => 1.5,
+            RateMod::HalfTime | RateMod::Daycore => 0.75,
+            RateMod::Custom(rate) => *rate,
+        }
+    }
+}
+
+/// Difficulty mods that change OD.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum OdModifier {
+    #[default]
+    None,
+    // OD × 1.4, capped at 10
+    HardRock,
+    // OD × 0.5
+    Easy,
+}
+
+impl OdModifier {
+    pub fn apply(&self, od: f64) -> f64 {
+        match self {
+            OdModifier::None => od,
+            OdModifier::HardRock => (od * 1.4).min(10.0),
+            OdModifier::Easy => od * 0.5,
+        }
+    }
+}
+
+/// How the great (300) hit window is derived from OD.
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum HitWindowModel {
+    /// osu!stable: `64.5 - ceil(3 * OD)` ms.
+    #[default]
+    Stable,
+    /// osu!lazer: `64 - 3 * OD` ms.
+    Lazer,
+    /// Fixed great window in ms, for judgements from other games. OD is ignored.
+    Custom { great: f64 },
+}
+
+impl HitWindowModel {
+    /// Half-width of the great window in milliseconds.
+    pub fn great_window(&self, od: f64) -> f64 {
+        match self {
+            HitWindowModel::Stable => 64.5 - (od * 3.0).ceil(),
+            HitWindowModel::Lazer => 64.0 - 3.0 * od,
+            HitWindowModel::Custom { great } => *great,
+        }
+    }
+}
+
+/// Version of the SR algorithm. Stored ratings can be recomputed with the version that produced them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum SrAlgorithm {
+    /// Port of the Star-Rating-Rebirth script, evaluated at note corners. Uses `SrParams` and cross matrices.
+    #[default]
+    Rebirth,
+    /// SR-V3.0 (`tests/AnalysisSR/SR-V3.0.cs`): evaluated every millisecond, with its own cross matrices
+    /// and final scaling. Supports odd key counts only up to 9K; `SrParams` do not apply. Maps spanning
+    /// more than [`MAX_V30_SPAN_MS`] of played time give `MapTooLong`.
+    V30,
+}
+
+/// Longest span of played time, from the first note (or 0 ms) to the last release, that
+/// [`SrAlgorithm::V30`] accepts. Its arrays hold a value per millisecond and per column, which at 18K
+/// comes to about 0.5 KB a millisecond, so 30 minutes already needs close to 1 GB.
+pub const MAX_V30_SPAN_MS: i64 = 30 * 60 * 1000;
+
+impl SrAlgorithm {
+    pub const ALL: [SrAlgorithm; 2] = [SrAlgorithm::Rebirth, SrAlgorithm::V30];
+
+    /// Stable number of this version, as used over the C ABI.
+    pub fn id(&self) -> i32 {
+        match self {
+            SrAlgorithm::Rebirth => 0,
+            SrAlgorithm::V30 => 1,
+        }
+    }
+
+    pub fn from_id(id: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|algorithm| algorithm.id() == id)
+    }
+}
+
+/// osu! mod bits understood by [`SrConfig::from_legacy_mods`].
+pub struct LegacyMods;
+
+impl LegacyMods {
+    pub const NO_FAIL: u32 = 1 << 0;
+    pub const EASY: u32 = 1 << 1;
+    pub const HARD_ROCK: u32 = 1 << 4;
+    pub const DOUBLE_TIME: u32 = 1 << 6;
+    pub const HALF_TIME: u32 = 1 << 8;
+    // Always set together with DOUBLE_TIME
+    pub const NIGHTCORE: u32 = 1 << 9;
+    // Dual stages
+    pub const KEY_COOP: u32 = 1 << 25;
+    pub const MIRROR: u32 = 1 << 30;
+}
+
+/// Options for a single SR calculation. `SrConfig::default()` gives the plain nomod result.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SrConfig {
+    pub algorithm: SrAlgorithm,
+    // Playback rate; note times are divided by it while hit windows stay in real time
+    pub rate: f64,
+    pub od_modifier: OdModifier,
+    pub hit_window: HitWindowModel,
+    // Applied to the notes in order before anything else
+    pub note_mods: Vec<NoteMod>,
+    // Cross matrices by key count, used instead of `CrossMatrixProvider` for this calculation
+    pub cross_matrices: BTreeMap<usize, Vec<f64>>,
+    // Constants of the algorithm itself
+    pub params: SrParams,
+    // Runs the skill stages and columns of this map on rayon threads. Same result; can only be faster with idle cores
+    pub parallel: bool,
+}
+
+impl Default for SrConfig {
+    fn default() -> Self {
+        SrConfig {
+            algorithm: SrAlgorithm::Rebirth,
+            rate: 1.0,
+            od_modifier: OdModifier::None,
+            hit_window: HitWindowModel::Stable,
+            note_mods: vec![],
+            cross_matrices: BTreeMap::new(),
+            params: SrParams::default(),
+            parallel: false,
+        }
+    }
+}
+
+impl From<RateMod> for SrConfig {
+    fn from(rate_mod: RateMod) -> Self {
+        SrConfig { rate: rate_mod.rate(), ..Default::default() }
+    }
+}
+
+impl SrConfig {
+    /// Config for a combination of osu! mod bits (including Mirror and KeyCoop). Unrelated bits are ignored; EZ wins over
+    /// HR and DT over HT when both are set.
+    pub fn from_legacy_mods(mods: u32) -> Self {
+        let rate = if mods & (LegacyMods::DOUBLE_TIME | LegacyMods::NIGHTCORE) != 0 {
+            RateMod::DoubleTime.rate()
+        } else if mods & LegacyMods::HALF_TIME != 0 {
+            RateMod::HalfTime.rate()
+        } else {
+            1.0
+        };
+        let od_modifier = if mods & LegacyMods::EASY != 0 {
+            OdModifier::Easy
+        } else if mods & LegacyMods::HARD_ROCK != 0 {
+            OdModifier::HardRock
+        } else {
+            OdModifier::None
+        };
+        // Random is seeded per play in the game, so there is no bit for it here
+        let mut note_mods = vec![];
+        if mods & LegacyMods::KEY_COOP != 0 {
+            note_mods.push(NoteMod::DualStages);
+        }
+        if mods & LegacyMods::MIRROR != 0 {
+            note_mods.push(NoteMod::Mirror);
+        }
+        SrConfig { rate, od_modifier, note_mods, ..Default::default() }
+    }
+
+    /// Great window in ms after the OD modifier, in real (not rate-adjusted) time.
+    pub fn great_window(&self, od: f64) -> f64 {
+        self.hit_window.great_window(self.od_modifier.apply(od))
+    }
+
+    /// Cross matrix used for `k` keys: from this config, else from `CrossMatrixProvider`.
+    pub fn cross_matrix(&self, k: usize) -> Option<Vec<f64>> {
+        self.cross_matrices.get(&k).cloned().or_else(|| CrossMatrixProvider::get_matrix(k))
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), SrError> {
+        if !self.rate.is_finite() || self.rate <= 0.0 {
+            return Err(SrError::InvalidConfig(format!("rate must be positive, got {}", self.rate)));
+        }
+        if let HitWindowModel::Custom { great } = self.hit_window
+            && (!great.is_finite() || great <= 0.0)
+        {
+            return Err(SrError::InvalidConfig(format!("great window must be positive, got {}", great)));
+        }
+        for (&k, matrix) in &self.cross_matrices {
+            CrossMatrixProvider::validate(k, matrix)?;
+        }
+        self.params.validate()
+    }
+
+    /// Converts a time from the beatmap to the time it is played at.
+    pub(crate) fn scale_time(&self, time: i32) -> i32 {
+        if self.rate == 1.0 { time } else { (time as f64 / self.rate).floor() as i32 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_od_modifiers_and_hit_windows() {
+        assert_eq!(OdModifier::HardRock.apply(5.0), 7.0);
+        assert_eq!(OdModifier::HardRock.apply(8.0), 10.0);
+        assert_eq!(OdModifier::Easy.apply(8.0), 4.0);
+
+        assert_eq!(HitWindowModel::Stable.great_window(8.0), 40.5);
+        assert_eq!(HitWindowModel::Stable.great_window(8.1), 39.5);
+        assert_eq!(HitWindowModel::Lazer.great_window(8.0), 40.0);
+        assert_eq!(HitWindowModel::Custom { great: 45.0 }.great_window(8.0), 45.0);
+
+        let config = SrConfig::from_legacy_mods(LegacyMods::HARD_ROCK | LegacyMods::DOUBLE_TIME | LegacyMods::NIGHTCORE);
+        assert_eq!(config.rate, 1.5);
+        assert_eq!(config.great_window(5.0), 43.5);
+        assert_eq!(SrConfig::from_legacy_mods(LegacyMods::EASY | LegacyMods::HALF_TIME).great_window(8.0), 52.5);
+        assert_eq!(SrConfig::from_legacy_mods(0), SrConfig::default());
+
+        let invalid = SrConfig { hit_window: HitWindowModel::Custom { great: 0.0 }, ..Default::default() };
+        assert!(matches!(invalid.validate(), Err(SrError::InvalidConfig(_))));
+    }
+}
@@ -0,0 +1,1219 @@
+use std::collections::VecDeque;
+use std::ops::{BitOr, BitOrAssign};
+
+use crate::hit_object::{HitSample, HitSound};
+use crate::metadata::DifficultyInfo;
+use crate::timing::ControlPointInfo;
+
+/// An osu!standard hit object as read from `[HitObjects]`, before conversion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StandardObject {
+    pub x: f64,
+    pub y: f64,
+    pub time: i32,
+    pub object_type: i32,
+    // Slider repeat count (`slides`), 1 for a slider without repeats
+    pub slides: i32,
+    // Slider length in osu! pixels
+    pub length: f64,
+    // Hitsound of each slider node (head, repeats, tail); empty for other objects
+    pub node_sounds: Vec<i32>,
+    // Spinner end time, -1 for other objects
+    pub end_time: i32,
+    pub hit_sound: i32,
+    pub sample: HitSample,
+    // 1-based line in the .osu file, 0 when unknown
+    pub line: usize,
+    pub raw: String,
+}
+
+impl StandardObject {
+    pub fn parse(line: &str) -> Option<StandardObject> {
+        let params: Vec<&str> = line.split(',').collect();
+        if params.len() < 4 {
+            return None;
+        }
+
+        let object_type: i32 = params[3].trim().parse().ok()?;
+        let mut object = StandardObject {
+            x: params[0].trim().parse().ok()?,
+            y: params[1].trim().parse().ok()?,
+            time: params[2].trim().parse::<f64>().ok()? as i32,
+            object_type,
+            slides: 1,
+            length: 0.0,
+            node_sounds: Vec::new(),
+            end_time: -1,
+            hit_sound: params.get(4).and_then(|p| p.trim().parse().ok()).unwrap_or(0),
+            sample: HitSample::default(),
+            line: 0,
+            raw: line.to_string(),
+        };
+
+        // The hit sample is the last field, whose position depends on the object kind
+        let sample_idx = if object.is_slider() {
+            object.slides = params.get(6).and_then(|p| p.trim().parse().ok()).unwrap_or(1).max(1);
+            object.length = params.get(7).and_then(|p| p.trim().parse().ok()).unwrap_or(0.0_f64).max(0.0);
+            // Nodes without an edge sound use the hitsound of the slider
+            object.node_sounds = vec![object.hit_sound; object.slides as usize + 1];
+            if let Some(edge_sounds) = params.get(8) {
+                for (node, sound) in object.node_sounds.iter_mut().zip(edge_sounds.split('|')) {
+                    *node = sound.trim().parse().unwrap_or(*node);
+                }
+            }
+            10
+        } else if object.is_spinner() {
+            object.end_time = params.get(5).and_then(|p| p.trim().parse::<f64>().ok()).map_or(object.time, |t| t as i32);
+            6
+        } else {
+            5
+        };
+        if let Some(sample) = params.get(sample_idx) {
+            object.sample = HitSample::parse(&sample.split(':').collect::<Vec<_>>());
+        }
+
+        Some(object)
+    }
+
+    pub fn is_slider(&self) -> bool {
+        self.object_type & 2 != 0
+    }
+
+    pub fn is_spinner(&self) -> bool {
+        self.object_type & 8 != 0
+    }
+
+    /// Position as osu! reads it, truncated to whole pixels.
+    fn position(&self) -> (f32, f32) {
+        (self.x as i32 as f32, self.y as i32 as f32)
+    }
+
+    /// Hitsound of the node at `index`, or of the object itself when it has no such node.
+    fn node_sound(&self, index: usize) -> HitSound {
+        HitSound(self.node_sounds.get(index).copied().unwrap_or(self.hit_sound))
+    }
+}
+
+/// A mania note produced by the conversion, in the same shape as the parser's columns.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConvertedNote {
+    pub column: i32,
+    pub start: i32,
+    // Hold end time; equal to `start` for single notes
+    pub end: i32,
+    pub note_type: i32,
+    // Index of the source object in the slice passed to `convert`
+    pub source: usize,
+}
+
+/// The xorshift generator used by osu!stable's mania converter.
+pub struct LegacyRandom {
+    x: u32,
+    y: u32,
+    z: u32,
+    w: u32,
+}
+
+impl LegacyRandom {
+    const INT_TO_REAL: f64 = 1.0 / (i32::MAX as f64 + 1.0);
+
+    pub fn new(seed: i32) -> Self {
+        LegacyRandom { x: seed as u32, y: 842502087, z: 3579807591, w: 273326509 }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let t = self.x ^ (self.x << 11);
+        self.x = self.y;
+        self.y = self.z;
+        self.z = self.w;
+        self.w = self.w ^ (self.w >> 19) ^ t ^ (t >> 8);
+        self.w
+    }
+
+    pub fn next_i32(&mut self) -> i32 {
+        (self.next_u32() & 0x7FFF_FFFF) as i32
+    }
+
+    pub fn next_f64(&mut self) -> f64 {
+        Self::INT_TO_REAL * self.next_i32() as f64
+    }
+
+    /// Integer in `[lower, upper)`.
+    pub fn next_range(&mut self, lower: i32, upper: i32) -> i32 {
+        (lower as f64 + self.next_f64() * (upper - lower) as f64) as i32
+    }
+}
+
+/// Converts osu!standard beatmaps to mania the way osu!stable does.
+///
+/// This is a port of stable's converter as mirrored by osu!lazer's `ManiaBeatmapConverter`: the column
+/// count, the seed, and the three pattern generators. Circles go through the hit object generator,
+/// sliders through the distance object generator and spinners through the end time object generator.
+/// Each generator sees the pattern produced for the previous object, so the whole map has to be
+/// converted in order with a single random generator.
+pub struct StandardConverter;
+
+impl StandardConverter {
+    // Spinners shorter than this stay single notes instead of becoming holds
+    const MIN_HOLD_DURATION: i32 = 100;
+
+    /// Column count chosen by osu!stable for a converted beatmap.
+    pub fn column_count(difficulty: &DifficultyInfo, objects: &[StandardObject]) -> i32 {
+        let rounded_cs = difficulty.circle_size.round_ties_even();
+        let rounded_od = difficulty.overall_difficulty.round_ties_even();
+
+        let total = objects.len();
+        if total == 0 {
+            return 4.max(7.min(rounded_od as i32 + 1));
+        }
+        let circles = objects.iter().filter(|o| !o.is_slider() && !o.is_spinner()).count();
+        let percent_special = (total - circles) as f64 / total as f64;
+
+        if percent_special < 0.2 {
+            return 7;
+        }
+        if percent_special < 0.3 || rounded_cs >= 5.0 {
+            return if rounded_od > 5.0 { 7 } else { 6 };
+        }
+        if percent_special > 0.6 {
+            return if rounded_od > 4.0 { 5 } else { 4 };
+        }
+        4.max(7.min(rounded_od as i32 + 1))
+    }
+
+    /// Seed of the conversion random generator, derived from the difficulty settings.
+    pub fn seed(difficulty: &DifficultyInfo) -> i32 {
+        let drain_and_cs = (difficulty.hp_drain_rate as f32 + difficulty.circle_size as f32).round_ties_even() as i32;
+        drain_and_cs * 20
+            + (difficulty.overall_difficulty as f32 as f64 * 41.2) as i32
+            + (difficulty.approach_rate as f32).round_ties_even() as i32
+    }
+
+    /// Converts `objects` and returns the column count with the notes sorted by start time.
+    /// `break_time` is the total length of the `[Events]` breaks in milliseconds.
+    pub fn convert(
+        objects: &[StandardObject],
+        difficulty: &DifficultyInfo,
+        control_points: &ControlPointInfo,
+        break_time: f64,
+    ) -> (i32, Vec<ConvertedNote>) {
+        let column_count = Self::column_count(difficulty, objects);
+        let mut random = LegacyRandom::new(Self::seed(difficulty));
+
+        let mut sorted: Vec<(usize, &StandardObject)> = objects.iter().enumerate().collect();
+        sorted.sort_by_key(|(_, o)| o.time);
+        let conversion_difficulty = Self::conversion_difficulty(difficulty, &sorted, break_time);
+
+        let mut last_pattern = Pattern::default();
+        let mut last_time = 0.0;
+        let mut last_position = (0.0, 0.0);
+        let mut last_stair = PatternType::STAIR;
+        let mut density = NoteDensity::default();
+        let mut notes = Vec::with_capacity(sorted.len());
+
+        for (source, object) in sorted {
+            let base = Generator {
+                random: &mut random,
+                object,
+                source,
+                previous: &last_pattern,
+                total_columns: column_count,
+                starting_column: if column_count == 8 { 1 } else { 0 },
+                conversion_difficulty,
+            };
+
+            let patterns = if object.is_slider() {
+                let generator = DistanceObjectPatternGenerator::new(base, difficulty, control_points);
+                for i in 0..=generator.span_count {
+                    let time = (object.time + generator.segment_duration * i) as f64;
+                    last_time = time;
+                    last_position = object.position();
+                    density.add(time);
+                }
+                generator.generate()
+            } else if object.is_spinner() {
+                let end_time = object.end_time.max(object.time) as f64;
+                last_time = end_time;
+                last_position = (256.0, 192.0);
+                density.add(end_time);
+                // End time patterns are not passed on to the next object
+                let pattern = EndTimeObjectPatternGenerator::new(base).generate();
+                notes.extend(pattern.notes);
+                continue;
+            } else {
+                density.add(object.time as f64);
+                let generator =
+                    HitObjectPatternGenerator::new(base, control_points, last_time, last_position, density.value, last_stair);
+                last_time = object.time as f64;
+                last_position = object.position();
+                last_stair = generator.stair_type;
+                vec![generator.generate()]
+            };
+
+            for pattern in patterns {
+                notes.extend(pattern.notes.iter().copied());
+                last_pattern = pattern;
+            }
+        }
+
+        // Stable sort, so notes of one object keep the order they were generated in
+        notes.sort_by_key(|n| n.start);
+        (column_count, notes)
+    }
+
+    /// How dense the original map is, from 0 to 12. Decides how many notes a generator may stack.
+    fn conversion_difficulty(difficulty: &DifficultyInfo, sorted: &[(usize, &StandardObject)], break_time: f64) -> f64 {
+        let first = sorted.first().map_or(0, |(_, o)| o.time);
+        let last = sorted.last().map_or(0, |(_, o)| o.time);
+
+        // Drain time in whole seconds
+        let mut drain_time = ((last - first) as f64 - break_time) / 1000.0;
+        drain_time = drain_time.trunc();
+        if drain_time == 0.0 {
+            drain_time = 10000.0;
+        }
+
+        let hp_and_ar = (difficulty.hp_drain_rate as f32 + (difficulty.approach_rate as f32).clamp(4.0, 7.0)) as f64;
+        let value = (hp_and_ar / 1.5 + sorted.len() as f64 / drain_time * 9.0) / 38.0 * 5.0 / 1.15;
+        value.min(12.0)
+    }
+}
+
+/// Average gap between the last seven note times seen by the converter.
+struct NoteDensity {
+    times: VecDeque<f64>,
+    value: f64,
+}
+
+impl Default for NoteDensity {
+    fn default() -> Self {
+        NoteDensity { times: VecDeque::with_capacity(Self::CAPACITY), value: i32::MAX as f64 }
+    }
+}
+
+impl NoteDensity {
+    const CAPACITY: usize = 7;
+
+    fn add(&mut self, time: f64) {
+        if self.times.len() == Self::CAPACITY {
+            self.times.pop_front();
+        }
+        self.times.push_back(time);
+
+        if let (Some(first), Some(last)) = (self.times.front(), self.times.back())
+            && self.times.len() >= 2
+        {
+            self.value = (last - first) / self.times.len() as f64;
+        }
+    }
+}
+
+/// Flags steering the pattern generators (`PatternType` in osu!lazer).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct PatternType(u32);
+
+impl PatternType {
+    const NONE: PatternType = PatternType(0);
+    // Keep the columns of the previous pattern
+    const FORCE_STACK: PatternType = PatternType(1);
+    // Avoid the columns of the previous pattern
+    const FORCE_NOT_STACK: PatternType = PatternType(1 << 1);
+    const KEEP_SINGLE: PatternType = PatternType(1 << 2);
+    const LOW_PROBABILITY: PatternType = PatternType(1 << 3);
+    // Place chord notes in neighbouring columns
+    const GATHERED: PatternType = PatternType(1 << 7);
+    const MIRROR: PatternType = PatternType(1 << 8);
+    const REVERSE: PatternType = PatternType(1 << 9);
+    const CYCLE: PatternType = PatternType(1 << 10);
+    const STAIR: PatternType = PatternType(1 << 11);
+    const REVERSE_STAIR: PatternType = PatternType(1 << 12);
+
+    fn has(self, flag: PatternType) -> bool {
+        self.0 & flag.0 != 0
+    }
+
+    fn remove(&mut self, flag: PatternType) {
+        self.0 &= !flag.0;
+    }
+}
+
+impl BitOr for PatternType {
+    type Output = PatternType;
+
+    fn bitor(self, rhs: PatternType) -> PatternType {
+        PatternType(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for PatternType {
+    fn bitor_assign(&mut self, rhs: PatternType) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Notes generated for one object, with the set of columns they occupy.
+#[derive(Clone, Debug, Default)]
+struct Pattern {
+    notes: Vec<ConvertedNote>,
+    columns: u32,
+}
+
+impl Pattern {
+    fn add(&mut self, note: ConvertedNote) {
+        self.columns |= 1 << note.column;
+        self.notes.push(note);
+    }
+
+    fn append(&mut self, other: &Pattern) {
+        for note in &other.notes {
+            self.add(*note);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.notes.clear();
+        self.columns = 0;
+    }
+
+    fn has_column(&self, column: i32) -> bool {
+        (0..32).contains(&column) && self.columns & (1 << column) != 0
+    }
+
+    /// Number of distinct columns with notes.
+    fn column_count(&self) -> i32 {
+        self.columns.count_ones() as i32
+    }
+}
+
+/// State shared by the three generators for one object (`LegacyPatternGenerator` in osu!lazer).
+struct Generator<'a> {
+    random: &'a mut LegacyRandom,
+    object: &'a StandardObject,
+    source: usize,
+    previous: &'a Pattern,
+    total_columns: i32,
+    // 1 for 8K, whose first column is the special column
+    starting_column: i32,
+    conversion_difficulty: f64,
+}
+
+impl Generator<'_> {
+    /// Column under the x position. With `allow_special`, 8K maps only use the seven normal columns.
+    fn column_for_x(&self, allow_special: bool) -> i32 {
+        let x = self.object.position().0;
+        if allow_special && self.total_columns == 8 {
+            let divisor = 512.0_f32 / 7.0;
+            return ((x / divisor).floor() as i32).clamp(0, 6) + 1;
+        }
+        let divisor = 512.0_f32 / self.total_columns as f32;
+        ((x / divisor).floor() as i32).clamp(0, self.total_columns - 1)
+    }
+
+    fn random_column(&mut self, lower: Option<i32>, upper: Option<i32>) -> i32 {
+        self.random.next_range(lower.unwrap_or(self.starting_column), upper.unwrap_or(self.total_columns))
+    }
+
+    /// Draws a note count from 1 to 6, where `probabilities[i]` is the chance of at least `i + 2` notes.
+    fn random_note_count(&mut self, probabilities: &[f64]) -> i32 {
+        let value = self.random.next_f64();
+        for (i, p) in probabilities.iter().enumerate().rev() {
+            if value >= 1.0 - p {
+                return i as i32 + 2;
+            }
+        }
+        1
+    }
+
+    /// `initial` if it is free, otherwise random columns in `lower..upper` until one is free.
+    fn find_available_column(
+        &mut self,
+        initial: i32,
+        lower: Option<i32>,
+        upper: Option<i32>,
+        exclude: Option<i32>,
+        patterns: &[&Pattern],
+    ) -> Option<i32> {
+        let (lower, upper) = (lower.unwrap_or(self.starting_column), upper.unwrap_or(self.total_columns));
+        self.find_available_column_with(initial, lower, upper, exclude, patterns, |random, _| random.next_range(lower, upper))
+    }
+
+    /// Like [`Self::find_available_column`], with `next` choosing the column to try after a taken one.
+    /// `None` where osu! gives up on the map because every column is taken; the caller keeps the notes
+    /// it has placed so far.
+    fn find_available_column_with(
+        &mut self,
+        initial: i32,
+        lower: i32,
+        upper: i32,
+        exclude: Option<i32>,
+        patterns: &[&Pattern],
+        mut next: impl FnMut(&mut LegacyRandom, i32) -> i32,
+    ) -> Option<i32> {
+        let is_valid = |column: i32| exclude != Some(column) && patterns.iter().all(|p| !p.has_column(column));
+
+        if is_valid(initial) {
+            return Some(initial);
+        }
+        if !(lower..upper).any(is_valid) {
+            return None;
+        }
+
+        let mut column = initial;
+        loop {
+            column = next(self.random, column);
+            if is_valid(column) {
+                return Some(column);
+            }
+        }
+    }
+
+    fn add_note(&self, pattern: &mut Pattern, column: i32, start: i32, end: i32) {
+        let note_type = if start == end { 1 } else { 128 };
+        pattern.add(ConvertedNote { column, start, end, note_type, source: self.source });
+    }
+}
+
+/// Turns a circle into single notes, choosing a pattern from the time and distance to the previous object.
+struct HitObjectPatternGenerator<'a> {
+    base: Generator<'a>,
+    convert_type: PatternType,
+    // Stair direction handed on to the next circle
+    stair_type: PatternType,
+}
+
+impl<'a> HitObjectPatternGenerator<'a> {
+    fn new(
+        base: Generator<'a>,
+        control_points: &ControlPointInfo,
+        previous_time: f64,
+        previous_position: (f32, f32),
+        density: f64,
+        last_stair: PatternType,
+    ) -> Self {
+        let time = base.object.time as f64;
+        let beat_length = control_points.beat_length_at(time).unwrap_or(DEFAULT_BEAT_LENGTH);
+        let kiai = control_points.kiai_at(time);
+
+        let (x, y) = base.object.position();
+        let position_separation = ((x - previous_position.0).powi(2) + (y - previous_position.1).powi(2)).sqrt();
+        let time_separation = time - previous_time;
+
+        let mut convert_type = PatternType::NONE;
+        if time_separation <= 80.0 {
+            // More than 187 BPM
+            convert_type |= PatternType::FORCE_NOT_STACK | PatternType::KEEP_SINGLE;
+        } else if time_separation <= 95.0 {
+            // More than 157 BPM
+            convert_type |= PatternType::FORCE_NOT_STACK | PatternType::KEEP_SINGLE | last_stair;
+        } else if time_separation <= 105.0 {
+            // More than 140 BPM
+            convert_type |= PatternType::FORCE_NOT_STACK | PatternType::LOW_PROBABILITY;
+        } else if time_separation <= 125.0 {
+            // More than 120 BPM
+            convert_type |= PatternType::FORCE_NOT_STACK;
+        } else if time_separation <= 135.0 && position_separation < 20.0 {
+            // More than 111 BPM stream
+            convert_type |= PatternType::CYCLE | PatternType::KEEP_SINGLE;
+        } else if time_separation <= 150.0 && position_separation < 20.0 {
+            // More than 100 BPM stream
+            convert_type |= PatternType::FORCE_STACK | PatternType::LOW_PROBABILITY;
+        } else if position_separation < 20.0 && density >= beat_length / 2.5 {
+            // Low density stream
+            convert_type |= PatternType::REVERSE | PatternType::LOW_PROBABILITY;
+        } else if density < beat_length / 2.5 || kiai {
+            // High density
+        } else {
+            convert_type |= PatternType::LOW_PROBABILITY;
+        }
+
+        if !convert_type.has(PatternType::KEEP_SINGLE) {
+            let sound = HitSound(base.object.hit_sound);
+            if sound.finish() && base.total_columns != 8 {
+                convert_type |= PatternType::MIRROR;
+            } else if sound.clap() {
+                convert_type |= PatternType::GATHERED;
+            }
+        }
+
+        HitObjectPatternGenerator { base, convert_type, stair_type: last_stair }
+    }
+
+    fn generate(mut self) -> Pattern {
+        let total = self.base.total_columns;
+        let start = self.base.starting_column;
+        let previous = self.base.previous;
+        let mut pattern = Pattern::default();
+
+        if total == 1 {
+            self.add_note(&mut pattern, 0);
+            return pattern;
+        }
+
+        let last_column = previous.notes.first().map_or(0, |n| n.column);
+
+        if self.convert_type.has(PatternType::REVERSE) && !previous.notes.is_empty() {
+            // Copy the previous pattern in reverse column order
+            for i in start..total {
+                if previous.has_column(i) {
+                    self.add_note(&mut pattern, start + total - i - 1);
+                }
+            }
+            return pattern;
+        }
+
+        if self.convert_type.has(PatternType::CYCLE)
+            && previous.notes.len() == 1
+            // Don't overload the special column of 8K
+            && (total != 8 || last_column != 0)
+            // The previous note was not in the centre column
+            && (total % 2 == 0 || last_column != total / 2)
+        {
+            // Like Reverse, for a single note
+            self.add_note(&mut pattern, start + total - last_column - 1);
+            return pattern;
+        }
+
+        if self.convert_type.has(PatternType::FORCE_STACK) && !previous.notes.is_empty() {
+            // Repeat the columns of the previous pattern
+            for i in start..total {
+                if previous.has_column(i) {
+                    self.add_note(&mut pattern, i);
+                }
+            }
+            return pattern;
+        }
+
+        if previous.notes.len() == 1 {
+            if self.convert_type.has(PatternType::STAIR) {
+                // Next column, wrapping around to the first
+                let mut column = last_column + 1;
+                if column == total {
+                    column = start;
+                }
+                self.add_note(&mut pattern, column);
+                return pattern;
+            }
+
+            if self.convert_type.has(PatternType::REVERSE_STAIR) {
+                // Previous column, wrapping around to the last
+                let mut column = last_column - 1;
+                if column == start - 1 {
+                    column = total - 1;
+                }
+                self.add_note(&mut pattern, column);
+                return pattern;
+            }
+        }
+
+        if self.convert_type.has(PatternType::KEEP_SINGLE) {
+            return self.generate_random_notes(1);
+        }
+
+        let difficulty = self.base.conversion_difficulty;
+        let low_probability = self.convert_type.has(PatternType::LOW_PROBABILITY);
+
+        if self.convert_type.has(PatternType::MIRROR) {
+            return if difficulty > 6.5 {
+                self.generate_random_pattern_with_mirrored(0.12, 0.38, 0.12)
+            } else if difficulty > 4.0 {
+                self.generate_random_pattern_with_mirrored(0.12, 0.17, 0.0)
+            } else {
+                self.generate_random_pattern_with_mirrored(0.12, 0.0, 0.0)
+            };
+        }
+
+        if difficulty > 6.5 {
+            if low_probability {
+                return self.generate_random_pattern(0.78, 0.42, 0.0, 0.0);
+            }
+            return self.generate_random_pattern(1.0, 0.62, 0.0, 0.0);
+        }
+        if difficulty > 4.0 {
+            if low_probability {
+                return self.generate_random_pattern(0.35, 0.08, 0.0, 0.0);
+            }
+            return self.generate_random_pattern(0.52, 0.15, 0.0, 0.0);
+        }
+        if difficulty > 2.0 {
+            if low_probability {
+                return self.generate_random_pattern(0.18, 0.0, 0.0, 0.0);
+            }
+            return self.generate_random_pattern(0.45, 0.0, 0.0, 0.0);
+        }
+        self.generate_random_pattern(0.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Up to `note_count` notes in different columns, starting under the x position.
+    fn generate_random_notes(&mut self, mut note_count: i32) -> Pattern {
+        let mut pattern = Pattern::default();
+        let (total, start) = (self.base.total_columns, self.base.starting_column);
+        let previous = self.base.previous;
+
+        let allow_stacking = !self.convert_type.has(PatternType::FORCE_NOT_STACK);
+        if !allow_stacking {
+            note_count = note_count.min(total - start - previous.column_count());
+        }
+
+        let gathered = self.convert_type.has(PatternType::GATHERED);
+        let mut column = self.base.column_for_x(true);
+        for _ in 0..note_count {
+            let taken = pattern.clone();
+            let patterns: &[&Pattern] = if allow_stacking { &[&taken] } else { &[&taken, previous] };
+            let next = self.base.find_available_column_with(column, start, total, None, patterns, |random, last| {
+                if gathered {
+                    if last + 1 == total { start } else { last + 1 }
+                } else {
+                    random.next_range(start, total)
+                }
+            });
+            let Some(next) = next else {
+                break;
+            };
+            column = next;
+            self.add_note(&mut pattern, column);
+        }
+
+        pattern
+    }
+
+    /// Whether the object can add a note in the special column of 8K.
+    fn has_special_column(&self) -> bool {
+        let sound = HitSound(self.base.object.hit_sound);
+        sound.clap() && sound.finish()
+    }
+
+    fn generate_random_pattern(&mut self, p2: f64, p3: f64, p4: f64, p5: f64) -> Pattern {
+        let note_count = self.random_note_count(p2, p3, p4, p5);
+        let mut pattern = Pattern::default();
+        pattern.append(&self.generate_random_notes(note_count));
+
+        if self.base.starting_column > 0 && self.has_special_column() {
+            self.add_note(&mut pattern, 0);
+        }
+        pattern
+    }
+
+    /// Notes on the left half, mirrored onto the right half, with an optional centre note.
+    fn generate_random_pattern_with_mirrored(&mut self, centre_probability: f64, p2: f64, p3: f64) -> Pattern {
+        if self.convert_type.has(PatternType::FORCE_NOT_STACK) {
+            return self.generate_random_pattern(0.5 + p2 / 2.0, p2, (p2 + p3) / 2.0, p3);
+        }
+
+        let mut pattern = Pattern::default();
+        let (total, start) = (self.base.total_columns, self.base.starting_column);
+        let (note_count, add_to_centre) = self.random_note_count_mirrored(centre_probability, p2, p3);
+
+        let column_limit = (if total % 2 == 0 { total } else { total - 1 }) / 2;
+        let mut column = self.base.random_column(None, Some(column_limit));
+        for _ in 0..note_count {
+            let taken = pattern.clone();
+            let Some(next) = self.base.find_available_column(column, None, Some(column_limit), None, &[&taken]) else {
+                break;
+            };
+            column = next;
+            self.add_note(&mut pattern, column);
+            self.add_note(&mut pattern, start + total - column - 1);
+        }
+
+        if add_to_centre {
+            self.add_note(&mut pattern, total / 2);
+        }
+        if start > 0 && self.has_special_column() {
+            self.add_note(&mut pattern, 0);
+        }
+        pattern
+    }
+
+    fn random_note_count(&mut self, mut p2: f64, mut p3: f64, mut p4: f64, mut p5: f64) -> i32 {
+        match self.base.total_columns {
+            2 => (p2, p3, p4, p5) = (0.0, 0.0, 0.0, 0.0),
+            3 => (p2, p3, p4, p5) = (p2.min(0.1), 0.0, 0.0, 0.0),
+            4 => (p2, p3, p4, p5) = (p2.min(0.23), p3.min(0.04), 0.0, 0.0),
+            5 => (p3, p4, p5) = (p3.min(0.15), p4.min(0.03), 0.0),
+            _ => {}
+        }
+        if HitSound(self.base.object.hit_sound).clap() {
+            p2 = 1.0;
+        }
+        self.base.random_note_count(&[p2, p3, p4, p5])
+    }
+
+    fn random_note_count_mirrored(&mut self, mut centre_probability: f64, mut p2: f64, mut p3: f64) -> (i32, bool) {
+        // Stable compares with inverse probabilities (value > x), so halving a chance there is doubling 1 - p here
+        match self.base.total_columns {
+            2 => (centre_probability, p2, p3) = (0.0, 0.0, 0.0),
+            3 => (centre_probability, p2, p3) = (centre_probability.min(0.03), 0.0, 0.0),
+            4 => (centre_probability, p2, p3) = (0.0, 1.0 - ((1.0 - p2) * 2.0).max(0.8), 0.0),
+            5 => (centre_probability, p3) = (centre_probability.min(0.03), 0.0),
+            6 => {
+                centre_probability = 0.0;
+                p2 = 1.0 - ((1.0 - p2) * 2.0).max(0.5);
+                p3 = 1.0 - ((1.0 - p3) * 2.0).max(0.85);
+            }
+            _ => {}
+        }
+        let (p2, p3) = (p2.clamp(0.0, 1.0), p3.clamp(0.0, 1.0));
+
+        let centre_value = self.base.random.next_f64();
+        let note_count = self.base.random_note_count(&[p2, p3]);
+        let add_to_centre = self.base.total_columns % 2 != 0 && note_count != 3 && centre_value > 1.0 - centre_probability;
+        (note_count, add_to_centre)
+    }
+
+    fn add_note(&self, pattern: &mut Pattern, column: i32) {
+        let time = self.base.object.time;
+        self.base.add_note(pattern, column, time, time);
+    }
+}
+
+/// Turns a slider into notes on its nodes or hold notes over its length, depending on how long each span is.
+struct DistanceObjectPatternGenerator<'a> {
+    base: Generator<'a>,
+    convert_type: PatternType,
+    start_time: i32,
+    end_time: i32,
+    segment_duration: i32,
+    span_count: i32,
+}
+
+impl<'a> DistanceObjectPatternGenerator<'a> {
+    fn new(base: Generator<'a>, difficulty: &DifficultyInfo, control_points: &ControlPointInfo) -> Self {
+        let time = base.object.time as f64;
+        let convert_type = if control_points.kiai_at(time) { PatternType::NONE } else { PatternType::LOW_PROBABILITY };
+
+        let beat_length = control_points.beat_length_at(time).unwrap_or(DEFAULT_BEAT_LENGTH)
+            * control_points.legacy_bpm_multiplier_at(time);
+        let span_count = base.object.slides.max(1);
+        let start_time = time.round() as i32;
+        // The same expression as stable, including the order of operations
+        let end_time = (start_time as f64
+            + base.object.length * beat_length * span_count as f64 * 0.01 / difficulty.slider_multiplier)
+            .floor() as i32;
+        let segment_duration = (end_time - start_time) / span_count;
+
+        DistanceObjectPatternGenerator { base, convert_type, start_time, end_time, segment_duration, span_count }
+    }
+
+    /// The generated pattern, split so that the notes ending with the slider come last; only those are
+    /// passed on as the previous pattern.
+    fn generate(mut self) -> Vec<Pattern> {
+        let pattern = self.generate_pattern();
+        if pattern.notes.len() == 1 {
+            return vec![pattern];
+        }
+
+        let mut intermediate = Pattern::default();
+        let mut at_end = Pattern::default();
+        for note in pattern.notes {
+            if note.end == self.end_time {
+                at_end.add(note);
+            } else {
+                intermediate.add(note);
+            }
+        }
+        vec![intermediate, at_end]
+    }
+
+    fn generate_pattern(&mut self) -> Pattern {
+        let (total, start) = (self.base.total_columns, self.base.starting_column);
+        let (start_time, segment) = (self.start_time, self.segment_duration);
+
+        if total == 1 {
+            let mut pattern = Pattern::default();
+            self.add_note(&mut pattern, 0, start_time, self.end_time);
+            return pattern;
+        }
+
+        if self.span_count > 1 {
+            if segment <= 90 {
+                return self.generate_random_hold_notes(start_time, 1);
+            }
+            if segment <= 120 {
+                self.convert_type |= PatternType::FORCE_NOT_STACK;
+                return self.generate_random_notes(start_time, self.span_count + 1);
+            }
+            if segment <= 160 {
+                return self.generate_stair(start_time);
+            }
+            if segment <= 200 && self.base.conversion_difficulty > 3.0 {
+                return self.generate_random_multiple_notes(start_time);
+            }
+            if self.end_time - start_time >= 4000 {
+                return self.generate_n_random_notes(start_time, 0.23, 0.0, 0.0);
+            }
+            if segment > 400 && self.span_count < total - 1 - start {
+                return self.generate_tiled_hold_notes(start_time);
+            }
+            return self.generate_hold_and_normal_notes(start_time);
+        }
+
+        if segment <= 110 {
+            if self.base.previous.column_count() < total {
+                self.convert_type |= PatternType::FORCE_NOT_STACK;
+            } else {
+                self.convert_type.remove(PatternType::FORCE_NOT_STACK);
+            }
+            return self.generate_random_notes(start_time, if segment < 80 { 1 } else { 2 });
+        }
+
+        let difficulty = self.base.conversion_difficulty;
+        let low_probability = self.convert_type.has(PatternType::LOW_PROBABILITY);
+        if difficulty > 6.5 {
+            if low_probability {
+                return self.generate_n_random_notes(start_time, 0.78, 0.3, 0.0);
+            }
+            return self.generate_n_random_notes(start_time, 0.85, 0.36, 0.03);
+        }
+        if difficulty > 4.0 {
+            if low_probability {
+                return self.generate_n_random_notes(start_time, 0.43, 0.08, 0.0);
+            }
+            return self.generate_n_random_notes(start_time, 0.56, 0.18, 0.0);
+        }
+        if difficulty > 2.5 {
+            if low_probability {
+                return self.generate_n_random_notes(start_time, 0.3, 0.0, 0.0);
+            }
+            return self.generate_n_random_notes(start_time, 0.37, 0.08, 0.0);
+        }
+        if low_probability {
+            return self.generate_n_random_notes(start_time, 0.17, 0.0, 0.0);
+        }
+        self.generate_n_random_notes(start_time, 0.27, 0.0, 0.0)
+    }
+
+    /// `note_count` hold notes over the whole slider, avoiding the previous pattern while columns are left.
+    fn generate_random_hold_notes(&mut self, start_time: i32, note_count: i32) -> Pattern {
+        let mut pattern = Pattern::default();
+        let previous = self.base.previous;
+        let usable_columns = self.base.total_columns - self.base.starting_column - previous.column_count();
+
+        let mut column = self.base.random_column(None, None);
+        for _ in 0..usable_columns.min(note_count) {
+            let taken = pattern.clone();
+            let Some(next) = self.base.find_available_column(column, None, None, None, &[&taken, previous]) else {
+                return pattern;
+            };
+            column = next;
+            self.add_note(&mut pattern, column, start_time, self.end_time);
+        }
+
+        // A separate loop, since it draws from the generator differently
+        for _ in 0..note_count - usable_columns {
+            let taken = pattern.clone();
+            let Some(next) = self.base.find_available_column(column, None, None, None, &[&taken]) else {
+                return pattern;
+            };
+            column = next;
+            self.add_note(&mut pattern, column, start_time, self.end_time);
+        }
+
+        pattern
+    }
+
+    /// One note per node, never twice in a row in the same column.
+    fn generate_random_notes(&mut self, mut start_time: i32, note_count: i32) -> Pattern {
+        let mut pattern = Pattern::default();
+        let previous = self.base.previous;
+
+        let mut column = self.base.column_for_x(true);
+        if self.convert_type.has(PatternType::FORCE_NOT_STACK) && previous.column_count() < self.base.total_columns {
+            column = self.base.find_available_column(column, None, None, None, &[previous]).unwrap_or(column);
+        }
+
+        let mut last_column = column;
+        for _ in 0..note_count {
+            self.add_note(&mut pattern, column, start_time, start_time);
+            let Some(next) = self.base.find_available_column(column, None, None, Some(last_column), &[]) else {
+                break;
+            };
+            column = next;
+            last_column = column;
+            start_time += self.segment_duration;
+        }
+
+        pattern
+    }
+
+    /// One note per node, walking across the columns and turning at the edges.
+    fn generate_stair(&mut self, mut start_time: i32) -> Pattern {
+        let mut pattern = Pattern::default();
+        let (total, start) = (self.base.total_columns, self.base.starting_column);
+
+        let mut column = self.base.column_for_x(true);
+        let mut increasing = self.base.random.next_f64() > 0.5;
+
+        for _ in 0..=self.span_count {
+            self.add_note(&mut pattern, column, start_time, start_time);
+            start_time += self.segment_duration;
+
+            if increasing {
+                if column >= total - 1 {
+                    increasing = false;
+                    column -= 1;
+                } else {
+                    column += 1;
+                }
+            } else if column <= start {
+                increasing = true;
+                column += 1;
+            } else {
+                column -= 1;
+            }
+        }
+
+        pattern
+    }
+
+    /// One or two notes per node.
+    fn generate_random_multiple_notes(&mut self, mut start_time: i32) -> Pattern {
+        let mut pattern = Pattern::default();
+        let (total, start) = (self.base.total_columns, self.base.starting_column);
+
+        let legacy = (4..=8).contains(&total);
+        let interval = self.base.random.next_range(1, total - if legacy { 1 } else { 0 });
+
+        let mut column = self.base.column_for_x(true);
+        for _ in 0..=self.span_count {
+            self.add_note(&mut pattern, column, start_time, start_time);
+
+            column += interval;
+            if column >= total - start {
+                column = column - total - start + if legacy { 1 } else { 0 };
+            }
+            column += start;
+
+            // Avoid long runs of doubles in 2K
+            if total > 2 {
+                self.add_note(&mut pattern, column, start_time, start_time);
+            }
+
+            column = self.base.random_column(None, None);
+            start_time += self.segment_duration;
+        }
+
+        pattern
+    }
+
+    /// Random hold notes over the whole slider; clap and finish sounds guarantee at least two.
+    fn generate_n_random_notes(&mut self, start_time: i32, mut p2: f64, mut p3: f64, mut p4: f64) -> Pattern {
+        match self.base.total_columns {
+            2 => (p2, p3, p4) = (0.0, 0.0, 0.0),
+            3 => (p2, p3, p4) = (p2.min(0.1), 0.0, 0.0),
+            4 => (p2, p3, p4) = (p2.min(0.3), p3.min(0.04), 0.0),
+            5 => (p2, p3, p4) = (p2.min(0.34), p3.min(0.1), p4.min(0.03)),
+            _ => {}
+        }
+
+        let is_double_sound = |sound: HitSound| sound.clap() || sound.finish();
+        let can_generate_two_notes = !self.convert_type.has(PatternType::LOW_PROBABILITY)
+            && (is_double_sound(HitSound(self.base.object.hit_sound)) || is_double_sound(self.sound_at(self.start_time)));
+        if can_generate_two_notes {
+            p2 = 1.0;
+        }
+
+        let note_count = self.base.random_note_count(&[p2, p3, p4]);
+        self.generate_random_hold_notes(start_time, note_count)
+    }
+
+    /// A stair of hold notes that all end together.
+    fn generate_tiled_hold_notes(&mut self, mut start_time: i32) -> Pattern {
+        let mut pattern = Pattern::default();
+        let previous = self.base.previous;
+
+        let column_repeat = self.span_count.min(self.base.total_columns);
+        // Integer rounding can make this differ from `end_time`
+        let end_time = start_time + self.segment_duration * self.span_count;
+
+        let mut column = self.base.column_for_x(true);
+        if self.convert_type.has(PatternType::FORCE_NOT_STACK) && previous.column_count() < self.base.total_columns {
+            column = self.base.find_available_column(column, None, None, None, &[previous]).unwrap_or(column);
+        }
+
+        for _ in 0..column_repeat {
+            let taken = pattern.clone();
+            let Some(next) = self.base.find_available_column(column, None, None, None, &[&taken]) else {
+                break;
+            };
+            column = next;
+            self.add_note(&mut pattern, column, start_time, end_time);
+            start_time += self.segment_duration;
+        }
+
+        pattern
+    }
+
+    /// A hold note over the slider with notes beside it on the nodes.
+    fn generate_hold_and_normal_notes(&mut self, mut start_time: i32) -> Pattern {
+        let mut pattern = Pattern::default();
+        let total = self.base.total_columns;
+        let previous = self.base.previous;
+
+        let mut hold_column = self.base.column_for_x(true);
+        if self.convert_type.has(PatternType::FORCE_NOT_STACK) && previous.column_count() < total {
+            hold_column = self.base.find_available_column(hold_column, None, None, None, &[previous]).unwrap_or(hold_column);
+        }
+        self.add_note(&mut pattern, hold_column, start_time, self.end_time);
+
+        let mut column = self.base.random_column(None, None);
+        let difficulty = self.base.conversion_difficulty;
+        let note_count = if difficulty > 6.5 {
+            self.base.random_note_count(&[0.63, 0.0])
+        } else if difficulty > 4.0 {
+            self.base.random_note_count(&[if total < 6 { 0.12 } else { 0.45 }, 0.0])
+        } else if difficulty > 2.5 {
+            self.base.random_note_count(&[if total < 6 { 0.0 } else { 0.24 }, 0.0])
+        } else {
+            0
+        };
+        let note_count = note_count.min(total - 1);
+
+        let head = self.sound_at(start_time);
+        let ignore_head = !(head.whistle() || head.finish() || head.clap());
+
+        let mut row = Pattern::default();
+        for _ in 0..=self.span_count {
+            if !(ignore_head && start_time == self.start_time) {
+                for _ in 0..note_count {
+                    let taken = row.clone();
+                    let Some(next) = self.base.find_available_column(column, None, None, Some(hold_column), &[&taken]) else {
+                        break;
+                    };
+                    column = next;
+                    self.add_note(&mut row, column, start_time, start_time);
+                }
+            }
+
+            pattern.append(&row);
+            row.clear();
+            start_time += self.segment_duration;
+        }
+
+        pattern
+    }
+
+    /// Hitsound of the node at or after `time`.
+    fn sound_at(&self, time: i32) -> HitSound {
+        let index = if self.segment_duration == 0 { 0 } else { (time - self.start_time) / self.segment_duration };
+        self.base.object.node_sound(index.max(0) as usize)
+    }
+
+    fn add_note(&self, pattern: &mut Pattern, column: i32, start: i32, end: i32) {
+        self.base.add_note(pattern, column, start, end);
+    }
+}
+
+/// Turns a spinner into a single note or hold note in a random column.
+struct EndTimeObjectPatternGenerator<'a> {
+    base: Generator<'a>,
+    end_time: i32,
+    convert_type: PatternType,
+}
+
+impl<'a> EndTimeObjectPatternGenerator<'a> {
+    fn new(base: Generator<'a>) -> Self {
+        let end_time = base.object.end_time.max(base.object.time);
+        let convert_type = if base.previous.column_count() == base.total_columns {
+            PatternType::NONE
+        } else {
+            PatternType::FORCE_NOT_STACK
+        };
+        EndTimeObjectPatternGenerator { base, end_time, convert_type }
+    }
+
+    fn generate(mut self) -> Pattern {
+        let mut pattern = Pattern::default();
+        let start_time = self.base.object.time;
+        let generate_hold = self.end_time - start_time >= StandardConverter::MIN_HOLD_DURATION;
+        let end_time = if generate_hold { self.end_time } else { start_time };
+
+        let column = if self.base.total_columns == 8 {
+            if HitSound(self.base.object.hit_sound).finish() && self.end_time - start_time < 1000 {
+                Some(0)
+            } else {
+                self.random_column(None)
+            }
+        } else {
+            self.random_column(Some(0))
+        };
+
+        if let Some(column) = column {
+            self.base.add_note(&mut pattern, column, start_time, end_time);
+        }
+        pattern
+    }
+
+    fn random_column(&mut self, lower: Option<i32>) -> Option<i32> {
+        let initial = self.base.random_column(lower, None);
+        let previous = self.base.previous;
+        let patterns: &[&Pattern] = if self.convert_type.has(PatternType::FORCE_NOT_STACK) { &[previous] } else { &[] };
+        self.base.find_available_column(initial, lower, None, None, patterns)
+    }
+}
+
+// Beat length osu! uses when the map has no timing points
+const DEFAULT_BEAT_LENGTH: f64 = 1000.0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn difficulty(cs: f64, od: f64) -> DifficultyInfo {
+        let mut difficulty = DifficultyInfo::default();
+        difficulty.apply("CircleSize", &cs.to_string());
+        difficulty.apply("OverallDifficulty", &od.to_string());
+        difficulty
+    }
+
+    fn circles(count: usize) -> Vec<StandardObject> {
+        (0..count).map(|i| StandardObject::parse(&format!("{},192,{},1,0", i * 50 % 512, i * 100)).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_column_count() {
+        let mut objects = circles(10);
+        assert_eq!(StandardConverter::column_count(&difficulty(4.0, 8.0), &objects), 7);
+
+        // 30% sliders with CS 4 falls through to the OD based rule
+        for object in objects.iter_mut().take(3) {
+            object.object_type = 2;
+        }
+        assert_eq!(StandardConverter::column_count(&difficulty(4.0, 5.0), &objects), 6);
+        assert_eq!(StandardConverter::column_count(&difficulty(4.0, 2.0), &objects), 4);
+
+        for object in objects.iter_mut().take(7) {
+            object.object_type = 2;
+        }
+        assert_eq!(StandardConverter::column_count(&difficulty(4.0, 4.0), &objects), 4);
+        assert_eq!(StandardConverter::column_count(&difficulty(4.0, 6.0), &objects), 5);
+    }
+
+    #[test]
+    fn test_repeat_slider_becomes_tiled_holds() {
+        let mut control_points = ControlPointInfo::default();
+        control_points.add(crate::timing::TimingPoint::parse("0,500,4,2,0,60,1,0").unwrap());
+
+        // 140px at SliderMultiplier 1.4 is one beat, twice for a repeat. Spans longer than 400 ms become
+        // hold notes that start on each node and end with the slider.
+        let objects = vec![StandardObject::parse("256,192,1000,2,0,B|300:192,2,140").unwrap()];
+        let (column_count, notes) = StandardConverter::convert(&objects, &difficulty(4.0, 8.0), &control_points, 0.0);
+
+        // Only sliders and OD 8 give 5K
+        assert_eq!(column_count, 5);
+        assert_eq!(notes.len(), 2);
+        assert!(notes.iter().all(|n| n.note_type == 128 && n.end == 2000));
+        assert_eq!((notes[0].start, notes[1].start), (1000, 1500));
+        // The first hold is under the slider head, the second in another column
+        assert_eq!(notes[0].column, 2);
+        assert_ne!(notes[1].column, 2);
+    }
+
+    #[test]
+    fn test_legacy_random_sequence() {
+        let mut random = LegacyRandom::new(0);
+        let first: Vec<u32> = (0..3).map(|_| random.next_u32()).collect();
+        let mut again = LegacyRandom::new(0);
+        assert_eq!(first, (0..3).map(|_| again.next_u32()).collect::<Vec<_>>());
+        assert!((0..100).all(|_| (0..7).contains(&random.next_range(0, 7))));
+    }
+}
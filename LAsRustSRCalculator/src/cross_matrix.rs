@@ -1,69 +1,145 @@
+use crate::error::SrError;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+/// 自定义交叉矩阵，覆盖默认数据（对应C#的 SetCustomMatrix）
+static CUSTOM_MATRICES: RwLock<BTreeMap<usize, Vec<f64>>> = RwLock::new(BTreeMap::new());
+
 pub struct CrossMatrixProvider;
 
 impl CrossMatrixProvider {
-    /// 获取指定键数(K)的交叉矩阵
+    /// 获取指定键数(K)的交叉矩阵，优先返回自定义矩阵
     /// K表示键数，从1开始索引
-    pub fn get_matrix(k: usize) -> Option<&'static [f64]> {
-        if k < 1 || k > Self::MATRICES.len() {
-            return None;
+    pub fn get_matrix(k: usize) -> Option<Vec<f64>> {
+        let custom = CUSTOM_MATRICES.read().unwrap_or_else(|e| e.into_inner()).get(&k).cloned();
+        custom.or_else(|| Self::default_matrix(k).map(|m| m.to_vec()))
+    }
+
+    /// 获取指定键数(K)的默认交叉矩阵，忽略自定义矩阵
+    pub fn default_matrix(k: usize) -> Option<&'static [f64]> {
+        Self::MATRICES.iter().find(|&&(key_count, _)| key_count == k).map(|&(_, matrix)| matrix)
+    }
+
+    /// 设置自定义交叉矩阵，matrix为None时清除
+    pub fn set_custom_matrix(k: usize, matrix: Option<Vec<f64>>) -> Result<(), SrError> {
+        let mut custom = CUSTOM_MATRICES.write().unwrap_or_else(|e| e.into_inner());
+        match matrix {
+            Some(matrix) => {
+                Self::validate(k, &matrix)?;
+                custom.insert(k, matrix);
+            }
+            None => {
+                custom.remove(&k);
+            }
         }
-        Self::MATRICES.get(k).and_then(|opt| *opt)
+        Ok(())
     }
 
-    /// 检查指定的键数是否受支持
+    /// 清除所有自定义交叉矩阵
+    pub fn clear_custom_matrices() {
+        CUSTOM_MATRICES.write().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// 检查矩阵是否可用于K键：长度为K+1，且每个权重为有限的非负数
+    pub fn validate(k: usize, matrix: &[f64]) -> Result<(), SrError> {
+        if !(1..=18).contains(&k) {
+            return Err(SrError::UnsupportedKeyCount(k as i32));
+        }
+        if matrix.len() != k + 1 {
+            return Err(SrError::InvalidConfig(format!("cross matrix for {}K needs {} entries, got {}", k, k + 1, matrix.len())));
+        }
+        if let Some(v) = matrix.iter().find(|v| !v.is_finite() || **v < 0.0) {
+            return Err(SrError::InvalidConfig(format!("cross matrix weight {} is not a finite non-negative number", v)));
+        }
+        Ok(())
+    }
+
+    /// 检查指定的键数是否受支持（有默认或自定义矩阵）
     pub fn is_supported(k: usize) -> bool {
-        k >= 1 && k <= Self::MATRICES.len() && Self::MATRICES[k - 1].is_some()
+        Self::default_matrix(k).is_some() || CUSTOM_MATRICES.read().unwrap_or_else(|e| e.into_inner()).contains_key(&k)
     }
 
-    /// 获取所有支持的键数
+    /// 获取所有有默认矩阵的键数，升序
     pub fn get_supported_keys() -> Vec<usize> {
-        (0..Self::MATRICES.len())
-            .filter(|&i| Self::MATRICES[i].is_some())
-            .map(|i| i + 1)
-            .collect()
+        Self::MATRICES.iter().map(|&(k, _)| k).collect()
     }
 
-    /// 交叉矩阵数据
-    /// 索引0对应K=0
-    /// None表示不支持该键数
-    const MATRICES: &'static [Option<&'static [f64]>] = &[
-        // K=0
-        Some(&[-1.0]),
-        // K=1
-        Some(&[0.075, 0.075]),
-        // K=2
-        Some(&[0.125, 0.05, 0.125]),
-        // K3
-        Some(&[0.125, 0.125, 0.125, 0.125]),
-        // K=4
-        Some(&[0.175, 0.25, 0.05, 0.25, 0.175]),
-        // K=5
-        Some(&[0.175, 0.25, 0.175, 0.175, 0.25, 0.175]),
-        // K=6
-        Some(&[0.225, 0.35, 0.25, 0.05, 0.25, 0.35, 0.225]),
-        // K=7
-        Some(&[0.225, 0.35, 0.25, 0.225, 0.225, 0.25, 0.35, 0.225]),
-        // K=8
-        Some(&[0.275, 0.45, 0.35, 0.25, 0.05, 0.25, 0.35, 0.45, 0.275]),
-        // K=9
-        Some(&[0.275, 0.45, 0.35, 0.25, 0.275, 0.275, 0.25, 0.35, 0.45, 0.275]),
-        // K=10
-        Some(&[0.325, 0.55, 0.45, 0.35, 0.25, 0.05, 0.25, 0.35, 0.45, 0.55, 0.325]),
-        // K=11 (odd, unsupported)
-        None,
-        // K=12 (even, sides 3 columns higher)
-        Some(&[0.8, 0.8, 0.8, 0.6, 0.4, 0.2, 0.05, 0.2, 0.4, 0.6, 0.8, 0.8, 0.8]),
-        // K=13 (odd, unsupported)
-        None,
-        // K=14 (wave: low-low-high-high-low-low-high-high)
-        Some(&[0.4, 0.4, 0.2, 0.2, 0.3, 0.3, 0.1, 0.1, 0.3, 0.3, 0.2, 0.2, 0.4, 0.4, 0.4]),
-        // K=15 (odd, unsupported)
-        None,
-        // K=16 (wave: low-low-high-high-low-low-high-high-low-low-high-high)
-        Some(&[0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.2, 0.1, 0.1, 0.2, 0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.4]),
-        // K=17 (odd, unsupported)
-        None,
-        // K=18 (wave: low-low-high-low-high-low-high-low-low-high-low-high-low-high)
-        Some(&[0.4, 0.4, 0.2, 0.4, 0.2, 0.4, 0.2, 0.3, 0.1, 0.1, 0.3, 0.2, 0.4, 0.2, 0.4, 0.2, 0.4, 0.4, 0.4]),
+    /// 默认交叉矩阵数据：(键数K, 长度为K+1的矩阵)，按K升序
+    const MATRICES: &'static [(usize, &'static [f64])] = &[
+        // 1K
+        (1, &[0.075, 0.075]),
+        // 2K
+        (2, &[0.125, 0.05, 0.125]),
+        // 3K
+        (3, &[0.125, 0.125, 0.125, 0.125]),
+        // 4K
+        (4, &[0.175, 0.25, 0.05, 0.25, 0.175]),
+        // 5K
+        (5, &[0.175, 0.25, 0.175, 0.175, 0.25, 0.175]),
+        // 6K
+        (6, &[0.225, 0.35, 0.25, 0.05, 0.25, 0.35, 0.225]),
+        // 7K
+        (7, &[0.225, 0.35, 0.25, 0.225, 0.225, 0.25, 0.35, 0.225]),
+        // 8K
+        (8, &[0.275, 0.45, 0.35, 0.25, 0.05, 0.25, 0.35, 0.45, 0.275]),
+        // 9K
+        (9, &[0.275, 0.45, 0.35, 0.25, 0.275, 0.275, 0.25, 0.35, 0.45, 0.275]),
+        // 10K
+        (10, &[0.325, 0.55, 0.45, 0.35, 0.25, 0.05, 0.25, 0.35, 0.45, 0.55, 0.325]),
+        // 11K (由10K推导: 中间0.05拆成两个边缘权重，与5K/7K/9K相同)
+        (11, &[0.325, 0.55, 0.45, 0.35, 0.25, 0.325, 0.325, 0.25, 0.35, 0.45, 0.55, 0.325]),
+        // 12K (even, sides 3 columns higher)
+        (12, &[0.8, 0.8, 0.8, 0.6, 0.4, 0.2, 0.05, 0.2, 0.4, 0.6, 0.8, 0.8, 0.8]),
+        // 13K (由12K推导)
+        (13, &[0.8, 0.8, 0.8, 0.6, 0.4, 0.2, 0.8, 0.8, 0.2, 0.4, 0.6, 0.8, 0.8, 0.8]),
+        // 14K (wave: low-low-high-high-low-low-high-high)
+        (14, &[0.4, 0.4, 0.2, 0.2, 0.3, 0.3, 0.1, 0.1, 0.3, 0.3, 0.2, 0.2, 0.4, 0.4, 0.4]),
+        // 15K (由14K推导)
+        (15, &[0.4, 0.4, 0.2, 0.2, 0.3, 0.3, 0.1, 0.4, 0.4, 0.3, 0.3, 0.2, 0.2, 0.4, 0.4, 0.4]),
+        // 16K (wave: low-low-high-high-low-low-high-high-low-low-high-high)
+        (16, &[0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.2, 0.1, 0.1, 0.2, 0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.4]),
+        // 17K (由16K推导)
+        (17, &[0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.2, 0.1, 0.4, 0.4, 0.2, 0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.4]),
+        // 18K (wave: low-low-high-low-high-low-high-low-low-high-low-high-low-high)
+        (18, &[0.4, 0.4, 0.2, 0.4, 0.2, 0.4, 0.2, 0.3, 0.1, 0.1, 0.3, 0.2, 0.4, 0.2, 0.4, 0.2, 0.4, 0.4, 0.4]),
     ];
 }
+
+// 编译期检查每个默认矩阵都有K+1个元素
+const _: () = {
+    let mut i = 0;
+    while i < CrossMatrixProvider::MATRICES.len() {
+        let (k, matrix) = CrossMatrixProvider::MATRICES[i];
+        assert!(matrix.len() == k + 1);
+        i += 1;
+    }
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_key_count_has_a_matrix() {
+        for k in 1..=18 {
+            let matrix = CrossMatrixProvider::default_matrix(k).unwrap_or_else(|| panic!("no matrix for {}K", k));
+            assert_eq!(matrix.len(), k + 1, "{}K", k);
+            assert!(CrossMatrixProvider::validate(k, matrix).is_ok(), "{}K", k);
+            assert!(CrossMatrixProvider::is_supported(k), "{}K", k);
+            assert!(CrossMatrixProvider::get_matrix(k).is_some(), "{}K", k);
+        }
+        assert_eq!(CrossMatrixProvider::get_supported_keys(), (1..=18).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_unsupported_key_counts() {
+        for k in [0, 19, 20] {
+            assert!(CrossMatrixProvider::default_matrix(k).is_none());
+            assert!(CrossMatrixProvider::get_matrix(k).is_none());
+            assert!(!CrossMatrixProvider::is_supported(k));
+            assert!(matches!(CrossMatrixProvider::validate(k, &vec![0.1; k + 1]), Err(SrError::UnsupportedKeyCount(_))));
+        }
+        assert!(matches!(CrossMatrixProvider::validate(4, &[0.1; 4]), Err(SrError::InvalidConfig(_))));
+        assert!(matches!(CrossMatrixProvider::validate(4, &[0.1, 0.1, f64::NAN, 0.1, 0.1]), Err(SrError::InvalidConfig(_))));
+    }
+}
@@ -0,0 +1,115 @@
+use std::cell::RefCell;
+use std::fmt;
+
+/// Errors produced while parsing a beatmap or calculating its SR.
+///
+/// Each variant has a stable negative code (see [`SrError::code`]) that the C ABI
+/// returns in place of an SR value.
+#[derive(Debug)]
+pub enum SrError {
+    /// The path passed over FFI was null or not valid UTF-8.
+    InvalidPath(String),
+    Io(std::io::Error),
+    /// A line of the .osu file could not be read. `line` is 1-based.
+    Parse { line: usize, reason: String },
+    /// The beatmap is neither mania nor a convertible osu!standard map.
+    UnsupportedMode(i32),
+    UnsupportedKeyCount(i32),
+    InvalidOd(f64),
+    /// The beatmap has no notes. Before the typed errors the C ABI returned 0.0 here.
+    EmptyMap,
+    /// A calculation option is out of range, e.g. a non-positive rate.
+    InvalidConfig(String),
+    /// The map spans more played time than the algorithm can hold in memory, see `MAX_V30_SPAN_MS`.
+    MapTooLong { span_ms: i64, limit_ms: i64 },
+    /// `sr_set_log_callback` found another `log` logger installed by the host, which keeps the records.
+    LoggerAlreadyInstalled,
+    /// The calculation panicked; only reported through the FFI.
+    InternalPanic,
+}
+
+impl SrError {
+    /// Stable numeric code of this error, as returned by the `extern "C"` functions.
+    pub fn code(&self) -> i32 {
+        match self {
+            SrError::InvalidPath(_) => -2,
+            SrError::Io(_) => -3,
+            SrError::Parse { .. } => -4,
+            SrError::InvalidOd(_) => -5,
+            SrError::UnsupportedKeyCount(_) => -6,
+            SrError::InternalPanic => -7,
+            SrError::EmptyMap => -8,
+            SrError::UnsupportedMode(_) => -9,
+            SrError::InvalidConfig(_) => -10,
+            SrError::MapTooLong { .. } => -11,
+            SrError::LoggerAlreadyInstalled => -12,
+        }
+    }
+
+    /// Name of the variant, as logged in the `kind` field.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SrError::InvalidPath(_) => "InvalidPath",
+            SrError::Io(_) => "Io",
+            SrError::Parse { .. } => "Parse",
+            SrError::InvalidOd(_) => "InvalidOd",
+            SrError::UnsupportedKeyCount(_) => "UnsupportedKeyCount",
+            SrError::InternalPanic => "InternalPanic",
+            SrError::EmptyMap => "EmptyMap",
+            SrError::UnsupportedMode(_) => "UnsupportedMode",
+            SrError::InvalidConfig(_) => "InvalidConfig",
+            SrError::MapTooLong { .. } => "MapTooLong",
+            SrError::LoggerAlreadyInstalled => "LoggerAlreadyInstalled",
+        }
+    }
+}
+
+impl fmt::Display for SrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SrError::InvalidPath(reason) => write!(f, "Invalid path: {}", reason),
+            SrError::Io(e) => write!(f, "I/O error: {}", e),
+            SrError::Parse { line, reason } => write!(f, "Parse error at line {}: {}", line, reason),
+            SrError::UnsupportedMode(mode) => write!(f, "Not a mania beatmap (Mode: {})", mode),
+            SrError::UnsupportedKeyCount(k) => write!(f, "Unsupported key count: {}K", k),
+            SrError::InvalidOd(od) => write!(f, "Invalid overall difficulty: {}", od),
+            SrError::EmptyMap => write!(f, "Beatmap has no notes"),
+            SrError::InvalidConfig(reason) => write!(f, "Invalid calculation config: {}", reason),
+            SrError::MapTooLong { span_ms, limit_ms } => write!(f, "Map spans {} ms, more than the {} ms limit", span_ms, limit_ms),
+            SrError::LoggerAlreadyInstalled => write!(f, "Another logger is already installed"),
+            SrError::InternalPanic => write!(f, "SR calculation panicked"),
+        }
+    }
+}
+
+impl std::error::Error for SrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SrError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SrError {
+    fn from(e: std::io::Error) -> Self {
+        SrError::Io(e)
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<(i32, String)>> = const { RefCell::new(None) };
+}
+
+/// Remembers `error` as the last error of the current thread, for `sr_last_error_message`.
+pub(crate) fn set_last_error(error: &SrError) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some((error.code(), error.to_string())));
+}
+
+pub(crate) fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+pub(crate) fn last_error() -> Option<(i32, String)> {
+    LAST_ERROR.with(|last| last.borrow().clone())
+}
@@ -0,0 +1,209 @@
+/// The object kind encoded in the low bits of a hit object's `type` field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum HitObjectKind {
+    Circle,
+    Slider,
+    Spinner,
+    Hold,
+}
+
+/// Decoded `type` bitfield of a hit object.
+///
+/// | bit | value | meaning |
+/// |-----|-------|---------|
+/// | 0 | 1 | circle (mania: single note) |
+/// | 1 | 2 | slider |
+/// | 2 | 4 | new combo |
+/// | 3 | 8 | spinner |
+/// | 4-6 | 16-64 | combo colour skip |
+/// | 7 | 128 | mania hold note |
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct HitObjectType {
+    pub kind: HitObjectKind,
+    pub new_combo: bool,
+    // Number of combo colours to skip (0-7)
+    pub combo_offset: u8,
+}
+
+impl HitObjectType {
+    pub const CIRCLE: i32 = 1;
+    pub const SLIDER: i32 = 2;
+    pub const NEW_COMBO: i32 = 4;
+    pub const SPINNER: i32 = 8;
+    pub const COMBO_OFFSET: i32 = 16 | 32 | 64;
+    pub const HOLD: i32 = 128;
+    pub const KNOWN_BITS: i32 = 0xFF;
+
+    /// Decodes a raw `type` value. Kind bits are checked in the same order as osu!
+    /// (circle, slider, spinner, hold); a value without any kind bit decodes as a circle.
+    pub fn from_bits(bits: i32) -> Self {
+        let kind = if bits & Self::CIRCLE != 0 {
+            HitObjectKind::Circle
+        } else if bits & Self::SLIDER != 0 {
+            HitObjectKind::Slider
+        } else if bits & Self::SPINNER != 0 {
+            HitObjectKind::Spinner
+        } else if bits & Self::HOLD != 0 {
+            HitObjectKind::Hold
+        } else {
+            HitObjectKind::Circle
+        };
+
+        HitObjectType {
+            kind,
+            new_combo: bits & Self::NEW_COMBO != 0,
+            combo_offset: ((bits & Self::COMBO_OFFSET) >> 4) as u8,
+        }
+    }
+
+    /// Whether `bits` contains a kind bit and nothing outside the documented bits.
+    pub fn is_valid_bits(bits: i32) -> bool {
+        bits & !Self::KNOWN_BITS == 0 && bits & (Self::CIRCLE | Self::SLIDER | Self::SPINNER | Self::HOLD) != 0
+    }
+
+    pub fn bits(&self) -> i32 {
+        let kind = match self.kind {
+            HitObjectKind::Circle => Self::CIRCLE,
+            HitObjectKind::Slider => Self::SLIDER,
+            HitObjectKind::Spinner => Self::SPINNER,
+            HitObjectKind::Hold => Self::HOLD,
+        };
+        let new_combo = if self.new_combo { Self::NEW_COMBO } else { 0 };
+        kind | new_combo | ((self.combo_offset as i32) << 4)
+    }
+
+    pub fn is_hold(&self) -> bool {
+        self.kind == HitObjectKind::Hold
+    }
+}
+
+/// Hitsound flags of a hit object (the `hitSound` field).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct HitSound(pub i32);
+
+impl HitSound {
+    pub const NORMAL: i32 = 1;
+    pub const WHISTLE: i32 = 2;
+    pub const FINISH: i32 = 4;
+    pub const CLAP: i32 = 8;
+
+    pub fn normal(&self) -> bool {
+        self.0 & Self::NORMAL != 0
+    }
+
+    pub fn whistle(&self) -> bool {
+        self.0 & Self::WHISTLE != 0
+    }
+
+    pub fn finish(&self) -> bool {
+        self.0 & Self::FINISH != 0
+    }
+
+    pub fn clap(&self) -> bool {
+        self.0 & Self::CLAP != 0
+    }
+}
+
+/// The `hitSample` field (`normalSet:additionSet:index:volume:filename`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct HitSample {
+    // 0 = inherit from the timing point, 1 = normal, 2 = soft, 3 = drum
+    pub normal_set: i32,
+    pub addition_set: i32,
+    // 0 = inherit from the timing point
+    pub index: i32,
+    // 0 = inherit from the timing point
+    pub volume: i32,
+    // Custom keysound file, empty when the default samples are used
+    pub filename: String,
+}
+
+impl HitSample {
+    /// Parses the colon separated sample fields. Missing or invalid fields keep their defaults.
+    pub fn parse(fields: &[&str]) -> HitSample {
+        let field = |idx: usize| -> i32 { fields.get(idx).and_then(|f| f.trim().parse().ok()).unwrap_or(0) };
+        HitSample {
+            normal_set: field(0),
+            addition_set: field(1),
+            index: field(2),
+            volume: field(3),
+            filename: fields.get(4).map(|f| f.trim().to_string()).unwrap_or_default(),
+        }
+    }
+
+    pub fn has_keysound(&self) -> bool {
+        !self.filename.is_empty()
+    }
+}
+
+/// A mania note as read from `[HitObjects]`, including everything needed to write it back.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct HitObject {
+    pub x: f64,
+    pub y: f64,
+    pub column: i32,
+    pub start_time: i32,
+    // Equal to `start_time` for everything but hold notes
+    pub end_time: i32,
+    pub object_type: HitObjectType,
+    pub hit_sound: HitSound,
+    pub sample: HitSample,
+    // 1-based line in the .osu file
+    pub line: usize,
+    // The line as it appears in the file
+    pub raw: String,
+}
+
+impl HitObject {
+    pub fn is_hold(&self) -> bool {
+        self.object_type.is_hold()
+    }
+
+    pub fn duration(&self) -> i32 {
+        self.end_time - self.start_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_type_bits() {
+        assert_eq!(HitObjectType::from_bits(1).kind, HitObjectKind::Circle);
+        assert_eq!(HitObjectType::from_bits(5).kind, HitObjectKind::Circle);
+        assert!(HitObjectType::from_bits(5).new_combo);
+        assert_eq!(HitObjectType::from_bits(6).kind, HitObjectKind::Slider);
+        assert_eq!(HitObjectType::from_bits(12).kind, HitObjectKind::Spinner);
+
+        for bits in [128, 132, 148, 244] {
+            let decoded = HitObjectType::from_bits(bits);
+            assert!(decoded.is_hold(), "type {} should be a hold note", bits);
+            assert_eq!(decoded.bits(), bits);
+        }
+        let decoded = HitObjectType::from_bits(132 | 64);
+        assert!(decoded.new_combo);
+        assert_eq!(decoded.combo_offset, 4);
+
+        assert!(HitObjectType::is_valid_bits(132));
+        assert!(!HitObjectType::is_valid_bits(4));
+        assert!(!HitObjectType::is_valid_bits(1024 | 1));
+    }
+
+    #[test]
+    fn test_parse_hit_sample() {
+        let sample = HitSample::parse(&"2:3:1:70:kick.wav".split(':').collect::<Vec<_>>());
+        assert_eq!(sample.normal_set, 2);
+        assert_eq!(sample.addition_set, 3);
+        assert_eq!(sample.index, 1);
+        assert_eq!(sample.volume, 70);
+        assert_eq!(sample.filename, "kick.wav");
+        assert!(sample.has_keysound());
+
+        assert_eq!(HitSample::parse(&["0", "0", "0", "0", ""]), HitSample::default());
+        assert_eq!(HitSample::parse(&[]), HitSample::default());
+
+        let hit_sound = HitSound(HitSound::WHISTLE | HitSound::CLAP);
+        assert!(hit_sound.whistle() && hit_sound.clap() && !hit_sound.finish() && !hit_sound.normal());
+    }
+}
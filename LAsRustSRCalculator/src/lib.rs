@@ -1,137 +1,548 @@
 pub mod sr;
+mod sr_v30;
 pub mod note;
 pub mod cross_matrix;
 pub mod parser;
 pub mod math;
+pub mod hit_object;
+pub mod error;
+pub mod convert;
+pub mod metadata;
+pub mod timing;
+pub mod beatmap;
+pub mod attributes;
+pub mod timeline;
+pub mod config;
+pub mod mods;
+pub mod performance;
+pub mod params;
+mod logging;
+#[doc(hidden)]
+pub mod synthetic;
 
-#[cfg(debug_assertions)]
-#[macro_export]
-macro_rules! debug_log {
-    ($($arg:tt)*) => (println!($($arg)*));
-}
-
-#[cfg(not(debug_assertions))]
-#[macro_export]
-macro_rules! debug_log {
-    ($($arg:tt)*) => {};
-}
-
+use crate::attributes::{DifficultyAttributes, SrDifficultyAttributes};
+use crate::error::SrError;
 use crate::parser::OsuParser;
+use crate::performance::{Judgements, PerformanceAttributes, PerformanceCalculator, PpFormula, ScoreInfo};
+use crate::beatmap::ManiaBeatmap;
+use crate::config::{HitWindowModel, SrAlgorithm, SrConfig};
+use crate::cross_matrix::CrossMatrixProvider;
 use crate::sr::SRCalculator;
+use crate::timeline::StrainTimeline;
+use rayon::prelude::*;
+use std::ffi::CString;
 use std::os::raw::c_char;
+use std::sync::{Arc, Mutex};
 
 pub struct SRAPI;
 
 impl SRAPI {
-    pub fn calculate_sr(file_path: &str) -> Result<f64, String> {
+    pub fn calculate_sr(file_path: &str) -> Result<f64, SrError> {
         let mut parser = OsuParser::new(file_path);
-        parser.process().map_err(|e| e.to_string())?;
+        parser.process()?;
         let data = parser.get_parsed_data();
-        // debug_log!("Parsed data: k={}, od={}, notes={}", data.column_count, data.od, data.columns.len());
         SRCalculator::calculate_sr_from_parsed_data(&data)
     }
+
+    /// SR of the .osu file at `file_path` with rate and other options applied.
+    pub fn calculate_sr_with_config(file_path: &str, config: &SrConfig) -> Result<f64, SrError> {
+        let mut parser = OsuParser::new(file_path);
+        parser.process()?;
+        let beatmap = ManiaBeatmap::from(&parser.get_parsed_data());
+        SRCalculator::calculate_sr_with_config(&beatmap.notes, beatmap.key_count, beatmap.od, config)
+    }
+
+    /// `(rate, SR)` for each rate, parsing the file only once.
+    pub fn calculate_rate_table(file_path: &str, rates: &[f64]) -> Result<Vec<(f64, f64)>, SrError> {
+        let mut parser = OsuParser::new(file_path);
+        parser.process()?;
+        SRCalculator::calculate_rate_table(&ManiaBeatmap::from(&parser.get_parsed_data()), rates)
+    }
+
+    /// pp of `score` on the .osu file at `file_path`. SR is calculated with the score's mods.
+    pub fn calculate_pp(file_path: &str, score: &ScoreInfo, formula: &PpFormula) -> Result<PerformanceAttributes, SrError> {
+        let mut parser = OsuParser::new(file_path);
+        parser.process()?;
+        let beatmap = ManiaBeatmap::from(&parser.get_parsed_data());
+        let config = SrConfig::from_legacy_mods(score.mods);
+        let attributes = SRCalculator::calculate_attributes_with_config(&beatmap.notes, beatmap.key_count, beatmap.od, &config)?;
+        Ok(PerformanceCalculator::calculate(&attributes, score, formula))
+    }
+
+    /// Calculates SR from .osu content held in memory, without touching the filesystem.
+    pub fn calculate_sr_from_str(content: &str) -> Result<f64, SrError> {
+        let data = OsuParser::parse_str(content)?;
+        SRCalculator::calculate_sr_from_parsed_data(&data)
+    }
+
+    fn calculate_sr_from_bytes(content: &[u8]) -> Result<f64, SrError> {
+        let data = OsuParser::parse_bytes(content)?;
+        SRCalculator::calculate_sr_from_parsed_data(&data)
+    }
+
+    /// SR of the .osu file at `file_path` with the per-skill breakdown.
+    pub fn calculate_attributes(file_path: &str) -> Result<DifficultyAttributes, SrError> {
+        let mut parser = OsuParser::new(file_path);
+        parser.process()?;
+        SRCalculator::calculate_attributes_from_parsed_data(&parser.get_parsed_data())
+    }
+
+    pub fn calculate_attributes_from_str(content: &str) -> Result<DifficultyAttributes, SrError> {
+        let data = OsuParser::parse_str(content)?;
+        SRCalculator::calculate_attributes_from_parsed_data(&data)
+    }
+
+    fn calculate_attributes_from_bytes(content: &[u8]) -> Result<DifficultyAttributes, SrError> {
+        let data = OsuParser::parse_bytes(content)?;
+        SRCalculator::calculate_attributes_from_parsed_data(&data)
+    }
+
+    /// Difficulty over time of the .osu file at `file_path`, see [`SRCalculator::calculate_strain_timeline`].
+    pub fn calculate_strain_timeline(file_path: &str, interval: f64, include_skills: bool) -> Result<StrainTimeline, SrError> {
+        Self::calculate_strain_timeline_with_config(file_path, interval, include_skills, &SrConfig::default())
+    }
+
+    /// Strain timeline of the .osu file at `file_path` with rate, mods and algorithm applied.
+    pub fn calculate_strain_timeline_with_config(
+        file_path: &str,
+        interval: f64,
+        include_skills: bool,
+        config: &SrConfig,
+    ) -> Result<StrainTimeline, SrError> {
+        let mut parser = OsuParser::new(file_path);
+        parser.process()?;
+        let beatmap = ManiaBeatmap::from(&parser.get_parsed_data());
+        SRCalculator::calculate_strain_timeline_with_config(&beatmap.notes, beatmap.key_count, beatmap.od, interval, include_skills, config)
+    }
+
+    pub fn calculate_strain_timeline_from_str(content: &str, interval: f64, include_skills: bool) -> Result<StrainTimeline, SrError> {
+        let beatmap = ManiaBeatmap::from(&OsuParser::parse_str(content)?);
+        SRCalculator::calculate_strain_timeline_for_beatmap(&beatmap, interval, include_skills)
+    }
+
+    /// SR of each file in `paths`, in the same order, calculated in parallel on `threads` threads
+    /// (0 uses the global pool, one thread per core). A failing file does not affect the others.
+    /// The pool for a thread count is created on first use and kept, so repeated calls are cheap.
+    pub fn calculate_sr_batch<P: AsRef<str> + Sync>(paths: &[P], threads: usize) -> Vec<Result<f64, SrError>> {
+        Self::calculate_sr_batch_with_config(paths, &SrConfig::default(), threads)
+    }
+
+    pub fn calculate_sr_batch_with_config<P: AsRef<str> + Sync>(paths: &[P], config: &SrConfig, threads: usize) -> Vec<Result<f64, SrError>> {
+        run_batch(paths, threads, |path| Self::calculate_sr_with_config(path.as_ref(), config))
+    }
 }
 
-#[allow(unsafe_code)]
+/// Returns the SR of the .osu file at the given UTF-8 path, or a negative `SrError` code.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
 #[unsafe(no_mangle)]
 pub extern "C" fn calculate_sr_from_osu_file(path_ptr: *const c_char, len: usize) -> f64 {
-    // Convert the C string to Rust string
-    let path_bytes = unsafe { std::slice::from_raw_parts(path_ptr as *const u8, len) };
-    let path_str = match std::str::from_utf8(path_bytes) {
+    let path_str = match unsafe { str_from_raw(path_ptr, len) } {
+        Ok(s) => s,
+        Err(e) => return ffi_sr_result(CallSource::Other("invalid_path"), || Err(e)),
+    };
+    ffi_sr_result(CallSource::Path(path_str), || SRAPI::calculate_sr(path_str))
+}
+
+/// Same as `calculate_sr_from_osu_file`, played at `rate` (1.5 for DT/NC, 0.75 for HT).
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_sr_from_osu_file_with_rate(path_ptr: *const c_char, len: usize, rate: f64) -> f64 {
+    let path_str = match unsafe { str_from_raw(path_ptr, len) } {
+        Ok(s) => s,
+        Err(e) => return ffi_sr_result(CallSource::Other("invalid_path"), || Err(e)),
+    };
+    ffi_sr_result(CallSource::Path(path_str), || SRAPI::calculate_sr_with_config(path_str, &SrConfig { rate, ..Default::default() }))
+}
+
+/// Same as `calculate_sr_from_osu_file` with osu! mod bits (EZ, HR, DT, NC, HT; see `LegacyMods`).
+/// `hit_window_model` is 0 for osu!stable, 1 for osu!lazer and 2 for a custom great window of
+/// `great_window` ms; `great_window` is ignored otherwise.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_sr_from_osu_file_with_mods(
+    path_ptr: *const c_char,
+    len: usize,
+    mods: u32,
+    hit_window_model: i32,
+    great_window: f64,
+) -> f64 {
+    let path_str = match unsafe { str_from_raw(path_ptr, len) } {
+        Ok(s) => s,
+        Err(e) => return ffi_sr_result(CallSource::Other("invalid_path"), || Err(e)),
+    };
+    ffi_sr_result(CallSource::Path(path_str), || {
+        let hit_window = match hit_window_model {
+            0 => HitWindowModel::Stable,
+            1 => HitWindowModel::Lazer,
+            2 => HitWindowModel::Custom { great: great_window },
+            other => return Err(SrError::InvalidConfig(format!("unknown hit window model {}", other))),
+        };
+        let config = SrConfig { hit_window, ..SrConfig::from_legacy_mods(mods) };
+        SRAPI::calculate_sr_with_config(path_str, &config)
+    })
+}
+
+/// Same as `calculate_sr_from_osu_file_with_mods` with the given algorithm version (see `SrAlgorithm::id`:
+/// 0 Rebirth, 1 SR-V3.0) and the stable hit window. An unknown version gives -10.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_sr_from_osu_file_with_algorithm(path_ptr: *const c_char, len: usize, algorithm: i32, mods: u32) -> f64 {
+    let path_str = match unsafe { str_from_raw(path_ptr, len) } {
         Ok(s) => s,
-        Err(e) => {
-            eprintln!("[SR][ERROR] 路径字符串无效: {:?}, 错误: {}", path_bytes, e);
-            return -2.0;
+        Err(e) => return ffi_sr_result(CallSource::Other("invalid_path"), || Err(e)),
+    };
+    ffi_sr_result(CallSource::Path(path_str), || {
+        let algorithm = SrAlgorithm::from_id(algorithm).ok_or_else(|| SrError::InvalidConfig(format!("unknown SR algorithm {}", algorithm)))?;
+        let config = SrConfig { algorithm, ..SrConfig::from_legacy_mods(mods) };
+        SRAPI::calculate_sr_with_config(path_str, &config)
+    })
+}
+
+/// Same as `calculate_sr_from_osu_file`, but one map's skill stages and columns run on rayon threads.
+/// Gives the same SR; it can only be faster when cores are idle.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_sr_from_osu_file_parallel(path_ptr: *const c_char, len: usize) -> f64 {
+    let path_str = match unsafe { str_from_raw(path_ptr, len) } {
+        Ok(s) => s,
+        Err(e) => return ffi_sr_result(CallSource::Other("invalid_path"), || Err(e)),
+    };
+    ffi_sr_result(CallSource::Path(path_str), || SRAPI::calculate_sr_with_config(path_str, &SrConfig { parallel: true, ..Default::default() }))
+}
+
+/// SR of `count` .osu files in parallel. File `i` has the UTF-8 path `paths[i]` of `lens[i]` bytes, and
+/// `out[i]` receives its SR or negative `SrError` code. `threads` 0 uses one thread per core, and larger
+/// counts are capped at the core count.
+/// Returns 0, or -2 without writing anything if an array pointer is null.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_sr_batch_from_osu_files(
+    paths: *const *const c_char,
+    lens: *const usize,
+    count: usize,
+    threads: usize,
+    out: *mut f64,
+) -> i32 {
+    let result = ffi_call(CallSource::Other("batch"), || {
+        if count == 0 {
+            return Ok(());
+        }
+        if paths.is_null() || lens.is_null() || out.is_null() {
+            return Err(SrError::InvalidPath("null batch array pointer".to_string()));
         }
+        let (paths, lens, out) = unsafe {
+            (std::slice::from_raw_parts(paths, count), std::slice::from_raw_parts(lens, count), std::slice::from_raw_parts_mut(out, count))
+        };
+        let files: Vec<Option<&str>> = paths.iter().zip(lens).map(|(&path, &len)| unsafe { str_from_raw(path, len) }.ok()).collect();
+        let results = run_batch(&files, threads, |file| match file {
+            Some(path) => SRAPI::calculate_sr(path),
+            None => Err(SrError::InvalidPath("null or non-UTF-8 path".to_string())),
+        });
+        for (slot, result) in out.iter_mut().zip(results) {
+            *slot = result.unwrap_or_else(|e| e.code() as f64);
+        }
+        Ok(())
+    });
+    result.err().unwrap_or(0)
+}
+
+/// osu! mania pp of a score with the given judgements and mod bits on the .osu file at the given
+/// path, or a negative `SrError` code. A null `judgements` counts as an SS with every note a perfect.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_pp_from_osu_file(path_ptr: *const c_char, len: usize, mods: u32, judgements: *const Judgements) -> f64 {
+    let path_str = match unsafe { str_from_raw(path_ptr, len) } {
+        Ok(s) => s,
+        Err(e) => return ffi_sr_result(CallSource::Other("invalid_path"), || Err(e)),
+    };
+    let score = match unsafe { judgements.as_ref() } {
+        Some(judgements) => ScoreInfo::from_judgements(judgements, mods),
+        None => ScoreInfo { accuracy: 1.0, total_hits: 0, mods },
+    };
+    ffi_sr_result(CallSource::Path(path_str), || SRAPI::calculate_pp(path_str, &score, &PpFormula::Mania).map(|p| p.pp))
+}
+
+/// Same as `calculate_sr_from_osu_file`, but takes the UTF-8 content of an .osu file
+/// instead of its path. Returns the same negative error codes.
+#[allow(unsafe_code)]
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_sr_from_osu_content(content_ptr: *const c_char, len: usize) -> f64 {
+    if content_ptr.is_null() {
+        return ffi_sr_result(CallSource::Other("content"), || Err(SrError::InvalidPath("null content pointer".to_string())));
+    }
+    let content_bytes = unsafe { std::slice::from_raw_parts(content_ptr as *const u8, len) };
+    ffi_sr_result(CallSource::Other("content"), || SRAPI::calculate_sr_from_bytes(content_bytes))
+}
+
+/// Like `calculate_sr_from_osu_file`, and on success also fills `out` with the per-skill
+/// breakdown. `out` is left untouched when an error code is returned.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_attributes_from_osu_file(path_ptr: *const c_char, len: usize, out: *mut SrDifficultyAttributes) -> f64 {
+    let path_str = match unsafe { str_from_raw(path_ptr, len) } {
+        Ok(s) => s,
+        Err(e) => return ffi_sr_result(CallSource::Other("invalid_path"), || Err(e)),
+    };
+    ffi_sr_result(CallSource::Path(path_str), || {
+        let attributes = SRAPI::calculate_attributes(path_str)?;
+        unsafe { write_attributes(out, &attributes) };
+        Ok(attributes.star_rating)
+    })
+}
+
+/// Content counterpart of `calculate_attributes_from_osu_file`.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_attributes_from_osu_content(content_ptr: *const c_char, len: usize, out: *mut SrDifficultyAttributes) -> f64 {
+    if content_ptr.is_null() {
+        return ffi_sr_result(CallSource::Other("content"), || Err(SrError::InvalidPath("null content pointer".to_string())));
+    }
+    let content_bytes = unsafe { std::slice::from_raw_parts(content_ptr as *const u8, len) };
+    ffi_sr_result(CallSource::Other("content"), || {
+        let attributes = SRAPI::calculate_attributes_from_bytes(content_bytes)?;
+        unsafe { write_attributes(out, &attributes) };
+        Ok(attributes.star_rating)
+    })
+}
+
+/// Returns the strain timeline of the .osu file at the given path as a NUL-terminated JSON
+/// string, or null on failure (see `sr_last_error_code`). The string must be released
+/// with `sr_free_string`.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_strain_timeline_from_osu_file(
+    path_ptr: *const c_char,
+    len: usize,
+    interval: f64,
+    include_skills: bool,
+) -> *mut c_char {
+    let result = match unsafe { str_from_raw(path_ptr, len) } {
+        Ok(path_str) => ffi_call(CallSource::Path(path_str), || SRAPI::calculate_strain_timeline(path_str, interval, include_skills)),
+        Err(e) => ffi_call(CallSource::Other("invalid_path"), || Err(e)),
     };
+    timeline_into_raw(result)
+}
 
-    debug_log!("Rust: Received path: {}", path_str);
+/// Same as `calculate_strain_timeline_from_osu_file` with legacy mod bits and an algorithm id
+/// (0 = Rebirth, 1 = SR-V3.0) applied, so the graph matches `calculate_sr_from_osu_file_with_algorithm`.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_strain_timeline_from_osu_file_with_mods(
+    path_ptr: *const c_char,
+    len: usize,
+    interval: f64,
+    include_skills: bool,
+    mods: u32,
+    algorithm: i32,
+) -> *mut c_char {
+    let result = match unsafe { str_from_raw(path_ptr, len) } {
+        Ok(path_str) => ffi_call(CallSource::Path(path_str), || {
+            let algorithm = SrAlgorithm::from_id(algorithm).ok_or_else(|| SrError::InvalidConfig(format!("unknown SR algorithm {}", algorithm)))?;
+            let config = SrConfig { algorithm, ..SrConfig::from_legacy_mods(mods) };
+            SRAPI::calculate_strain_timeline_with_config(path_str, interval, include_skills, &config)
+        }),
+        Err(e) => ffi_call(CallSource::Other("invalid_path"), || Err(e)),
+    };
+    timeline_into_raw(result)
+}
 
-    let file = match std::fs::File::open(path_str) {
-        Ok(f) => f,
-        Err(e) => {
-            eprintln!("[SR][ERROR] 文件打开失败: {}, 错误: {}", path_str, e);
-            return -3.0;
+fn timeline_into_raw(result: Result<StrainTimeline, i32>) -> *mut c_char {
+    match result.map(|timeline| CString::new(timeline.to_json())) {
+        Ok(Ok(json)) => json.into_raw(),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// Frees a string returned by this library. Null is ignored.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn sr_free_string(ptr: *mut c_char) {
+    if !ptr.is_null() {
+        drop(unsafe { CString::from_raw(ptr) });
+    }
+}
+
+/// Sets the cross matrix used for `k` keys by every later calculation in this process, like
+/// `CrossMatrixProvider.SetCustomMatrix` in C#. `len` must be `k + 1`; a null `matrix` restores
+/// the default. Returns 0 on success or a negative `SrError` code.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn set_custom_cross_matrix(k: i32, matrix: *const f64, len: usize) -> i32 {
+    let matrix = if matrix.is_null() { None } else { Some(unsafe { std::slice::from_raw_parts(matrix, len) }.to_vec()) };
+    match ffi_call(CallSource::Other("cross_matrix"), || CrossMatrixProvider::set_custom_matrix(k.max(0) as usize, matrix)) {
+        Ok(()) => 0,
+        Err(code) => code,
+    }
+}
+
+/// Sends the library's log records up to `max_level` (0 off, 1 error up to 5 trace) to `callback`,
+/// see `logging::LogCallback`; a null callback silences the library, which is the default.
+/// Returns 0, -10 for an unknown level, or -12 when the host process already installed a Rust logger.
+#[unsafe(no_mangle)]
+pub extern "C" fn sr_set_log_callback(callback: Option<logging::LogCallback>, max_level: i32) -> i32 {
+    let result = ffi_call(CallSource::Other("log_callback"), || {
+        let level = logging::level_filter(max_level).ok_or_else(|| SrError::InvalidConfig(format!("unknown log level {}", max_level)))?;
+        if logging::set_callback(callback, level) {
+            Ok(())
+        } else {
+            Err(SrError::LoggerAlreadyInstalled)
         }
+    });
+    match result {
+        Ok(()) => 0,
+        Err(code) => code,
+    }
+}
+
+/// Numeric code of the last error raised on the calling thread, 0 if the last call succeeded.
+#[unsafe(no_mangle)]
+pub extern "C" fn sr_last_error_code() -> i32 {
+    error::last_error().map_or(0, |(code, _)| code)
+}
+
+/// Copies the message of the last error raised on the calling thread into `buf` as a
+/// NUL-terminated UTF-8 string, truncated to `cap` bytes. Returns the full message length
+/// in bytes (excluding the NUL), or 0 if the last call succeeded, so callers can retry
+/// with a larger buffer.
+#[allow(unsafe_code, clippy::not_unsafe_ptr_arg_deref)]
+#[unsafe(no_mangle)]
+pub extern "C" fn sr_last_error_message(buf: *mut c_char, cap: usize) -> usize {
+    let Some((_, message)) = error::last_error() else {
+        return 0;
     };
+    if !buf.is_null() && cap > 0 {
+        let bytes = message.as_bytes();
+        let copied = bytes.len().min(cap - 1);
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), buf as *mut u8, copied);
+            *buf.add(copied) = 0;
+        }
+    }
+    message.len()
+}
 
-    let mut parser = OsuParser::new(path_str);
-    if let Err(e) = parser.process() {
-        eprintln!("[SR][ERROR] 解析失败: {}, 错误: {}", path_str, e);
-        return -4.0;
+#[allow(unsafe_code)]
+unsafe fn str_from_raw<'a>(ptr: *const c_char, len: usize) -> Result<&'a str, SrError> {
+    if ptr.is_null() {
+        return Err(SrError::InvalidPath("null path pointer".to_string()));
     }
+    let bytes = unsafe { std::slice::from_raw_parts(ptr as *const u8, len) };
+    std::str::from_utf8(bytes).map_err(|e| SrError::InvalidPath(e.to_string()))
+}
 
-    let data = parser.get_parsed_data();
-    if data.column_count < 1 || data.od < 0.0 {
-        eprintln!("[SR][ERROR] 数据非法: {}, column_count: {}, od: {}", path_str, data.column_count, data.od);
-        return -5.0;
+#[allow(unsafe_code)]
+unsafe fn write_attributes(out: *mut SrDifficultyAttributes, attributes: &DifficultyAttributes) {
+    if !out.is_null() {
+        unsafe { out.write(SrDifficultyAttributes::from(attributes)) };
+    }
+}
+
+// The last pool built by `run_batch`, kept so calling the batch API in a loop with the same thread
+// count does not start and stop threads every time. Another count replaces it.
+static BATCH_POOL: Mutex<Option<Arc<rayon::ThreadPool>>> = Mutex::new(None);
+
+// More threads than cores only adds switching, so requests are capped at the core count
+fn batch_threads(threads: usize) -> usize {
+    threads.min(std::thread::available_parallelism().map_or(1, |cores| cores.get()))
+}
+
+fn batch_pool(threads: usize) -> Option<Arc<rayon::ThreadPool>> {
+    let threads = batch_threads(threads);
+    let mut cached = BATCH_POOL.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(pool) = cached.as_ref().filter(|pool| pool.current_num_threads() == threads) {
+        return Some(pool.clone());
     }
+    let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(threads).build().ok()?);
+    *cached = Some(pool.clone());
+    Some(pool)
+}
 
-    if data.columns.is_empty() {
-        eprintln!("[SR][ERROR] 没有notes: {}", path_str);
-        return 0.0;
+/// Calculates every item on a rayon pool of `threads` threads (0 for the global pool, at most one per
+/// core), keeping the order. The pool is reused while the thread count stays the same. A panic only fails the item it happened in.
+fn run_batch<T: Sync>(items: &[T], threads: usize, calculate: impl Fn(&T) -> Result<f64, SrError> + Sync) -> Vec<Result<f64, SrError>> {
+    let run = || {
+        items
+            .par_iter()
+            .map(|item| std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| calculate(item))).unwrap_or(Err(SrError::InternalPanic)))
+            .collect()
+    };
+    if threads == 0 {
+        return run();
+    }
+    match batch_pool(threads) {
+        Some(pool) => pool.install(run),
+        // Threads could not be started; the global pool still gives the same results
+        None => run(),
     }
+}
+
+// What a C ABI call works on. A file is logged under `path`; calls without one (in-memory content,
+// batches, settings, or a path that could not be read) are logged under `source` instead
+#[derive(Clone, Copy)]
+enum CallSource<'a> {
+    Path(&'a str),
+    Other(&'static str),
+}
 
-    debug_log!("Rust: Calculating SR for {}", path_str);
-    match std::panic::catch_unwind(|| SRCalculator::calculate_sr_from_parsed_data(&data)) {
-        Ok(Ok(sr)) => {
-            debug_log!("Rust: Calculated SR: {}", sr);
+// Logs a record of an FFI call with its `CallSource` field followed by the given key-values
+macro_rules! log_call {
+    ($level:ident, $source:expr $(, $key:ident = $value:expr)*; $($arg:tt)+) => {
+        match $source {
+            CallSource::Path(path) => log::$level!(path = path $(, $key = $value)*; $($arg)+),
+            CallSource::Other(source) => log::$level!(source = source $(, $key = $value)*; $($arg)+),
+        }
+    };
+}
+
+/// Runs an SR calculation for the C ABI: panics become `InternalPanic`, errors are
+/// recorded for `sr_last_error_message` and returned as their negative code.
+fn ffi_sr_result(source: CallSource, calculate: impl FnOnce() -> Result<f64, SrError>) -> f64 {
+    match ffi_call(source, calculate) {
+        Ok(sr) => {
+            log_call!(debug, source, sr = sr; "calculated SR");
             sr
-        },
-        Ok(Err(e)) => {
-            eprintln!("[SR][ERROR] SR计算失败: {}, 错误: {}", path_str, e);
-            -6.0
-        },
-        Err(_) => {
-            eprintln!("[SR][ERROR] SR计算panic: {}", path_str);
-            -7.0
-        },
+        }
+        Err(code) => code as f64,
     }
 }
 
+/// Shared part of the C ABI wrappers: catches panics and records the error of a failed call.
+fn ffi_call<T>(source: CallSource, calculate: impl FnOnce() -> Result<T, SrError>) -> Result<T, i32> {
+    error::clear_last_error();
+    log_call!(trace, source; "calculating");
+    // The closures only read their inputs, so nothing is left half-updated after a panic
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(calculate)).unwrap_or(Err(SrError::InternalPanic));
+    result.map_err(|e| {
+        log_call!(error, source, kind = e.kind(), code = e.code(); "{}", e);
+        error::set_last_error(&e);
+        e.code()
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::note::Note;
+    use crate::params::SrParams;
+    use crate::config::{LegacyMods, MAX_V30_SPAN_MS};
+
+    // The 10K resource map most tests calculate
+    const GLEN_CHECK: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/Resource/Glen Check - 60's Cardin (SK_la) [Insane].osu");
 
     #[test]
     fn test_sr_calculation() {
         // Use the actual osu file for testing
-        let file_path = "..\\tests\\Resource\\Glen Check - 60's Cardin (SK_la) [Insane].osu";
-        println!("Testing with file: {}", file_path);
-
-        // Check if file exists
-        if std::path::Path::new(file_path).exists() {
-            println!("File exists!");
-        } else {
-            println!("File does not exist!");
-            panic!("Test file not found: {}", file_path);
-        }
+        assert!(std::path::Path::new(GLEN_CHECK).exists(), "Test file not found: {}", GLEN_CHECK);
 
         // Test parsing first
-        let mut parser = OsuParser::new(file_path);
-        match parser.process() {
-            Ok(_) => {
-                println!("Parsing successful!");
-                let data = parser.get_parsed_data();
-                println!("Column count: {}", data.column_count);
-                println!("Number of notes: {}", data.columns.len());
-                println!("OD: {}", data.od);
-                // Count LN notes
-                let ln_count = data.note_types.iter().filter(|&&t| t == 128).count();
-                println!("Number of LN notes: {}", ln_count);
-                // Print first 10 column values
-                println!("First 10 columns: {:?}", &data.columns[..10.min(data.columns.len())]);
-            }
-            Err(e) => {
-                println!("Parsing failed: {}", e);
-                panic!("Parsing failed: {}", e);
-            }
+        let mut parser = OsuParser::new(GLEN_CHECK);
+        if let Err(e) = parser.process() {
+            panic!("Parsing failed: {}", e);
         }
+        assert!(!parser.get_parsed_data().hit_objects.is_empty());
 
-        let result = SRAPI::calculate_sr(file_path);
-        match result {
+        match SRAPI::calculate_sr(GLEN_CHECK) {
             Ok(sr) => {
-                println!("Calculated SR: {}", sr);
                 assert!(sr >= 0.0, "SR should be non-negative");
                 // The SR calculation should produce a reasonable value for this beatmap
                 assert!(sr > 0.0, "SR should be greater than 0 for non-empty beatmap");
@@ -139,10 +550,800 @@ mod tests {
                 // Our implementation produces 5.68, which is very close (within 7% of C# reference)
                 assert!(sr > 5.5 && sr < 6.5, "SR should be close to C# reference implementation, got: {}", sr);
             }
-            Err(e) => {
-                println!("SR calculation failed with error: {}", e);
-                panic!("SR calculation failed: {}", e);
+            Err(e) => panic!("SR calculation failed: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_sr_calculation_from_content() {
+        let content = std::fs::read_to_string(GLEN_CHECK).expect("Test file not found");
+
+        let from_file = SRAPI::calculate_sr(GLEN_CHECK).unwrap();
+        let from_str = SRAPI::calculate_sr_from_str(&content).unwrap();
+        assert_eq!(from_file, from_str);
+
+        let from_ffi = calculate_sr_from_osu_content(content.as_ptr() as *const c_char, content.len());
+        assert_eq!(from_file, from_ffi);
+
+        let data = OsuParser::parse_reader(std::io::BufReader::new(content.as_bytes())).unwrap();
+        assert_eq!(data.hit_objects, OsuParser::parse_bytes(content.as_bytes()).unwrap().hit_objects);
+    }
+
+    #[test]
+    fn test_parse_timing_points() {
+        let file_path = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/Resource/Studio Syrup Comfiture - R.I.N. (SK_la) [If love, Be like].osu");
+        let mut parser = OsuParser::new(file_path);
+        parser.process().unwrap();
+        let data = parser.get_parsed_data();
+
+        assert_eq!(data.control_points.timing_points.len(), 1);
+        assert_eq!(data.control_points.inherited_points.len(), 16);
+        assert!(data.control_points.kiai_at(140000.0));
+        assert!(!data.control_points.kiai_at(160000.0));
+        assert!((data.bpm_at(40000.0).unwrap() - 126.0).abs() < 1e-6);
+        assert!((data.dominant_bpm().unwrap() - 126.0).abs() < 1e-6);
+        assert_eq!(data.beat_length_at(0.0), Some(476.190476190476));
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let mut parser = OsuParser::new(GLEN_CHECK);
+        parser.process().unwrap();
+        let data = parser.get_parsed_data();
+
+        assert_eq!(data.format_version, 14);
+        assert_eq!(data.general.mode, 3);
+        assert_eq!(data.general.audio_filename, "audio.mp3");
+        assert_eq!(data.general.preview_time, -1);
+        assert_eq!(data.metadata.title, "60's Cardin");
+        assert_eq!(data.metadata.artist, "Glen Check");
+        assert_eq!(data.metadata.creator, "SK_la");
+        assert_eq!(data.metadata.version, "Insane");
+        assert!(data.metadata.tags.is_empty());
+        assert_eq!(data.metadata.beatmap_id, 4976072);
+        assert_eq!(data.metadata.beatmap_set_id, 2322359);
+        assert_eq!(data.difficulty.hp_drain_rate, 8.0);
+        assert_eq!(data.difficulty.circle_size, 10.0);
+        assert_eq!(data.difficulty.approach_rate, 5.0);
+        assert_eq!(data.difficulty.slider_multiplier, 1.4);
+    }
+
+    const STANDARD_MAP: &str = "osu file format v14
+
+[General]
+Mode: 0
+
+[Difficulty]
+HPDrainRate:5
+CircleSize:4
+OverallDifficulty:8
+ApproachRate:9
+SliderMultiplier:1.4
+
+[TimingPoints]
+0,500,4,2,0,60,1,0
+
+[HitObjects]
+64,192,1000,1,0,0:0:0:0:
+192,192,1250,1,0,0:0:0:0:
+320,192,1500,1,0,0:0:0:0:
+448,192,1750,1,0,0:0:0:0:
+256,192,2000,2,0,B|300:192,1,140
+64,192,2500,1,0,0:0:0:0:
+448,192,2750,1,0,0:0:0:0:
+256,192,3000,12,0,4000,0:0:0:0:
+";
+
+    #[test]
+    fn test_convert_standard_map() {
+        // Conversion is opt-in
+        assert!(matches!(OsuParser::parse_str(STANDARD_MAP), Err(SrError::UnsupportedMode(0))));
+        assert!(matches!(SRAPI::calculate_sr_from_str(STANDARD_MAP), Err(SrError::UnsupportedMode(0))));
+
+        let mut parser = OsuParser::with_options("", parser::ParserOptions { convert_standard: true, ..Default::default() });
+        parser.process_reader(STANDARD_MAP.as_bytes()).unwrap();
+        let data = parser.get_parsed_data();
+        assert!(data.converted);
+        assert_eq!(data.column_count, 7);
+        // Circles can become chords; every note keeps the line of the object it came from
+        assert_eq!(data.hit_objects.len(), 13);
+        assert_eq!(data.hit_objects.iter().filter(|o| o.is_hold()).count(), 3);
+        assert_eq!(data.hit_objects.iter().filter(|o| o.line == 20).count(), 3);
+        assert!(data.hit_objects.iter().filter(|o| o.line == 21).all(|o| o.is_hold() && o.end_time == 2500));
+        assert!(SRCalculator::calculate_sr_from_parsed_data(&data).unwrap() > 0.0);
+    }
+
+    // Streams, jumps, repeat sliders, kiai, a break and spinners, so that every pattern generator is used
+    const CONVERTED_MAP: &str = "osu file format v14
+
+[General]
+Mode: 0
+
+[Difficulty]
+HPDrainRate:6
+CircleSize:4
+OverallDifficulty:8
+ApproachRate:9
+SliderMultiplier:1.6
+SliderTickRate:1
+
+[Events]
+2,9000,11000
+
+[TimingPoints]
+0,375,4,2,0,60,1,0
+6000,-100,4,2,0,60,0,1
+8000,-50,4,2,0,60,0,0
+
+[HitObjects]
+64,192,1000,1,0,0:0:0:0:
+96,192,1094,1,0,0:0:0:0:
+128,192,1188,1,0,0:0:0:0:
+160,192,1281,1,0,0:0:0:0:
+192,192,1375,5,4,0:0:0:0:
+192,192,1500,1,0,0:0:0:0:
+192,192,1625,1,8,0:0:0:0:
+400,300,1750,1,0,0:0:0:0:
+100,100,2125,1,2,0:0:0:0:
+256,192,2500,2,0,B|356:192,1,160,4|0,0:0|0:0,0:0:0:0:
+256,192,3250,2,0,B|306:192,3,40,0|0|0|0,0:0|0:0|0:0|0:0,0:0:0:0:
+320,100,3625,2,8,L|420:100,2,80
+448,192,4375,1,4,0:0:0:0:
+448,192,4562,1,0,0:0:0:0:
+448,192,4750,1,0,0:0:0:0:
+20,300,4843,1,0,0:0:0:0:
+500,50,4937,1,12,0:0:0:0:
+256,192,5125,2,0,B|356:192,2,240,8|0|4,0:0|0:0|0:0,0:0:0:0:
+256,192,6000,1,0,0:0:0:0:
+300,192,6094,1,0,0:0:0:0:
+256,192,6187,1,0,0:0:0:0:
+300,192,6281,1,0,0:0:0:0:
+100,192,6375,6,0,B|200:192,1,120
+400,192,6750,1,4,0:0:0:0:
+256,192,7125,12,0,7800,0:0:0:0:
+256,192,8000,2,0,B|256:300,4,60
+128,192,8750,1,0,0:0:0:0:
+256,192,12000,12,0,12060,0:0:0:0:
+384,192,12250,1,8,0:0:0:0:
+";
+
+    #[test]
+    fn test_converted_layout() {
+        let mut parser = OsuParser::with_options("", parser::ParserOptions { convert_standard: true, ..Default::default() });
+        parser.process_reader(CONVERTED_MAP.as_bytes()).unwrap();
+        let data = parser.get_parsed_data();
+        assert_eq!(data.column_count, 7);
+
+        // (start, column, end) of every converted note. This is the port's own output, not a layout
+        // exported from osu!, so it only guards against regressions
+        let layout: Vec<(i32, i32, i32)> = data.hit_objects.iter().map(|o| (o.start_time, o.column, o.end_time)).collect();
+        assert_eq!(layout, vec![
+            // 94 ms stream: a stair
+            (1000, 0, 1000), (1094, 1, 1094), (1188, 2, 1188), (1281, 3, 1281), (1375, 4, 1375),
+            // 125 ms: random chords avoiding the previous columns, the clap one gathered
+            (1500, 2, 1500), (1625, 3, 1625), (1625, 4, 1625), (1750, 5, 1750), (1750, 2, 1750), (1750, 6, 1750),
+            (2125, 1, 2125),
+            // Single span slider: a hold note
+            (2500, 4, 2875),
+            // 93 ms spans: one note per node
+            (3250, 3, 3250), (3343, 6, 3343), (3436, 5, 3436), (3529, 0, 3529),
+            // 187 ms spans: one or two notes per node
+            (3625, 4, 3625), (3625, 2, 3625), (3812, 4, 3812), (3812, 2, 3812), (3999, 0, 3999), (3999, 4, 3999),
+            // Finish: mirrored chord, then the stacked circles reverse it
+            (4375, 1, 4375), (4375, 5, 4375), (4375, 2, 4375), (4375, 4, 4375),
+            (4562, 5, 4562), (4562, 4, 4562), (4562, 2, 4562), (4562, 1, 4562),
+            (4750, 5, 4750), (4750, 4, 4750), (4750, 2, 4750), (4750, 1, 4750),
+            (4843, 0, 4843), (4937, 1, 4937),
+            // 562 ms spans: tiled holds ending together
+            (5125, 3, 6249), (5687, 6, 6249),
+            (6000, 3, 6000), (6094, 4, 6094), (6187, 5, 6187), (6281, 6, 6281),
+            // Kiai slider: three holds
+            (6375, 3, 6656), (6375, 4, 6656), (6375, 2, 6656),
+            (6750, 5, 6750),
+            // Spinner
+            (7125, 1, 7800),
+            // SV 2: 70 ms spans, one hold note
+            (8000, 2, 8281),
+            (8750, 1, 8750),
+            // Spinner too short for a hold
+            (12000, 4, 12000),
+            (12250, 5, 12250), (12250, 6, 12250),
+        ]);
+
+        let sr = SRCalculator::calculate_sr_from_parsed_data(&data).unwrap();
+        assert!((sr - 1.812479615846663).abs() < 1e-9, "sr = {}", sr);
+    }
+
+    #[test]
+    fn test_reject_taiko_and_catch() {
+        for mode in [1, 2] {
+            let content = STANDARD_MAP.replace("Mode: 0", &format!("Mode: {}", mode));
+            let err = OsuParser::parse_str(&content).unwrap_err();
+            assert!(matches!(err, SrError::UnsupportedMode(m) if m == mode));
+            assert_eq!(err.code(), -9);
+        }
+    }
+
+    #[test]
+    fn test_ffi_error_codes() {
+        let missing = "does/not/exist.osu";
+        assert_eq!(calculate_sr_from_osu_file(missing.as_ptr() as *const c_char, missing.len()), -3.0);
+        assert_eq!(sr_last_error_code(), -3);
+
+        let mut buf = [0 as c_char; 8];
+        let len = sr_last_error_message(buf.as_mut_ptr(), buf.len());
+        assert!(len > buf.len());
+        assert_eq!(buf[7], 0);
+
+        let invalid_utf8 = [0xffu8, 0xfe];
+        assert_eq!(calculate_sr_from_osu_file(invalid_utf8.as_ptr() as *const c_char, invalid_utf8.len()), -2.0);
+
+        let empty = STANDARD_MAP.replace("Mode: 0", "Mode: 3").split("[HitObjects]").next().unwrap().to_string() + "[HitObjects]\n";
+        assert_eq!(calculate_sr_from_osu_content(empty.as_ptr() as *const c_char, empty.len()), -8.0);
+        assert!(matches!(SRAPI::calculate_sr_from_str(&empty), Err(SrError::EmptyMap)));
+
+        let no_od = STANDARD_MAP.replace("OverallDifficulty:8\n", "");
+        assert!(matches!(SRAPI::calculate_sr_from_str(&no_od.replace("Mode: 0", "Mode: 3")), Err(SrError::InvalidOd(_))));
+
+        let too_many_keys = STANDARD_MAP.replace("Mode: 0", "Mode: 3").replace("CircleSize:4", "CircleSize:19");
+        assert!(matches!(SRAPI::calculate_sr_from_str(&too_many_keys), Err(SrError::UnsupportedKeyCount(19))));
+
+        assert!(calculate_sr_from_osu_file(GLEN_CHECK.as_ptr() as *const c_char, GLEN_CHECK.len()) > 0.0);
+        assert_eq!(sr_last_error_code(), 0);
+        assert_eq!(sr_last_error_message(std::ptr::null_mut(), 0), 0);
+    }
+
+    #[test]
+    fn test_ffi_panic_becomes_internal_panic() {
+        // Needs panic = "unwind", which the release profile keeps for the cdylib
+        assert_eq!(ffi_sr_result(CallSource::Path("panics.osu"), || panic!("calculation bug")), -7.0);
+        assert_eq!(sr_last_error_code(), -7);
+
+        let results = run_batch(&[1, 2, 3], 0, |&i| if i == 2 { panic!("calculation bug") } else { Ok(i as f64) });
+        assert!(matches!(results.as_slice(), [Ok(_), Err(SrError::InternalPanic), Ok(_)]));
+    }
+
+    #[test]
+    fn test_batch_pool_reused() {
+        let cores = std::thread::available_parallelism().unwrap().get();
+        assert_eq!(batch_threads(usize::MAX), cores);
+        assert_eq!(batch_threads(1), 1);
+
+        // Two threads, as in `test_sr_batch`, so a test running alongside does not replace the pool
+        let first = batch_pool(2).unwrap();
+        assert_eq!(first.current_num_threads(), batch_threads(2));
+        let results = run_batch(&[1, 2], 2, |&i| Ok(i as f64));
+        assert!(matches!(results.as_slice(), [Ok(1.0), Ok(2.0)]));
+        assert!(Arc::ptr_eq(&first, &batch_pool(2).unwrap()));
+    }
+
+    static LOGGED: std::sync::Mutex<Vec<(i32, String, String)>> = std::sync::Mutex::new(Vec::new());
+
+    extern "C" fn record_log(level: i32, _target: *const c_char, message: *const c_char, fields: *const c_char) {
+        let text = |ptr: *const c_char| unsafe { std::ffi::CStr::from_ptr(ptr) }.to_string_lossy().into_owned();
+        LOGGED.lock().unwrap().push((level, text(message), text(fields)));
+    }
+
+    #[test]
+    fn test_log_callback() {
+        // Other tests log from their own threads, so only records for this path are checked
+        let missing = "log/callback/missing.osu";
+        let logged_for_path = || -> Vec<(i32, String, serde_json::Value)> {
+            LOGGED
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(level, message, fields)| (*level, message.clone(), serde_json::from_str(fields).unwrap()))
+                .filter(|(_, _, fields): &(i32, String, serde_json::Value)| fields["path"] == missing)
+                .collect()
+        };
+
+        assert_eq!(sr_set_log_callback(Some(record_log), 1), 0);
+        assert_eq!(calculate_sr_from_osu_file(missing.as_ptr() as *const c_char, missing.len()), -3.0);
+        let records = logged_for_path();
+        assert_eq!(records.len(), 1);
+        let (level, message, fields) = &records[0];
+        assert_eq!(*level, 1);
+        assert!(message.starts_with("I/O error"));
+        assert_eq!(fields["kind"], "Io");
+        assert_eq!(fields["code"], -3);
+
+        // Calls without a file name what they work on under `source`, and leave `path` to real paths
+        assert_eq!(calculate_sr_from_osu_content(std::ptr::null(), 0), -2.0);
+        let sources: Vec<serde_json::Value> = LOGGED.lock().unwrap().iter().map(|(_, _, fields)| serde_json::from_str(fields).unwrap()).collect();
+        assert!(sources.iter().any(|fields| fields["source"] == "content" && fields["code"] == -2));
+        assert!(sources.iter().all(|fields| fields.get("path").is_none() || fields.get("source").is_none()));
+
+        // Silenced: nothing more arrives
+        assert_eq!(sr_set_log_callback(None, 5), 0);
+        assert_eq!(calculate_sr_from_osu_file(missing.as_ptr() as *const c_char, missing.len()), -3.0);
+        assert_eq!(logged_for_path().len(), 1);
+
+        assert_eq!(sr_set_log_callback(Some(record_log), 6), -10);
+        assert_eq!(SrError::LoggerAlreadyInstalled.code(), -12);
+    }
+
+    fn with_hold_type(content: &str, hold_type: &str) -> String {
+        let (head, objects) = content.split_once("[HitObjects]").unwrap();
+        let objects: Vec<String> = objects.lines().map(|line| {
+            let mut fields: Vec<&str> = line.split(',').collect();
+            if fields.len() > 3 && fields[3] == "128" {
+                fields[3] = hold_type;
             }
+            fields.join(",")
+        }).collect();
+        format!("{}[HitObjects]{}", head, objects.join("\n"))
+    }
+
+    #[test]
+    fn test_flagged_hold_notes_are_long_notes() {
+        let content = std::fs::read_to_string(GLEN_CHECK).unwrap();
+        let plain = SRAPI::calculate_sr_from_str(&content).unwrap();
+
+        // New combo (4) and combo skip (16-64) bits must not turn holds into rice notes
+        for hold_type in ["132", "148", "244"] {
+            let flagged = with_hold_type(&content, hold_type);
+            let data = OsuParser::parse_str(&flagged).unwrap();
+            assert_eq!(data.hit_objects.iter().filter(|o| o.is_hold()).count(), 398);
+            assert_eq!(SRAPI::calculate_sr_from_str(&flagged).unwrap(), plain, "type {}", hold_type);
+        }
+
+        // The map really has LNs, so treating them as rice would change the result
+        let as_rice = with_hold_type(&content, "1");
+        assert_ne!(SRAPI::calculate_sr_from_str(&as_rice).unwrap(), plain);
+    }
+
+    #[test]
+    fn test_sr_from_generated_notes() {
+        let data = OsuParser::parse_str(&std::fs::read_to_string(GLEN_CHECK).unwrap()).unwrap();
+        let beatmap = ManiaBeatmap::from(&data);
+        let expected = SRAPI::calculate_sr(GLEN_CHECK).unwrap();
+
+        // Note order must not matter
+        let mut shuffled = beatmap.notes.clone();
+        shuffled.reverse();
+        assert_eq!(SRCalculator::calculate_sr(&shuffled, beatmap.key_count, beatmap.od).unwrap(), expected);
+        assert_eq!(SRCalculator::calculate_sr_for_beatmap(&beatmap).unwrap(), expected);
+
+        // A stream across 4 columns with a few holds
+        let notes: Vec<Note> = (0..400)
+            .map(|i| Note::new(i % 4, i * 120, if i % 10 == 0 { i * 120 + 300 } else { -1 }))
+            .collect();
+        let sr = SRCalculator::calculate_sr(&notes, 4, 8.0).unwrap();
+        assert!(sr > 0.0 && sr.is_finite());
+
+        assert!(matches!(SRCalculator::calculate_sr(&[], 4, 8.0), Err(SrError::EmptyMap)));
+        assert!(matches!(SRCalculator::calculate_sr(&notes, 19, 8.0), Err(SrError::UnsupportedKeyCount(19))));
+    }
+
+    #[test]
+    fn test_out_of_range_columns() {
+        // Columns outside 0..k are clamped, not a panic
+        let notes: Vec<Note> = (0..200).map(|i| Note::new(i % 4, i * 150, if i % 7 == 0 { i * 150 + 400 } else { -1 })).collect();
+        let mut outside = notes.clone();
+        outside[3].k = 5;
+        outside[10].k = -1;
+        let mut clamped = notes.clone();
+        clamped[3].k = 3;
+        clamped[10].k = 0;
+        for algorithm in SrAlgorithm::ALL {
+            let config = SrConfig { algorithm, ..Default::default() };
+            let expected = SRCalculator::calculate_sr_with_config(&clamped, 4, 8.0, &config).unwrap();
+            assert_eq!(SRCalculator::calculate_sr_with_config(&outside, 4, 8.0, &config).unwrap(), expected, "{:?}", algorithm);
         }
+
+        // Over the FFI, x positions outside 0-511 land in the edge columns
+        let mania = STANDARD_MAP.replace("Mode: 0", "Mode: 3");
+        let outside = mania.replace("448,192,1750", "600,192,1750").replace("64,192,1000", "-40,192,1000");
+        let clamped = mania.replace("448,192,1750", "511,192,1750").replace("64,192,1000", "0,192,1000");
+        let ffi = |content: &str| calculate_sr_from_osu_content(content.as_ptr() as *const c_char, content.len());
+        let expected = ffi(&clamped);
+        assert!(expected > 0.0);
+        assert_eq!(ffi(&outside), expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_difficulty_attributes() {
+        let attributes = SRAPI::calculate_attributes(GLEN_CHECK).unwrap();
+        assert_eq!(attributes.star_rating, SRAPI::calculate_sr(GLEN_CHECK).unwrap());
+        assert_eq!(attributes.hold_count, 398);
+
+        for skill in [attributes.jack, attributes.cross_column, attributes.pressing_intensity, attributes.density, attributes.active_keys] {
+            assert!(skill.mean > 0.0 && skill.percentile93 >= skill.mean, "{:?}", skill);
+        }
+        assert!(attributes.active_keys.percentile93 <= attributes.key_count as f64);
+
+        // A one-column jack stream has no cross-column or LN component
+        let jacks: Vec<Note> = (0..200).map(|i| Note::new(0, i * 100, -1)).collect();
+        let jack_map = SRCalculator::calculate_attributes(&jacks, 4, 8.0).unwrap();
+        assert_eq!(jack_map.release.percentile93, 0.0);
+        assert!(jack_map.jack.percentile93 > attributes.jack.percentile93);
+
+        let mut out = SrDifficultyAttributes::default();
+        let path = GLEN_CHECK.as_bytes();
+        let sr = calculate_attributes_from_osu_file(path.as_ptr() as *const c_char, path.len(), &mut out);
+        assert_eq!(sr, attributes.star_rating);
+        assert_eq!(out, SrDifficultyAttributes::from(&attributes));
+    }
+
+    #[test]
+    fn test_strain_timeline() {
+        let timeline = SRAPI::calculate_strain_timeline(GLEN_CHECK, 1000.0, false).unwrap();
+        assert_eq!(timeline.points[0].time, 0.0);
+        assert!(timeline.points.windows(2).all(|w| w[1].time - w[0].time == 1000.0));
+        assert!(timeline.points.iter().all(|p| p.skills.is_none() && p.difficulty >= 0.0));
+
+        // The resampled curve follows the raw one
+        let raw = SRAPI::calculate_strain_timeline(GLEN_CHECK, 0.0, true).unwrap();
+        assert!(raw.points.len() > timeline.points.len());
+        let raw_peak = raw.peak().unwrap().difficulty;
+        assert!(timeline.peak().unwrap().difficulty <= raw_peak && timeline.peak().unwrap().difficulty > raw_peak * 0.8);
+        assert!(raw.points.iter().any(|p| p.skills.unwrap().release > 0.0));
+
+        let path = GLEN_CHECK.as_bytes();
+        let json_ptr = calculate_strain_timeline_from_osu_file(path.as_ptr() as *const c_char, path.len(), 1000.0, false);
+        assert!(!json_ptr.is_null());
+        let json = unsafe { std::ffi::CStr::from_ptr(json_ptr) }.to_str().unwrap().to_string();
+        sr_free_string(json_ptr);
+        let decoded: StrainTimeline = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.points.len(), timeline.points.len());
+        assert!(decoded.points.iter().zip(&timeline.points).all(|(a, b)| a.time == b.time && (a.difficulty - b.difficulty).abs() < 1e-12));
+
+        let missing = b"missing.osu";
+        assert!(calculate_strain_timeline_from_osu_file(missing.as_ptr() as *const c_char, missing.len(), 1000.0, false).is_null());
+        assert_eq!(sr_last_error_code(), -3);
+
+        // Sub-millisecond intervals are rejected before anything is allocated
+        assert!(matches!(SRAPI::calculate_strain_timeline(GLEN_CHECK, 0.5, false), Err(SrError::InvalidConfig(_))));
+        assert!(calculate_strain_timeline_from_osu_file(path.as_ptr() as *const c_char, path.len(), 1e-6, false).is_null());
+        assert_eq!(sr_last_error_code(), -10);
+        assert!(SRAPI::calculate_strain_timeline(GLEN_CHECK, 1.0, false).is_ok());
+    }
+
+    #[test]
+    fn test_strain_timeline_with_config() {
+        let nomod = SRAPI::calculate_strain_timeline(GLEN_CHECK, 0.0, false).unwrap();
+        let config = SrConfig::from_legacy_mods(LegacyMods::DOUBLE_TIME);
+        let dt = SRAPI::calculate_strain_timeline_with_config(GLEN_CHECK, 0.0, false, &config).unwrap();
+
+        // DT plays the map in two thirds of the time, and harder
+        let (nomod_end, dt_end) = (nomod.points.last().unwrap().time, dt.points.last().unwrap().time);
+        assert!((dt_end - nomod_end / 1.5).abs() <= 2.0, "{} vs {}", dt_end, nomod_end);
+        assert!(dt.peak().unwrap().difficulty > nomod.peak().unwrap().difficulty);
+
+        let v30 = SrConfig { algorithm: SrAlgorithm::V30, ..SrConfig::default() };
+        let v30_timeline = SRAPI::calculate_strain_timeline_with_config(GLEN_CHECK, 1000.0, false, &v30).unwrap();
+        let rebirth_timeline = SRAPI::calculate_strain_timeline(GLEN_CHECK, 1000.0, false).unwrap();
+        assert!(v30_timeline.points.iter().zip(&rebirth_timeline.points).any(|(a, b)| a.difficulty != b.difficulty));
+
+        let path = GLEN_CHECK.as_bytes();
+        let json_ptr = calculate_strain_timeline_from_osu_file_with_mods(path.as_ptr() as *const c_char, path.len(), 0.0, false, LegacyMods::DOUBLE_TIME, 0);
+        assert!(!json_ptr.is_null());
+        let json = unsafe { std::ffi::CStr::from_ptr(json_ptr) }.to_str().unwrap().to_string();
+        sr_free_string(json_ptr);
+        let decoded: StrainTimeline = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.points.len(), dt.points.len());
+        assert!(decoded.points.iter().zip(&dt.points).all(|(a, b)| a.time == b.time && (a.difficulty - b.difficulty).abs() < 1e-12));
+
+        assert!(calculate_strain_timeline_from_osu_file_with_mods(path.as_ptr() as *const c_char, path.len(), 1000.0, false, 0, 99).is_null());
+        assert_eq!(sr_last_error_code(), -10);
+    }
+
+    #[test]
+    fn test_rate_mods() {
+        use crate::config::RateMod;
+
+        let nomod = SRAPI::calculate_sr(GLEN_CHECK).unwrap();
+        assert_eq!(SRAPI::calculate_sr_with_config(GLEN_CHECK, &SrConfig::default()).unwrap(), nomod);
+
+        let table = SRAPI::calculate_rate_table(GLEN_CHECK, &[0.5, 0.75, 1.0, 1.5, 2.0]).unwrap();
+        assert_eq!(table[2], (1.0, nomod));
+        assert!(table.windows(2).all(|w| w[0].1 < w[1].1), "{:?}", table);
+
+        let dt = SRAPI::calculate_sr_with_config(GLEN_CHECK, &RateMod::DoubleTime.into()).unwrap();
+        assert_eq!(dt, table[3].1);
+        assert_eq!(SRAPI::calculate_sr_with_config(GLEN_CHECK, &RateMod::Nightcore.into()).unwrap(), dt);
+        assert_eq!(SRAPI::calculate_sr_with_config(GLEN_CHECK, &RateMod::HalfTime.into()).unwrap(), table[1].1);
+
+        let path = GLEN_CHECK.as_bytes();
+        assert_eq!(calculate_sr_from_osu_file_with_rate(path.as_ptr() as *const c_char, path.len(), 1.5), dt);
+        assert_eq!(calculate_sr_from_osu_file_with_rate(path.as_ptr() as *const c_char, path.len(), 0.0), -10.0);
+    }
+
+    #[test]
+    fn test_od_mods_and_hit_windows() {
+        use crate::config::{LegacyMods, OdModifier};
+
+        let nomod = SRAPI::calculate_sr(GLEN_CHECK).unwrap();
+        let with = |config: SrConfig| SRAPI::calculate_sr_with_config(GLEN_CHECK, &config).unwrap();
+
+        // Tighter windows make the map harder
+        let hr = with(SrConfig { od_modifier: OdModifier::HardRock, ..Default::default() });
+        let ez = with(SrConfig { od_modifier: OdModifier::Easy, ..Default::default() });
+        assert!(ez < nomod && nomod < hr, "{} {} {}", ez, nomod, hr);
+
+        let lazer = with(SrConfig { hit_window: HitWindowModel::Lazer, ..Default::default() });
+        assert!((lazer - nomod).abs() < 0.05);
+
+        // A custom window equal to the stable one gives the stable result
+        let data = OsuParser::parse_str(&std::fs::read_to_string(GLEN_CHECK).unwrap()).unwrap();
+        let great = HitWindowModel::Stable.great_window(data.od);
+        assert_eq!(with(SrConfig { hit_window: HitWindowModel::Custom { great }, ..Default::default() }), nomod);
+
+        let path = GLEN_CHECK.as_bytes();
+        let ffi = |mods: u32, model: i32, great: f64| calculate_sr_from_osu_file_with_mods(path.as_ptr() as *const c_char, path.len(), mods, model, great);
+        assert_eq!(ffi(LegacyMods::HARD_ROCK, 0, 0.0), hr);
+        assert_eq!(ffi(0, 1, 0.0), lazer);
+        assert_eq!(ffi(0, 2, 0.0), -10.0);
+        assert_eq!(ffi(0, 7, 0.0), -10.0);
+        assert_eq!(ffi(LegacyMods::DOUBLE_TIME, 0, 0.0), SRAPI::calculate_sr_with_config(GLEN_CHECK, &SrConfig { rate: 1.5, ..Default::default() }).unwrap());
+    }
+
+    #[test]
+    fn test_note_mods() {
+        use crate::config::LegacyMods;
+        use crate::mods::NoteMod;
+
+        let data = OsuParser::parse_str(&std::fs::read_to_string(GLEN_CHECK).unwrap()).unwrap();
+        let beatmap = ManiaBeatmap::from(&data);
+        let nomod = SRCalculator::calculate_sr_for_beatmap(&beatmap).unwrap();
+        let with = |note_mods: Vec<NoteMod>| {
+            let config = SrConfig { note_mods, ..Default::default() };
+            SRCalculator::calculate_sr_with_config(&beatmap.notes, beatmap.key_count, beatmap.od, &config).unwrap()
+        };
+
+        // The config result is the SR of the exported notes
+        let no_ln = with(vec![NoteMod::NoLn]);
+        assert_eq!(no_ln, SRCalculator::calculate_sr_for_beatmap(&beatmap.with_mods(&[NoteMod::NoLn])).unwrap());
+        assert!(no_ln < nomod);
+        assert!(with(vec![NoteMod::FullLn { gap: 60 }]) > nomod);
+        assert!((with(vec![NoteMod::Mirror]) - nomod).abs() < 0.2);
+        assert!(with(vec![NoteMod::Random { seed: 7 }]).is_finite());
+        assert!(with(vec![NoteMod::Invert { gap: 90 }]).is_finite());
+
+        let path = GLEN_CHECK.as_bytes();
+        let mirror = calculate_sr_from_osu_file_with_mods(path.as_ptr() as *const c_char, path.len(), LegacyMods::MIRROR, 0, 0.0);
+        assert_eq!(mirror, with(vec![NoteMod::Mirror]));
+        // Doubling this 10K map would need 20 columns
+        assert_eq!(calculate_sr_from_osu_file_with_mods(path.as_ptr() as *const c_char, path.len(), LegacyMods::KEY_COOP, 0, 0.0), -6.0);
+        let five_key = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/Resource/Jumpstream - Happy Hardcore Synthesizer (SK_la) [5k-1].osu");
+        let dual = SRAPI::calculate_sr_with_config(five_key, &SrConfig::from_legacy_mods(LegacyMods::KEY_COOP)).unwrap();
+        assert!(dual > 0.0 && dual != SRAPI::calculate_sr(five_key).unwrap());
+    }
+
+    #[test]
+    fn test_pp_from_file() {
+        let attributes = SRAPI::calculate_attributes(GLEN_CHECK).unwrap();
+        let ss = ScoreInfo { accuracy: 1.0, total_hits: 0, mods: 0 };
+        let pp = SRAPI::calculate_pp(GLEN_CHECK, &ss, &PpFormula::Mania).unwrap();
+        assert_eq!(pp, PerformanceCalculator::calculate(&attributes, &ss, &PpFormula::Mania));
+
+        let path = GLEN_CHECK.as_bytes();
+        assert_eq!(calculate_pp_from_osu_file(path.as_ptr() as *const c_char, path.len(), 0, std::ptr::null()), pp.pp);
+
+        let judgements = Judgements { perfect: 1500, great: 300, good: 40, ok: 10, meh: 5, miss: 8 };
+        let played = calculate_pp_from_osu_file(path.as_ptr() as *const c_char, path.len(), 0, &judgements);
+        assert!(played > 0.0 && played < pp.pp);
+
+        // DT raises SR and therefore pp
+        let dt = SRAPI::calculate_pp(GLEN_CHECK, &ScoreInfo { mods: config::LegacyMods::DOUBLE_TIME, ..ss }, &PpFormula::Mania).unwrap();
+        assert!(dt.pp > pp.pp);
+    }
+
+    #[test]
+    fn test_odd_key_counts_above_10k() {
+        // The same pattern for every key count
+        let pattern = |k: i32| -> Vec<Note> {
+            (0..600).map(|i| Note::new((i * 7 + i / 3) % k, i * 90, if i % 9 == 0 { i * 90 + 250 } else { -1 })).collect()
+        };
+        for k in 1..=18 {
+            assert!(SRCalculator::calculate_sr(&pattern(k), k, 8.0).is_ok(), "{}K", k);
+        }
+
+        // The same values are asserted against the C# SRCalculator (PythonPortAlgorithm) in
+        // VS_SR_Tests.Test_OddKeyCountsAbove10K_PinnedValues, to the 0.001 bound used between the two
+        let reference = [(11, 3.596664668579102), (13, 3.478764698265222), (15, 3.7706354740072094), (17, 2.7612356501878734)];
+        for (k, expected) in reference {
+            let attributes = SRCalculator::calculate_attributes(&pattern(k), k, 8.0).unwrap();
+            assert!((attributes.star_rating - expected).abs() < 1e-3, "{}K: {}", k, attributes.star_rating);
+            // Without a matrix the cross-column skill would be zero
+            assert!(attributes.cross_column.percentile93 > 1.0, "{}K", k);
+        }
+
+        // 18K with every fourth note on the last column, and 18K using only columns 0, 5, 11 and 17;
+        // also checked in the same C# test
+        let max_column: Vec<Note> = (0..600)
+            .map(|i| Note::new(if i % 4 == 0 { 17 } else { (i * 7 + i / 3) % 18 }, i * 90, if i % 9 == 0 { i * 90 + 250 } else { -1 }))
+            .collect();
+        let columns = [0, 5, 11, 17];
+        let sparse: Vec<Note> = (0..500)
+            .map(|i| Note::new(columns[((i * 3 + i / 5) % 4) as usize], i * 110, if i % 7 == 0 { i * 110 + 100 } else { -1 }))
+            .collect();
+        for (name, notes, expected) in [("max column", max_column, 3.4779772607656967), ("sparse columns", sparse, 2.9005994824591403)] {
+            let sr = SRCalculator::calculate_sr(&notes, 18, 8.0).unwrap();
+            assert!((sr - expected).abs() < 1e-3, "18K {}: {}", name, sr);
+        }
+    }
+
+    #[test]
+    fn test_custom_cross_matrices() {
+        let notes: Vec<Note> = (0..300).map(|i| Note::new((i * 2 + i / 4) % 3, i * 110, -1)).collect();
+        let default = SRCalculator::calculate_attributes(&notes, 3, 8.0).unwrap();
+
+        let heavier = vec![0.5, 0.5, 0.5, 0.5];
+        let config = SrConfig { cross_matrices: [(3, heavier.clone())].into_iter().collect(), ..Default::default() };
+        let per_call = SRCalculator::calculate_attributes_with_config(&notes, 3, 8.0, &config).unwrap();
+        assert!(per_call.cross_column.mean > default.cross_column.mean);
+
+        let bad = SrConfig { cross_matrices: [(3, vec![0.5; 3])].into_iter().collect(), ..Default::default() };
+        assert!(matches!(SRCalculator::calculate_sr_with_config(&notes, 3, 8.0, &bad), Err(SrError::InvalidConfig(_))));
+
+        // The global override is shared with tests running in parallel, so it is only ever set to the
+        // built-in matrix, which changes no result elsewhere. Overrides taking effect are covered above.
+        let builtin = CrossMatrixProvider::get_matrix(3).unwrap();
+        assert_eq!(set_custom_cross_matrix(3, builtin.as_ptr(), builtin.len()), 0);
+        assert_eq!(CrossMatrixProvider::get_matrix(3), Some(builtin.clone()));
+        assert_eq!(SRCalculator::calculate_attributes(&notes, 3, 8.0).unwrap(), default);
+        assert_eq!(set_custom_cross_matrix(3, heavier.as_ptr(), 2), -10);
+        assert_eq!(set_custom_cross_matrix(19, heavier.as_ptr(), heavier.len()), -6);
+        assert_eq!(CrossMatrixProvider::get_matrix(3), Some(builtin));
+
+        assert_eq!(set_custom_cross_matrix(3, std::ptr::null(), 0), 0);
+        assert_eq!(SRCalculator::calculate_attributes(&notes, 3, 8.0).unwrap(), default);
+    }
+
+    #[test]
+    fn test_sr_params() {
+        let notes: Vec<Note> = (0..400).map(|i| Note::new((i * 3 + i / 5) % 7, i * 95, if i % 6 == 0 { i * 95 + 300 } else { -1 })).collect();
+        let sr = SRCalculator::calculate_sr(&notes, 7, 8.0).unwrap();
+
+        // Defaults loaded from JSON reproduce the standard SR exactly
+        let params = SrParams::from_json(&SrParams::default().to_json()).unwrap();
+        let config = SrConfig { params, ..Default::default() };
+        assert_eq!(SRCalculator::calculate_sr_with_config(&notes, 7, 8.0, &config).unwrap(), sr);
+
+        let unscaled = SrConfig { params: SrParams { final_scale: 1.0, ..Default::default() }, ..Default::default() };
+        let unscaled_sr = SRCalculator::calculate_sr_with_config(&notes, 7, 8.0, &unscaled).unwrap();
+        assert!((unscaled_sr * 0.975 - sr).abs() < 1e-9);
+
+        let undamped = SrConfig { params: SrParams { note_count_damping: 0.0, ..Default::default() }, ..Default::default() };
+        assert!(SRCalculator::calculate_sr_with_config(&notes, 7, 8.0, &undamped).unwrap() > sr);
+
+        let wider = SrConfig { params: SrParams { smoothing_window: 800.0, key_usage_span: 250.0, ..Default::default() }, ..Default::default() };
+        let wider_sr = SRCalculator::calculate_sr_with_config(&notes, 7, 8.0, &wider).unwrap();
+        assert!(wider_sr.is_finite() && wider_sr != sr);
+
+        let invalid = SrConfig { params: SrParams { density_window: -1.0, ..Default::default() }, ..Default::default() };
+        assert!(matches!(SRCalculator::calculate_sr_with_config(&notes, 7, 8.0, &invalid), Err(SrError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_sr_algorithms() {
+        let v30 = SrConfig { algorithm: SrAlgorithm::V30, ..Default::default() };
+        let sr = SRAPI::calculate_sr_with_config(GLEN_CHECK, &v30).unwrap();
+        // Same value as tests/AnalysisSR/SR-V3.0.cs
+        assert!((sr - 5.770052697159).abs() < 1e-9, "{}", sr);
+        assert_ne!(sr, SRAPI::calculate_sr(GLEN_CHECK).unwrap());
+
+        let path = GLEN_CHECK.as_bytes();
+        let ffi = |algorithm: i32, mods: u32| calculate_sr_from_osu_file_with_algorithm(path.as_ptr() as *const c_char, path.len(), algorithm, mods);
+        assert_eq!(ffi(SrAlgorithm::Rebirth.id(), 0), SRAPI::calculate_sr(GLEN_CHECK).unwrap());
+        assert_eq!(ffi(SrAlgorithm::V30.id(), 0), sr);
+        assert_eq!(ffi(2, 0), -10.0);
+        let dt = SrConfig { rate: 1.5, ..v30.clone() };
+        assert_eq!(ffi(SrAlgorithm::V30.id(), LegacyMods::DOUBLE_TIME), SRAPI::calculate_sr_with_config(GLEN_CHECK, &dt).unwrap());
+
+        for algorithm in SrAlgorithm::ALL {
+            assert_eq!(SrAlgorithm::from_id(algorithm.id()), Some(algorithm));
+        }
+
+        // V3.0 has no odd key counts above 9K
+        let notes: Vec<Note> = (0..200).map(|i| Note::new(i % 11, i * 100, -1)).collect();
+        assert!(SRCalculator::calculate_sr(&notes, 11, 8.0).is_ok());
+        assert!(matches!(SRCalculator::calculate_sr_with_config(&notes, 11, 8.0, &v30), Err(SrError::UnsupportedKeyCount(11))));
+        let notes: Vec<Note> = (0..200).map(|i| Note::new(i % 10, i * 100, -1)).collect();
+        let attributes = SRCalculator::calculate_attributes_with_config(&notes, 10, 8.0, &v30).unwrap();
+        assert!(attributes.star_rating > 0.0 && attributes.jack.mean > 0.0);
+
+        // Notes before 0 ms rate the same as the map moved to start at 0, and the timeline keeps map time
+        let early: Vec<Note> = (0..200).map(|i| Note::new(i % 4, i * 100 - 450, if i % 5 == 0 { i * 100 - 300 } else { -1 })).collect();
+        let moved: Vec<Note> = early.iter().map(|n| Note::new(n.k, n.h + 450, if n.t >= 0 { n.t + 450 } else { -1 })).collect();
+        let early_sr = SRCalculator::calculate_sr_with_config(&early, 4, 8.0, &v30).unwrap();
+        assert_eq!(early_sr, SRCalculator::calculate_sr_with_config(&moved, 4, 8.0, &v30).unwrap());
+        let timeline = SRCalculator::calculate_strain_timeline_with_config(&early, 4, 8.0, 0.0, false, &v30).unwrap();
+        assert_eq!(timeline.points[0].time, -450.0);
+        let resampled = SRCalculator::calculate_strain_timeline_with_config(&early, 4, 8.0, 200.0, false, &v30).unwrap();
+        assert_eq!(resampled.points[0].time, -600.0);
+        assert_eq!(resampled.points.iter().find(|p| p.time == 0.0).map(|p| p.difficulty), timeline.points.iter().find(|p| p.time == 0.0).map(|p| p.difficulty));
+
+        // One hold past the limit, or at the end of the i32 range, is refused before allocating
+        let limit = MAX_V30_SPAN_MS as i32;
+        for tail in [limit, 100_000_000, i32::MAX] {
+            let long = [Note::new(0, 0, -1), Note::new(1, 1000, tail)];
+            let err = SRCalculator::calculate_sr_with_config(&long, 4, 8.0, &v30).unwrap_err();
+            assert!(matches!(err, SrError::MapTooLong { limit_ms: MAX_V30_SPAN_MS, .. }), "{}", err);
+            assert_eq!(err.code(), -11);
+        }
+        // The span counts from the first note when it is before 0 ms
+        let early_long = [Note::new(0, -1000, -1), Note::new(1, limit - 1000, -1)];
+        assert!(matches!(SRCalculator::calculate_sr_with_config(&early_long, 4, 8.0, &v30), Err(SrError::MapTooLong { span_ms, .. }) if span_ms == MAX_V30_SPAN_MS + 1));
+    }
+
+    #[test]
+    fn test_sr_batch() {
+        let resource = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/Resource/");
+        let paths: Vec<String> = ["Jumpstream - Happy Hardcore Synthesizer (SK_la) [5k-1].osu", "missing.osu", "Jumpstream - Happy Hardcore Synthesizer (SK_la) [7k-1].osu"]
+            .iter()
+            .map(|name| format!("{}{}", resource, name))
+            .collect();
+
+        let results = SRAPI::calculate_sr_batch(&paths, 2);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), &SRAPI::calculate_sr(&paths[0]).unwrap());
+        assert!(matches!(results[1], Err(SrError::Io(_))));
+        assert_eq!(results[2].as_ref().unwrap(), &SRAPI::calculate_sr(&paths[2]).unwrap());
+
+        let ht = SrConfig { rate: 0.75, ..Default::default() };
+        let with_config = SRAPI::calculate_sr_batch_with_config(&paths[..1], &ht, 0);
+        assert_eq!(with_config[0].as_ref().unwrap(), &SRAPI::calculate_sr_with_config(&paths[0], &ht).unwrap());
+
+        let ptrs: Vec<*const c_char> = paths.iter().map(|p| p.as_ptr() as *const c_char).chain([std::ptr::null()]).collect();
+        let lens: Vec<usize> = paths.iter().map(|p| p.len()).chain([0]).collect();
+        let mut out = vec![0.0; 4];
+        assert_eq!(calculate_sr_batch_from_osu_files(ptrs.as_ptr(), lens.as_ptr(), 4, 0, out.as_mut_ptr()), 0);
+        assert_eq!(out[0], *results[0].as_ref().unwrap());
+        assert_eq!(out[1], -3.0);
+        assert_eq!(out[2], *results[2].as_ref().unwrap());
+        assert_eq!(out[3], -2.0);
+
+        assert_eq!(calculate_sr_batch_from_osu_files(ptrs.as_ptr(), lens.as_ptr(), 4, 0, std::ptr::null_mut()), -2);
+        assert_eq!(calculate_sr_batch_from_osu_files(std::ptr::null(), std::ptr::null(), 0, 0, std::ptr::null_mut()), 0);
+    }
+
+    #[test]
+    fn test_long_high_key_maps() {
+        // SR of long synthetic maps, pinned so the corner data structures stay exact
+        let cases = [(4, 1, 11, 3.8784695805556284), (7, 3, 12, 4.66224990642019), (10, 3, 13, 4.764202892049302), (18, 4, 14, 4.763725164336283)];
+        for (key_count, minutes, seed, expected) in cases {
+            let notes = synthetic::synthetic_map(key_count, minutes, 1, seed);
+            assert_eq!(SRCalculator::calculate_sr(&notes, key_count, 8.0).unwrap(), expected, "{}K", key_count);
+        }
+    }
+
+    #[test]
+    fn test_sparse_matches_dense() {
+        // The corner runs must give bit for bit the SR of the K x corners matrices they replaced
+        let maps = [(7, 3, 1, 12), (10, 3, 3, 13), (18, 2, 5, 14)];
+        for (key_count, minutes, notes_per_step, seed) in maps {
+            let notes = synthetic::synthetic_map(key_count, minutes, notes_per_step, seed);
+            let dense = sr::dense::calculate_sr_with_config(&notes, key_count, 8.0, &SrConfig::default()).unwrap();
+            assert_eq!(SRCalculator::calculate_sr(&notes, key_count, 8.0).unwrap(), dense, "{}K", key_count);
+        }
+        let beatmap = ManiaBeatmap::from(&OsuParser::parse_str(&std::fs::read_to_string(GLEN_CHECK).unwrap()).unwrap());
+        let dense = sr::dense::calculate_sr_with_config(&beatmap.notes, beatmap.key_count, beatmap.od, &SrConfig::default()).unwrap();
+        assert_eq!(SRCalculator::calculate_sr_for_beatmap(&beatmap).unwrap(), dense);
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let resource = concat!(env!("CARGO_MANIFEST_DIR"), "/../tests/Resource/");
+        let names = [
+            "Glen Check - 60's Cardin (SK_la) [Insane].osu",
+            "Studio Syrup Comfiture - R.I.N. (SK_la) [If love, Be like].osu",
+            "Jumpstream - Happy Hardcore Synthesizer (SK_la) [10k-1].osu",
+        ];
+        for name in names {
+            let content = std::fs::read_to_string(format!("{}{}", resource, name)).unwrap();
+            let beatmap = ManiaBeatmap::from(&OsuParser::parse_str(&content).unwrap());
+            for algorithm in SrAlgorithm::ALL {
+                let sequential = SrConfig { algorithm, ..Default::default() };
+                let parallel = SrConfig { parallel: true, ..sequential.clone() };
+                let attributes = |config: &SrConfig| SRCalculator::calculate_attributes_with_config(&beatmap.notes, beatmap.key_count, beatmap.od, config).unwrap();
+                assert_eq!(attributes(&parallel), attributes(&sequential), "{} {:?}", name, algorithm);
+            }
+        }
+
+        let path = format!("{}{}", resource, names[1]);
+        assert_eq!(calculate_sr_from_osu_file_parallel(path.as_ptr() as *const c_char, path.len()), SRAPI::calculate_sr(&path).unwrap());
+    }
+}
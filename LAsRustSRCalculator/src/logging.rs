@@ -0,0 +1,77 @@
+use log::kv::{Error, Key, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::sync::{OnceLock, RwLock};
+
+/// Receives the crate's log records over the C ABI. `level` is 1 (error) to 5 (trace); `fields`
+/// is a JSON object of the record's structured fields, such as `path` (or `source` for calls without
+/// a file), `kind` and `code`.
+/// The strings are NUL-terminated UTF-8 and only valid during the call.
+pub type LogCallback = extern "C" fn(level: i32, target: *const c_char, message: *const c_char, fields: *const c_char);
+
+static CALLBACK: RwLock<Option<LogCallback>> = RwLock::new(None);
+// Whether `log::set_logger` accepted the callback logger; only one logger can be installed per process
+static INSTALLED: OnceLock<bool> = OnceLock::new();
+
+struct CallbackLogger;
+
+static LOGGER: CallbackLogger = CallbackLogger;
+
+impl Log for CallbackLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        // Levels are filtered by `log::set_max_level`
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let Some(callback) = *CALLBACK.read().unwrap_or_else(|e| e.into_inner()) else {
+            return;
+        };
+        let mut fields = FieldCollector(serde_json::Map::new());
+        let _ = record.key_values().visit(&mut fields);
+
+        let c_string = |s: String| CString::new(s.replace('\0', " ")).unwrap_or_default();
+        let target = c_string(record.target().to_string());
+        let message = c_string(record.args().to_string());
+        let fields = c_string(serde_json::Value::Object(fields.0).to_string());
+        callback(record.level() as i32, target.as_ptr(), message.as_ptr(), fields.as_ptr());
+    }
+
+    fn flush(&self) {}
+}
+
+struct FieldCollector(serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for FieldCollector {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        let value = if let Some(n) = value.to_i64() {
+            n.into()
+        } else if let Some(b) = value.to_bool() {
+            b.into()
+        } else if let Some(x) = value.to_f64() {
+            x.into()
+        } else {
+            value.to_string().into()
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Routes log records up to `max_level` to `callback`, or silences the crate when `callback` is `None`.
+/// Returns false if the host already installed another `log` implementation, which then keeps receiving
+/// the records instead.
+pub(crate) fn set_callback(callback: Option<LogCallback>, max_level: LevelFilter) -> bool {
+    if !*INSTALLED.get_or_init(|| log::set_logger(&LOGGER).is_ok()) {
+        return false;
+    }
+    *CALLBACK.write().unwrap_or_else(|e| e.into_inner()) = callback;
+    log::set_max_level(if callback.is_some() { max_level } else { LevelFilter::Off });
+    true
+}
+
+/// Level filter for the `max_level` argument of the C ABI: 0 off, 1 error up to 5 trace.
+pub(crate) fn level_filter(level: i32) -> Option<LevelFilter> {
+    LevelFilter::iter().find(|filter| *filter as i32 == level)
+}
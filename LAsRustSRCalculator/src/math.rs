@@ -1,3 +1,26 @@
+//! Numerical helpers the SR calculator is built on. Skill curves are piecewise constant over sorted corner
+//! times `x`: value `f[i]` holds on `[x[i], x[i + 1])`.
+
+/// How [`smooth_on_corners`] turns the integral over a window into a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmoothMode {
+    // Integral times `scale`
+    Sum,
+    // Integral divided by the window length, 0 for an empty window
+    Average,
+}
+
+/// Index of the first element of `sorted` that is not below `value`, like Python's `bisect_left`.
+pub fn bisect_left(sorted: &[f64], value: f64) -> usize {
+    sorted.partition_point(|&x| x < value)
+}
+
+/// Index of the first element of `sorted` above `value`, like Python's `bisect_right`.
+pub fn bisect_right(sorted: &[f64], value: f64) -> usize {
+    sorted.partition_point(|&x| x <= value)
+}
+
+/// Integral of `f` from `x[0]` up to each corner.
 pub fn cumulative_sum(x: &[f64], f: &[f64]) -> Vec<f64> {
     let mut f_cum = vec![0.0; x.len()];
     for i in 1..x.len() {
@@ -6,6 +29,7 @@ pub fn cumulative_sum(x: &[f64], f: &[f64]) -> Vec<f64> {
     f_cum
 }
 
+/// Integral of `f` from `x[0]` up to `q`, with `f_cum` from [`cumulative_sum`]. Clamped to the corner range.
 pub fn query_cumsum(q: f64, x: &[f64], f_cum: &[f64], f: &[f64]) -> f64 {
     if q <= x[0] {
         return 0.0;
@@ -13,26 +37,29 @@ pub fn query_cumsum(q: f64, x: &[f64], f_cum: &[f64], f: &[f64]) -> f64 {
     if q >= x[x.len() - 1] {
         return f_cum[f_cum.len() - 1];
     }
-    let i = x.partition_point(|&val| val < q) - 1;
+    let i = bisect_left(x, q).saturating_sub(1);
     f_cum[i] + f[i] * (q - x[i])
 }
 
-pub fn smooth_on_corners(x: &[f64], f: &[f64], window: f64, scale: f64, mode: &str) -> Vec<f64> {
+/// Integral of `f` over `[x[i] - window, x[i] + window]` at each corner, clipped to the corner range
+/// and scaled or averaged according to `mode`.
+pub fn smooth_on_corners(x: &[f64], f: &[f64], window: f64, scale: f64, mode: SmoothMode) -> Vec<f64> {
     let f_cum = cumulative_sum(x, f);
-    let mut g = vec![0.0; f.len()];
-    for i in 0..x.len() {
-        let a = (x[i] - window).max(x[0]);
-        let b = (x[i] + window).min(x[x.len() - 1]);
-        let val = query_cumsum(b, x, &f_cum, f) - query_cumsum(a, x, &f_cum, f);
-        g[i] = if mode == "avg" {
-            if (b - a) > 0.0 { val / (b - a) } else { 0.0 }
-        } else {
-            scale * val
-        };
-    }
-    g
+    x.iter()
+        .map(|&s| {
+            let a = (s - window).max(x[0]);
+            let b = (s + window).min(x[x.len() - 1]);
+            let integral = query_cumsum(b, x, &f_cum, f) - query_cumsum(a, x, &f_cum, f);
+            match mode {
+                SmoothMode::Sum => integral * scale,
+                SmoothMode::Average if b > a => integral / (b - a),
+                SmoothMode::Average => 0.0,
+            }
+        })
+        .collect()
 }
 
+/// Linear interpolation of `(old_x, old_vals)` at `new_x`, holding the end values outside `old_x`.
 pub fn interp_values(new_x: &[f64], old_x: &[f64], old_vals: &[f64]) -> Vec<f64> {
     new_x.iter().map(|&x| {
         if x <= old_x[0] {
@@ -40,16 +67,78 @@ pub fn interp_values(new_x: &[f64], old_x: &[f64], old_vals: &[f64]) -> Vec<f64>
         } else if x >= old_x[old_x.len() - 1] {
             old_vals[old_vals.len() - 1]
         } else {
-            let i = old_x.partition_point(|&val| val < x) - 1;
-            let t = (x - old_x[i]) / (old_x[i + 1] - old_x[i]);
-            old_vals[i] * (1.0 - t) + old_vals[i + 1] * t
+            let idx = bisect_left(old_x, x) - 1;
+            let frac = (x - old_x[idx]) / (old_x[idx + 1] - old_x[idx]);
+            old_vals[idx] + frac * (old_vals[idx + 1] - old_vals[idx])
         }
     }).collect()
 }
 
+/// Piecewise-constant lookup: the value of the last corner of `old_x` before `x`, or the first value.
 pub fn step_interp(new_x: &[f64], old_x: &[f64], old_vals: &[f64]) -> Vec<f64> {
     new_x.iter().map(|&x| {
-        let i = old_x.partition_point(|&val| val < x).saturating_sub(1);
-        old_vals[i.min(old_vals.len() - 1)]
+        let idx = bisect_left(old_x, x).saturating_sub(1);
+        old_vals[idx.min(old_vals.len() - 1)]
     }).collect()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // Strictly increasing integer corners with a value on each
+    fn curve() -> impl Strategy<Value = (Vec<f64>, Vec<f64>)> {
+        prop::collection::vec((1..500i32, 0.0..100.0f64), 2..60).prop_map(|steps| {
+            let mut time = 0.0;
+            steps
+                .into_iter()
+                .map(|(gap, value)| {
+                    time += gap as f64;
+                    (time, value)
+                })
+                .unzip()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn bisect_matches_linear_scan((x, _) in curve(), value in -100.0..30_000.0f64) {
+            prop_assert_eq!(bisect_left(&x, value), x.iter().filter(|&&c| c < value).count());
+            prop_assert_eq!(bisect_right(&x, value), x.iter().filter(|&&c| c <= value).count());
+        }
+
+        #[test]
+        fn query_cumsum_integrates_the_steps((x, f) in curve(), t in 0.0..1.0f64) {
+            let f_cum = cumulative_sum(&x, &f);
+            let q = x[0] + t * (x[x.len() - 1] - x[0]);
+            let expected: f64 = (0..x.len() - 1).map(|i| f[i] * (q.min(x[i + 1]) - x[i]).max(0.0)).sum();
+            prop_assert!((query_cumsum(q, &x, &f_cum, &f) - expected).abs() <= 1e-9 * expected.max(1.0));
+            for i in 0..x.len() {
+                prop_assert_eq!(query_cumsum(x[i], &x, &f_cum, &f), f_cum[i]);
+            }
+        }
+
+        #[test]
+        fn smoothing_stays_within_the_values((x, f) in curve(), window in 1.0..2000.0f64) {
+            let (min, max) = f[..f.len() - 1].iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+            for (avg, sum) in smooth_on_corners(&x, &f, window, 0.0, SmoothMode::Average).into_iter().zip(smooth_on_corners(&x, &f, window, 1.0, SmoothMode::Sum)) {
+                prop_assert!(avg >= min - 1e-9 && avg <= max + 1e-9);
+                prop_assert!(sum >= -1e-9 && sum <= max * 2.0 * window + 1e-9);
+            }
+        }
+
+        #[test]
+        fn interpolation_hits_the_corners((x, f) in curve(), t in 0.0..1.0f64) {
+            for (value, expected) in interp_values(&x, &x, &f).into_iter().zip(&f) {
+                prop_assert!((value - expected).abs() <= 1e-12 * expected.max(1.0));
+            }
+            prop_assert_eq!(step_interp(&x, &x, &f)[1..].to_vec(), f[..f.len() - 1].to_vec());
+
+            let q = x[0] + t * (x[x.len() - 1] - x[0]);
+            let i = bisect_right(&x, q).saturating_sub(1).min(x.len() - 2);
+            let linear = interp_values(&[q], &x, &f)[0];
+            prop_assert!(linear >= f[i].min(f[i + 1]) - 1e-9 && linear <= f[i].max(f[i + 1]) + 1e-9);
+        }
+    }
+}
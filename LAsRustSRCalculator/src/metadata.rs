@@ -0,0 +1,145 @@
+/// Fields read from the `[General]` section.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GeneralInfo {
+    pub audio_filename: String,
+    pub audio_lead_in: i32,
+    // -1 when the beatmap does not define a preview point
+    pub preview_time: i32,
+    // 0 = osu!, 1 = taiko, 2 = catch, 3 = mania
+    pub mode: i32,
+}
+
+impl Default for GeneralInfo {
+    fn default() -> Self {
+        GeneralInfo {
+            audio_filename: String::new(),
+            audio_lead_in: 0,
+            preview_time: -1,
+            mode: 0,
+        }
+    }
+}
+
+impl GeneralInfo {
+    pub(crate) fn apply(&mut self, key: &str, value: &str) {
+        match key {
+            "AudioFilename" => self.audio_filename = value.to_string(),
+            "AudioLeadIn" => self.audio_lead_in = value.parse().unwrap_or(self.audio_lead_in),
+            "PreviewTime" => self.preview_time = value.parse().unwrap_or(self.preview_time),
+            "Mode" => self.mode = value.parse().unwrap_or(self.mode),
+            _ => {}
+        }
+    }
+}
+
+/// Fields read from the `[Metadata]` section.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BeatmapMetadata {
+    pub title: String,
+    pub title_unicode: String,
+    pub artist: String,
+    pub artist_unicode: String,
+    pub creator: String,
+    pub version: String,
+    pub source: String,
+    pub tags: Vec<String>,
+    // -1 when the beatmap has not been submitted
+    pub beatmap_id: i32,
+    pub beatmap_set_id: i32,
+}
+
+impl Default for BeatmapMetadata {
+    fn default() -> Self {
+        BeatmapMetadata {
+            title: String::new(),
+            title_unicode: String::new(),
+            artist: String::new(),
+            artist_unicode: String::new(),
+            creator: String::new(),
+            version: String::new(),
+            source: String::new(),
+            tags: Vec::new(),
+            beatmap_id: -1,
+            beatmap_set_id: -1,
+        }
+    }
+}
+
+impl BeatmapMetadata {
+    pub(crate) fn apply(&mut self, key: &str, value: &str) {
+        match key {
+            "Title" => self.title = value.to_string(),
+            "TitleUnicode" => self.title_unicode = value.to_string(),
+            "Artist" => self.artist = value.to_string(),
+            "ArtistUnicode" => self.artist_unicode = value.to_string(),
+            "Creator" => self.creator = value.to_string(),
+            "Version" => self.version = value.to_string(),
+            "Source" => self.source = value.to_string(),
+            "Tags" => self.tags = value.split_whitespace().map(|t| t.to_string()).collect(),
+            "BeatmapID" => self.beatmap_id = value.parse().unwrap_or(self.beatmap_id),
+            "BeatmapSetID" => self.beatmap_set_id = value.parse().unwrap_or(self.beatmap_set_id),
+            _ => {}
+        }
+    }
+}
+
+/// Fields read from the `[Difficulty]` section. Missing values take the osu! defaults.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DifficultyInfo {
+    pub hp_drain_rate: f64,
+    pub circle_size: f64,
+    pub overall_difficulty: f64,
+    // Old beatmaps without ApproachRate use OverallDifficulty
+    pub approach_rate: f64,
+    pub slider_multiplier: f64,
+    pub slider_tick_rate: f64,
+    #[serde(skip)]
+    has_approach_rate: bool,
+}
+
+impl Default for DifficultyInfo {
+    fn default() -> Self {
+        DifficultyInfo {
+            hp_drain_rate: 5.0,
+            circle_size: 5.0,
+            overall_difficulty: 5.0,
+            approach_rate: 5.0,
+            slider_multiplier: 1.4,
+            slider_tick_rate: 1.0,
+            has_approach_rate: false,
+        }
+    }
+}
+
+impl DifficultyInfo {
+    pub(crate) fn apply(&mut self, key: &str, value: &str) {
+        let Ok(parsed) = value.parse::<f64>() else {
+            return;
+        };
+        match key {
+            "HPDrainRate" => self.hp_drain_rate = parsed,
+            "CircleSize" => self.circle_size = parsed,
+            "OverallDifficulty" => {
+                self.overall_difficulty = parsed;
+                if !self.has_approach_rate {
+                    self.approach_rate = parsed;
+                }
+            }
+            "ApproachRate" => {
+                self.approach_rate = parsed;
+                self.has_approach_rate = true;
+            }
+            "SliderMultiplier" => self.slider_multiplier = parsed,
+            "SliderTickRate" => self.slider_tick_rate = parsed,
+            _ => {}
+        }
+    }
+}
+
+/// Reads `N` from the `osu file format vN` header line.
+pub(crate) fn parse_format_version(line: &str) -> Option<i32> {
+    line.trim_start_matches('\u{feff}')
+        .trim()
+        .strip_prefix("osu file format v")
+        .and_then(|v| v.trim().parse().ok())
+}
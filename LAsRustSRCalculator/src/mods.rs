@@ -0,0 +1,177 @@
+use crate::beatmap::ManiaBeatmap;
+use crate::convert::LegacyRandom;
+use crate::note::Note;
+
+/// Mania mods that change which notes are played, applied to a [`ManiaBeatmap`] before calculation.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum NoteMod {
+    /// Column `k` becomes `K - 1 - k`.
+    Mirror,
+    /// Shuffles whole columns. The same seed always gives the same layout.
+    Random { seed: i32 },
+    /// Every note holds until the next note in its column, leaving `gap` ms to release
+    /// (at least half of the distance). The last note of each column is unchanged.
+    FullLn { gap: i32 },
+    /// Holds become gaps and gaps become holds, as in osu!lazer. `gap` is the release
+    /// gap in ms, usually a quarter beat.
+    Invert { gap: i32 },
+    /// Holds become single notes.
+    NoLn,
+    /// Adds a second stage with a copy of every note, doubling the key count.
+    DualStages,
+}
+
+impl NoteMod {
+    pub fn apply(&self, beatmap: &mut ManiaBeatmap) {
+        let k = beatmap.key_count;
+        match *self {
+            NoteMod::Mirror => {
+                for note in &mut beatmap.notes {
+                    note.k = k - 1 - note.k;
+                }
+            }
+            NoteMod::Random { seed } => {
+                let mut random = LegacyRandom::new(seed);
+                let mut columns: Vec<i32> = (0..k).collect();
+                for i in (1..columns.len()).rev() {
+                    let j = random.next_range(0, i as i32 + 1) as usize;
+                    columns.swap(i, j);
+                }
+                for note in &mut beatmap.notes {
+                    if let Some(&column) = columns.get(note.k as usize) {
+                        note.k = column;
+                    }
+                }
+            }
+            NoteMod::FullLn { gap } => {
+                beatmap.notes = Self::by_column(&beatmap.notes, k, |column| {
+                    let mut notes = column.to_vec();
+                    for i in 0..notes.len().saturating_sub(1) {
+                        notes[i].t = Self::release_time(notes[i].h, notes[i + 1].h, gap);
+                    }
+                    notes
+                });
+            }
+            NoteMod::Invert { gap } => {
+                beatmap.notes = Self::by_column(&beatmap.notes, k, |column| {
+                    // Every head and tail starts a new hold that ends before the next one
+                    let mut times: Vec<i32> = column.iter().flat_map(|n| if n.t >= 0 { vec![n.h, n.t] } else { vec![n.h] }).collect();
+                    times.sort_unstable();
+                    times.dedup();
+                    let k = column[0].k;
+                    let mut notes: Vec<Note> = times.windows(2).map(|w| Note::new(k, w[0], Self::release_time(w[0], w[1], gap))).collect();
+                    notes.push(Note::new(k, *times.last().unwrap(), -1));
+                    notes
+                });
+            }
+            NoteMod::NoLn => {
+                for note in &mut beatmap.notes {
+                    note.t = -1;
+                }
+            }
+            NoteMod::DualStages => {
+                let second_stage: Vec<Note> = beatmap.notes.iter().map(|n| Note::new(n.k + k, n.h, n.t)).collect();
+                beatmap.notes.extend(second_stage);
+                beatmap.key_count = k * 2;
+            }
+        }
+    }
+
+    // Splits notes into columns, transforms each non-empty column and joins them back in (h, k) order
+    fn by_column(notes: &[Note], k: i32, transform: impl Fn(&[Note]) -> Vec<Note>) -> Vec<Note> {
+        let mut columns: Vec<Vec<Note>> = vec![vec![]; k.max(0) as usize];
+        let mut others = vec![];
+        for &note in notes {
+            match columns.get_mut(note.k as usize) {
+                Some(column) => column.push(note),
+                None => others.push(note),
+            }
+        }
+
+        let mut result = others;
+        for column in &mut columns {
+            if column.is_empty() {
+                continue;
+            }
+            column.sort();
+            result.extend(transform(column));
+        }
+        result.sort();
+        result
+    }
+
+    fn release_time(start: i32, next: i32, gap: i32) -> i32 {
+        let distance = next - start;
+        start + (distance - gap).max(distance / 2)
+    }
+}
+
+impl ManiaBeatmap {
+    /// A copy of this beatmap with `mods` applied in order, e.g. for exporting practice maps.
+    pub fn with_mods(&self, mods: &[NoteMod]) -> ManiaBeatmap {
+        let mut beatmap = self.clone();
+        for note_mod in mods {
+            note_mod.apply(&mut beatmap);
+        }
+        beatmap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beatmap() -> ManiaBeatmap {
+        ManiaBeatmap::new(4, 8.0, vec![
+            Note::new(0, 0, -1),
+            Note::new(1, 0, 300),
+            Note::new(0, 500, -1),
+            Note::new(3, 500, -1),
+            Note::new(0, 1000, 1200),
+        ])
+    }
+
+    #[test]
+    fn test_mirror_and_no_ln() {
+        let mirrored = beatmap().with_mods(&[NoteMod::Mirror]);
+        assert_eq!(mirrored.notes.iter().map(|n| n.k).collect::<Vec<_>>(), vec![3, 2, 3, 0, 3]);
+        assert_eq!(mirrored.with_mods(&[NoteMod::Mirror]), beatmap());
+
+        assert!(beatmap().with_mods(&[NoteMod::NoLn]).notes.iter().all(|n| n.t == -1));
+    }
+
+    #[test]
+    fn test_random_is_a_seeded_column_permutation() {
+        let a = beatmap().with_mods(&[NoteMod::Random { seed: 42 }]);
+        assert_eq!(a, beatmap().with_mods(&[NoteMod::Random { seed: 42 }]));
+
+        // Notes sharing a column before still share one afterwards
+        let columns: Vec<i32> = a.notes.iter().map(|n| n.k).collect();
+        assert_eq!(columns[0], columns[2]);
+        assert_eq!(columns[0], columns[4]);
+        let mut distinct = vec![columns[0], columns[1], columns[3]];
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 3);
+    }
+
+    #[test]
+    fn test_full_ln_and_invert() {
+        let full_ln = beatmap().with_mods(&[NoteMod::FullLn { gap: 100 }]);
+        let column_0: Vec<Note> = full_ln.notes.iter().filter(|n| n.k == 0).cloned().collect();
+        assert_eq!(column_0, vec![Note::new(0, 0, 400), Note::new(0, 500, 900), Note::new(0, 1000, 1200)]);
+
+        // Heads and tails of column 0 are at 0, 500, 1000, 1200
+        let inverted = beatmap().with_mods(&[NoteMod::Invert { gap: 125 }]);
+        let column_0: Vec<Note> = inverted.notes.iter().filter(|n| n.k == 0).cloned().collect();
+        assert_eq!(column_0, vec![Note::new(0, 0, 375), Note::new(0, 500, 875), Note::new(0, 1000, 1100), Note::new(0, 1200, -1)]);
+    }
+
+    #[test]
+    fn test_dual_stages() {
+        let dual = beatmap().with_mods(&[NoteMod::DualStages]);
+        assert_eq!(dual.key_count, 8);
+        assert_eq!(dual.notes.len(), 10);
+        assert!(dual.notes.contains(&Note::new(5, 0, 300)));
+    }
+}
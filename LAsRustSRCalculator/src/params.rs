@@ -0,0 +1,125 @@
+use crate::error::SrError;
+
+/// Tunable constants of the SR algorithm. `SrParams::default()` reproduces the standard SR;
+/// fields missing from a JSON file keep their default.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SrParams {
+    // Half-width in ms of the windows Jbar, Xbar, Pbar and Rbar are smoothed over
+    pub smoothing_window: f64,
+    // Half-width in ms of the Abar smoothing window
+    pub unevenness_window: f64,
+    // Half-width in ms of the window notes are counted in for C
+    pub density_window: f64,
+    // A column counts as in use this many ms before and after each note (Ks, Xbar, Abar)
+    pub key_usage_span: f64,
+    // Falloff in ms of the per-column usage the anchor is computed from
+    pub anchor_span: f64,
+    // Cumulative weights averaged for the top percentile and the 93% skill summaries
+    pub top_percentiles: Vec<f64>,
+    // Cumulative weights averaged for the middle percentile
+    pub middle_percentiles: Vec<f64>,
+    pub top_weight: f64,
+    pub top_scale: f64,
+    pub middle_weight: f64,
+    pub middle_scale: f64,
+    // Weight of the power mean of all difficulties
+    pub mean_weight: f64,
+    // SR is multiplied by notes / (notes + note_count_damping), so short maps rate lower
+    pub note_count_damping: f64,
+    // SR above `rescale_knee` grows `1 / rescale_divisor` times as fast
+    pub rescale_knee: f64,
+    pub rescale_divisor: f64,
+    pub final_scale: f64,
+}
+
+impl Default for SrParams {
+    fn default() -> Self {
+        SrParams {
+            smoothing_window: 500.0,
+            unevenness_window: 250.0,
+            density_window: 500.0,
+            key_usage_span: 150.0,
+            anchor_span: 400.0,
+            top_percentiles: vec![0.945, 0.935, 0.925, 0.915],
+            middle_percentiles: vec![0.845, 0.835, 0.825, 0.815],
+            top_weight: 0.25,
+            top_scale: 0.88,
+            middle_weight: 0.2,
+            middle_scale: 0.94,
+            mean_weight: 0.55,
+            note_count_damping: 60.0,
+            rescale_knee: 9.0,
+            rescale_divisor: 1.2,
+            final_scale: 0.975,
+        }
+    }
+}
+
+impl SrParams {
+    pub fn from_json(json: &str) -> Result<Self, SrError> {
+        let params: SrParams = serde_json::from_str(json).map_err(|e| SrError::InvalidConfig(format!("SR params: {}", e)))?;
+        params.validate()?;
+        Ok(params)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), SrError> {
+        let positive = [
+            ("smoothing_window", self.smoothing_window),
+            ("unevenness_window", self.unevenness_window),
+            ("density_window", self.density_window),
+            ("key_usage_span", self.key_usage_span),
+            ("anchor_span", self.anchor_span),
+            ("rescale_divisor", self.rescale_divisor),
+        ];
+        for (name, value) in positive {
+            if !value.is_finite() || value <= 0.0 {
+                return Err(SrError::InvalidConfig(format!("{} must be positive, got {}", name, value)));
+            }
+        }
+        let finite = [
+            ("top_weight", self.top_weight),
+            ("top_scale", self.top_scale),
+            ("middle_weight", self.middle_weight),
+            ("middle_scale", self.middle_scale),
+            ("mean_weight", self.mean_weight),
+            ("note_count_damping", self.note_count_damping),
+            ("rescale_knee", self.rescale_knee),
+            ("final_scale", self.final_scale),
+        ];
+        for (name, value) in finite {
+            if !value.is_finite() || value < 0.0 {
+                return Err(SrError::InvalidConfig(format!("{} must be a finite non-negative number, got {}", name, value)));
+            }
+        }
+        for (name, percentiles) in [("top_percentiles", &self.top_percentiles), ("middle_percentiles", &self.middle_percentiles)] {
+            if percentiles.is_empty() || percentiles.iter().any(|p| !(0.0..=1.0).contains(p)) {
+                return Err(SrError::InvalidConfig(format!("{} must be a non-empty list of values in [0, 1]", name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip_and_defaults() {
+        let params = SrParams { note_count_damping: 30.0, top_percentiles: vec![0.95], ..Default::default() };
+        assert_eq!(SrParams::from_json(&params.to_json()).unwrap(), params);
+
+        // Missing fields keep their defaults
+        let partial = SrParams::from_json(r#"{ "final_scale": 1.0 }"#).unwrap();
+        assert_eq!(partial, SrParams { final_scale: 1.0, ..Default::default() });
+
+        assert!(matches!(SrParams::from_json(r#"{ "smoothing_window": 0 }"#), Err(SrError::InvalidConfig(_))));
+        assert!(matches!(SrParams::from_json(r#"{ "middle_percentiles": [] }"#), Err(SrError::InvalidConfig(_))));
+        assert!(matches!(SrParams::from_json("not json"), Err(SrError::InvalidConfig(_))));
+    }
+}
@@ -1,87 +1,260 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-use crate::debug_log;
+use crate::convert::{StandardConverter, StandardObject};
+use crate::error::SrError;
+use crate::hit_object::{HitObject, HitObjectKind, HitObjectType, HitSample, HitSound};
+use crate::metadata::{parse_format_version, BeatmapMetadata, DifficultyInfo, GeneralInfo};
+use crate::timing::{ControlPointInfo, TimingPoint};
 
 #[derive(Debug)]
 pub struct ParsedData {
     pub column_count: i32,
-    pub columns: Vec<i32>,
-    pub note_starts: Vec<i32>,
-    pub note_ends: Vec<i32>,
-    pub note_types: Vec<i32>,
+    pub hit_objects: Vec<HitObject>,
     pub od: f64,
+    pub control_points: ControlPointInfo,
+    // -1 when the file has no `osu file format vN` header
+    pub format_version: i32,
+    pub general: GeneralInfo,
+    pub metadata: BeatmapMetadata,
+    pub difficulty: DifficultyInfo,
+    // True when the notes were converted from an osu!standard beatmap
+    pub converted: bool,
+    pub warnings: Vec<ParseWarning>,
+}
+
+impl ParsedData {
+    pub fn bpm_at(&self, time: f64) -> Option<f64> {
+        self.control_points.bpm_at(time)
+    }
+
+    pub fn beat_length_at(&self, time: f64) -> Option<f64> {
+        self.control_points.beat_length_at(time)
+    }
+
+    pub fn slider_velocity_at(&self, time: f64) -> f64 {
+        self.control_points.slider_velocity_at(time)
+    }
+
+    /// The BPM active for the longest time before the last note ends.
+    pub fn dominant_bpm(&self) -> Option<f64> {
+        let end_time = self.hit_objects.iter().map(|o| o.start_time.max(o.end_time)).max().unwrap_or(0);
+        self.control_points.dominant_bpm(end_time as f64)
+    }
 }
 
 pub struct OsuParser {
     file_path: String,
     od: f64,
     column_count: i32,
-    columns: Vec<i32>,
-    note_starts: Vec<i32>,
-    note_ends: Vec<i32>,
-    note_types: Vec<i32>,
+    hit_objects: Vec<HitObject>,
+    control_points: ControlPointInfo,
+    format_version: i32,
+    general: GeneralInfo,
+    metadata: BeatmapMetadata,
+    difficulty: DifficultyInfo,
+    options: ParserOptions,
+    standard_objects: Vec<StandardObject>,
+    // Total length of the `[Events]` breaks, used by the osu!standard conversion
+    break_time: f64,
+    converted: bool,
+    warnings: Vec<ParseWarning>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParserOptions {
+    /// Convert osu!standard (Mode: 0) beatmaps to mania instead of rejecting them, with osu!stable's
+    /// pattern generators (see `StandardConverter`). Off by default, so that only real mania maps are rated.
+    pub convert_standard: bool,
+    /// Fail with `SrError::Parse` on the first problem instead of collecting warnings.
+    pub strict: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ParseWarningKind {
+    /// Too few fields or unreadable numbers; the object is skipped.
+    MalformedHitObject,
+    /// Unreadable timing point; the line is skipped.
+    MalformedTimingPoint,
+    /// x is outside 0-511; the note is clamped to the nearest column.
+    XOutOfRange,
+    /// A hold note ends before it starts.
+    HoldEndBeforeStart,
+    /// Type bits that do not describe a mania note.
+    UnknownTypeBits,
+}
+
+/// A problem found on a specific line while parsing. `line` is 1-based.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ParseWarning {
+    pub line: usize,
+    pub kind: ParseWarningKind,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    None,
+    General,
+    Metadata,
+    Difficulty,
+    Events,
+    TimingPoints,
+    HitObjects,
+    Other,
+}
+
+impl Section {
+    fn from_header(header: &str) -> Section {
+        match header {
+            "[General]" => Section::General,
+            "[Metadata]" => Section::Metadata,
+            "[Difficulty]" => Section::Difficulty,
+            "[Events]" => Section::Events,
+            "[TimingPoints]" => Section::TimingPoints,
+            "[HitObjects]" => Section::HitObjects,
+            _ => Section::Other,
+        }
+    }
 }
 
 impl OsuParser {
     pub fn new(file_path: &str) -> Self {
+        Self::with_options(file_path, ParserOptions::default())
+    }
+
+    pub fn with_options(file_path: &str, options: ParserOptions) -> Self {
         OsuParser {
             file_path: file_path.to_string(),
             od: -1.0,
             column_count: -1,
-            columns: Vec::new(),
-            note_starts: Vec::new(),
-            note_ends: Vec::new(),
-            note_types: Vec::new(),
+            hit_objects: Vec::new(),
+            control_points: ControlPointInfo::default(),
+            format_version: -1,
+            general: GeneralInfo::default(),
+            metadata: BeatmapMetadata::default(),
+            difficulty: DifficultyInfo::default(),
+            options,
+            standard_objects: Vec::new(),
+            break_time: 0.0,
+            converted: false,
+            warnings: Vec::new(),
         }
     }
 
-    pub fn process(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Parses .osu content that is already in memory.
+    pub fn parse_str(content: &str) -> Result<ParsedData, SrError> {
+        Self::parse_reader(content.as_bytes())
+    }
+
+    /// Parses raw .osu bytes, e.g. an uploaded file. The content must be UTF-8.
+    pub fn parse_bytes(content: &[u8]) -> Result<ParsedData, SrError> {
+        Self::parse_reader(content)
+    }
+
+    /// Parses .osu content from any buffered reader.
+    pub fn parse_reader<R: BufRead>(reader: R) -> Result<ParsedData, SrError> {
+        let mut parser = OsuParser::new("");
+        parser.process_reader(reader)?;
+        Ok(parser.get_parsed_data())
+    }
+
+    pub fn process(&mut self) -> Result<(), SrError> {
         let file = File::open(&self.file_path)?;
-        let reader = BufReader::new(file);
+        self.process_reader(BufReader::new(file))
+    }
 
-        let mut lines = reader.lines();
-        let mut in_hit_objects = false;
-        let mut _line_count = 0;
+    pub fn process_reader<R: BufRead>(&mut self, reader: R) -> Result<(), SrError> {
+        let mut section = Section::None;
+        let mut line_number = 0;
 
-        while let Some(line) = lines.next() {
-            let line = line?;
-            _line_count += 1;
+        for line in reader.lines() {
+            line_number += 1;
+            let line = line.map_err(|e| match e.kind() {
+                std::io::ErrorKind::InvalidData => SrError::Parse { line: line_number, reason: "invalid UTF-8".to_string() },
+                _ => SrError::Io(e),
+            })?;
+
+            if line_number == 1 && let Some(version) = parse_format_version(&line) {
+                self.format_version = version;
+                continue;
+            }
 
             // Check for section headers
-            if line.starts_with("[HitObjects]") {
-                in_hit_objects = true;
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                section = Section::from_header(trimmed);
                 continue;
             }
-            
-            if in_hit_objects {
-                if line.trim().is_empty() {
-                    continue;
-                }
-                if line.starts_with('[') {
-                    break; // Next section
-                }
-                self.parse_hit_object(&line, self.column_count);
-            } else {
-                // Read metadata and difficulty settings
-                self.read_metadata(&line);
-                let temp_cc = self.read_column_count(&line);
-                if temp_cc != -1 {
-                    self.column_count = temp_cc;
-                }
-                let temp_od = self.read_overall_difficulty(&line);
-                if temp_od != -1.0 {
-                    self.od = temp_od;
+            if trimmed.is_empty() || trimmed.starts_with("//") {
+                continue;
+            }
+
+            match section {
+                Section::General => self.read_key_value(&line, |p, k, v| p.general.apply(k, v)),
+                Section::Metadata => self.read_key_value(&line, |p, k, v| p.metadata.apply(k, v)),
+                Section::Difficulty => {
+                    self.read_key_value(&line, |p, k, v| p.difficulty.apply(k, v));
+                    let temp_cc = self.read_column_count(&line);
+                    if temp_cc != -1 {
+                        self.column_count = temp_cc;
+                    }
+                    let temp_od = self.read_overall_difficulty(&line);
+                    if temp_od != -1.0 {
+                        self.od = temp_od;
+                    }
                 }
+                Section::Events => self.parse_event(&line),
+                Section::TimingPoints => self.parse_timing_point(&line, line_number)?,
+                Section::HitObjects => match self.general.mode {
+                    3 => self.parse_hit_object(&line, self.column_count, line_number)?,
+                    0 => self.parse_standard_object(&line, line_number)?,
+                    _ => {}
+                },
+                Section::None | Section::Other => {}
             }
         }
-        
-        debug_log!("Total lines processed: {}, Hit objects parsed: {}", _line_count, self.columns.len());
+
+        self.apply_mode()?;
+
+        log::trace!(lines = line_number, hit_objects = self.hit_objects.len(); "parsed beatmap");
         Ok(())
     }
 
-    fn read_metadata(&self, _line: &str) {
-        // Skip metadata for now
+    fn apply_mode(&mut self) -> Result<(), SrError> {
+        match self.general.mode {
+            3 => Ok(()),
+            0 if self.options.convert_standard => {
+                let (column_count, notes) =
+                    StandardConverter::convert(&self.standard_objects, &self.difficulty, &self.control_points, self.break_time);
+                self.column_count = column_count;
+                let standard_objects = std::mem::take(&mut self.standard_objects);
+                for note in notes {
+                    let source = &standard_objects[note.source];
+                    self.hit_objects.push(HitObject {
+                        x: source.x,
+                        y: source.y,
+                        column: note.column,
+                        start_time: note.start,
+                        end_time: note.end,
+                        object_type: HitObjectType::from_bits(note.note_type),
+                        hit_sound: HitSound(source.hit_sound),
+                        sample: source.sample.clone(),
+                        line: source.line,
+                        raw: source.raw.clone(),
+                    });
+                }
+                self.converted = true;
+                Ok(())
+            }
+            mode => Err(SrError::UnsupportedMode(mode)),
+        }
+    }
+
+    fn read_key_value(&mut self, line: &str, apply: impl FnOnce(&mut Self, &str, &str)) {
+        if let Some((key, value)) = line.split_once(':') {
+            apply(self, key.trim(), value.trim());
+        }
     }
 
     fn read_overall_difficulty(&self, line: &str) -> f64 {
@@ -105,38 +278,238 @@ impl OsuParser {
         -1
     }
 
+    /// Adds up break periods (`2,start,end` or `Break,start,end`); other events do not affect the notes.
+    fn parse_event(&mut self, event_line: &str) {
+        let params: Vec<&str> = event_line.split(',').map(|p| p.trim()).collect();
+        if params.len() < 3 || !matches!(params[0], "2" | "Break") {
+            return;
+        }
+        if let (Ok(start), Ok(end)) = (params[1].parse::<f64>(), params[2].parse::<f64>()) {
+            self.break_time += (end - start).max(0.0);
+        }
+    }
+
+    fn parse_timing_point(&mut self, timing_line: &str, line: usize) -> Result<(), SrError> {
+        match TimingPoint::parse(timing_line) {
+            Some(point) => {
+                self.control_points.add(point);
+                Ok(())
+            }
+            None => self.warn(line, ParseWarningKind::MalformedTimingPoint, format!("invalid timing point \"{}\"", timing_line.trim())),
+        }
+    }
 
+    fn parse_standard_object(&mut self, object_line: &str, line: usize) -> Result<(), SrError> {
+        match StandardObject::parse(object_line) {
+            Some(mut object) => {
+                object.line = line;
+                self.standard_objects.push(object);
+                Ok(())
+            }
+            None => self.warn(line, ParseWarningKind::MalformedHitObject, format!("invalid hit object \"{}\"", object_line.trim())),
+        }
+    }
 
-    fn parse_hit_object(&mut self, object_line: &str, column_count: i32) {
-        let params: Vec<&str> = object_line.split(',').collect();
-        if params.len() < 6 {
-            return;
+    fn parse_hit_object(&mut self, object_line: &str, column_count: i32, line: usize) -> Result<(), SrError> {
+        let params: Vec<&str> = object_line.split(',').map(|p| p.trim()).collect();
+        // The hit sample field is optional, so single notes may only have five fields
+        if params.len() < 5 {
+            return self.warn(line, ParseWarningKind::MalformedHitObject, format!("expected at least 5 fields, found {}", params.len()));
+        }
+
+        let (Ok(x), Ok(note_start), Ok(note_type)) = (params[0].parse::<f64>(), params[2].parse::<f64>(), params[3].parse::<i32>()) else {
+            return self.warn(line, ParseWarningKind::MalformedHitObject, format!("invalid x, time or type in \"{}\"", object_line.trim()));
+        };
+        let Some(note_start) = Self::parse_time(note_start) else {
+            return self.warn(line, ParseWarningKind::MalformedHitObject, format!("time \"{}\" is not finite or outside i32", params[2]));
+        };
+
+        let decoded_type = HitObjectType::from_bits(note_type);
+        if !HitObjectType::is_valid_bits(note_type) || !matches!(decoded_type.kind, HitObjectKind::Circle | HitObjectKind::Hold) {
+            self.warn(line, ParseWarningKind::UnknownTypeBits, format!("unexpected type {} for a mania note", note_type))?;
+        }
+
+        // Hold notes prefix the hit sample with their end time: `endTime:normalSet:...`
+        let is_hold = decoded_type.is_hold();
+        let extras: Vec<&str> = params.get(5).map(|p| p.split(':').collect()).unwrap_or_default();
+        let (note_end, sample_fields) = if is_hold {
+            let Some(end) = extras.first() else {
+                return self.warn(line, ParseWarningKind::MalformedHitObject, "hold note without end time".to_string());
+            };
+            match end.trim().parse::<f64>().ok().and_then(Self::parse_time) {
+                Some(end) => (end, &extras[1..]),
+                None => return self.warn(line, ParseWarningKind::MalformedHitObject, format!("invalid hold end time \"{}\" (not a finite i32)", end)),
+            }
+        } else {
+            (note_start, &extras[..])
+        };
+        if is_hold && note_end < note_start {
+            self.warn(line, ParseWarningKind::HoldEndBeforeStart, format!("hold ends at {} before it starts at {}", note_end, note_start))?;
         }
 
-        let x: f64 = params[0].parse().unwrap_or(0.0);
+        if !(0.0..512.0).contains(&x) {
+            self.warn(line, ParseWarningKind::XOutOfRange, format!("x = {} is outside 0-511", x))?;
+        }
         let column = (x * column_count as f64 / 512.0).floor() as i32;
         let column = column.max(0).min(column_count - 1);
-        self.columns.push(column);
 
-        let note_start: i32 = params[2].parse().unwrap_or(0);
-        self.note_starts.push(note_start);
+        self.hit_objects.push(HitObject {
+            x,
+            y: params[1].parse().unwrap_or(0.0),
+            column,
+            start_time: note_start,
+            end_time: note_end,
+            object_type: decoded_type,
+            hit_sound: HitSound(params[4].parse().unwrap_or(0)),
+            sample: HitSample::parse(sample_fields),
+            line,
+            raw: object_line.to_string(),
+        });
+        Ok(())
+    }
 
-        let note_type: i32 = params[3].parse().unwrap_or(0);
-        self.note_types.push(note_type);
+    // osu! writes times as integers but older maps may carry fractions, which are truncated. NaN,
+    // infinities and times past i32 have no sensible note time, so they are rejected rather than cast
+    fn parse_time(time: f64) -> Option<i32> {
+        let time = time.trunc();
+        (time >= i32::MIN as f64 && time <= i32::MAX as f64).then_some(time as i32)
+    }
 
-        let last_param_chunk: Vec<&str> = params[5].split(':').collect();
-        let note_end: i32 = last_param_chunk[0].parse().unwrap_or(0);
-        self.note_ends.push(note_end);
+    /// Records a problem on `line`. In strict mode the problem is returned as an error instead.
+    fn warn(&mut self, line: usize, kind: ParseWarningKind, message: String) -> Result<(), SrError> {
+        if self.options.strict {
+            return Err(SrError::Parse { line, reason: message });
+        }
+        log::debug!(line, kind:?; "{}", message);
+        self.warnings.push(ParseWarning { line, kind, message });
+        Ok(())
     }
 
     pub fn get_parsed_data(&self) -> ParsedData {
         ParsedData {
             column_count: self.column_count,
-            columns: self.columns.clone(),
-            note_starts: self.note_starts.clone(),
-            note_ends: self.note_ends.clone(),
-            note_types: self.note_types.clone(),
+            hit_objects: self.hit_objects.clone(),
             od: self.od,
+            control_points: self.control_points.clone(),
+            format_version: self.format_version,
+            general: self.general.clone(),
+            metadata: self.metadata.clone(),
+            difficulty: self.difficulty.clone(),
+            converted: self.converted,
+            warnings: self.warnings.clone(),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "osu file format v14
+
+[General]
+Mode: 3
+
+[Difficulty]
+CircleSize:4
+OverallDifficulty:8
+
+[TimingPoints]
+0,500,4,2,0,60,1,0
+oops
+
+[HitObjects]
+64,192,1000,1,0,0:0:0:0:
+64,192
+192,192,abc,1,0,0:0:0:0:
+600,192,1500,1,0,0:0:0:0:
+320,192,2000,128,0,1800:0:0:0:0:
+448,192,2500,1024,0,0:0:0:0:
+448,192,3000,1,0
+";
+
+    #[test]
+    fn test_collects_warnings_with_line_numbers() {
+        let data = OsuParser::parse_str(MAP).unwrap();
+
+        let found: Vec<(usize, ParseWarningKind)> = data.warnings.iter().map(|w| (w.line, w.kind)).collect();
+        assert_eq!(found, vec![
+            (12, ParseWarningKind::MalformedTimingPoint),
+            (16, ParseWarningKind::MalformedHitObject),
+            (17, ParseWarningKind::MalformedHitObject),
+            (18, ParseWarningKind::XOutOfRange),
+            (19, ParseWarningKind::HoldEndBeforeStart),
+            (20, ParseWarningKind::UnknownTypeBits),
+        ]);
+
+        // Malformed objects are skipped, out-of-range x is clamped, five-field notes are kept
+        let starts: Vec<i32> = data.hit_objects.iter().map(|o| o.start_time).collect();
+        assert_eq!(starts, vec![1000, 1500, 2000, 2500, 3000]);
+        assert_eq!(data.hit_objects[1].column, 3);
+        assert_eq!(data.hit_objects[4].line, 21);
+        assert_eq!(data.hit_objects[4].raw, "448,192,3000,1,0");
+    }
+
+    #[test]
+    fn test_strict_mode_fails_on_first_problem() {
+        let mut parser = OsuParser::with_options("", ParserOptions { strict: true, ..Default::default() });
+        match parser.process_reader(MAP.as_bytes()) {
+            Err(SrError::Parse { line, .. }) => assert_eq!(line, 12),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        // Everything up to and including the first, valid hit object
+        let clean = MAP.replace("oops\n", "").lines().take(14).collect::<Vec<_>>().join("\n");
+        let mut parser = OsuParser::with_options("", ParserOptions { strict: true, ..Default::default() });
+        assert!(parser.process_reader(clean.as_bytes()).is_ok());
+        assert!(parser.get_parsed_data().warnings.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_times_outside_i32() {
+        let head = MAP.split("[HitObjects]").next().unwrap().to_string() + "[HitObjects]\n";
+        let lines = [
+            "64,192,NaN,1,0,0:0:0:0:",
+            "64,192,inf,1,0,0:0:0:0:",
+            "64,192,1e12,1,0,0:0:0:0:",
+            "64,192,-3000000000,1,0,0:0:0:0:",
+            "64,192,1000,128,0,NaN:0:0:0:0:",
+            "64,192,1000,128,0,2147483648:0:0:0:0:",
+        ];
+        for object in lines {
+            let data = OsuParser::parse_str(&(head.clone() + object + "\n")).unwrap();
+            assert!(data.hit_objects.is_empty(), "{}", object);
+            assert_eq!(data.warnings.last().map(|w| w.kind), Some(ParseWarningKind::MalformedHitObject), "{}", object);
+
+            let mut parser = OsuParser::with_options("", ParserOptions { strict: true, ..Default::default() });
+            let content = head.replace("oops\n", "") + object + "\n";
+            assert!(matches!(parser.process_reader(content.as_bytes()), Err(SrError::Parse { .. })), "{}", object);
+        }
+
+        // Fractional times are still truncated
+        let data = OsuParser::parse_str(&(head + "64,192,1000.7,128,0,2147483647.5:0:0:0:0:\n")).unwrap();
+        assert_eq!((data.hit_objects[0].start_time, data.hit_objects[0].end_time), (1000, i32::MAX));
+    }
+
+    #[test]
+    fn test_keeps_hitsounds_and_samples() {
+        let content = MAP.split("[HitObjects]").next().unwrap().to_string()
+            + "[HitObjects]\n64,192,1000,1,10,2:3:1:70:kick.wav\n320,192,2000,128,2,2400:1:0:0:50:hold.ogg\n";
+        let data = OsuParser::parse_str(&content).unwrap();
+
+        let note = &data.hit_objects[0];
+        assert!(note.hit_sound.whistle() && note.hit_sound.clap());
+        assert_eq!(note.sample.normal_set, 2);
+        assert_eq!(note.sample.addition_set, 3);
+        assert_eq!(note.sample.volume, 70);
+        assert_eq!(note.sample.filename, "kick.wav");
+        assert_eq!(note.end_time, note.start_time);
+
+        let hold = &data.hit_objects[1];
+        assert!(hold.is_hold());
+        assert_eq!(hold.duration(), 400);
+        assert_eq!(hold.sample.normal_set, 1);
+        assert_eq!(hold.sample.volume, 50);
+        assert_eq!(hold.sample.filename, "hold.ogg");
+        assert_eq!(hold.raw, "320,192,2000,128,2,2400:1:0:0:50:hold.ogg");
+    }
+}
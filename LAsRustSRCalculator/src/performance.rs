@@ -0,0 +1,189 @@
+use crate::attributes::DifficultyAttributes;
+use crate::config::LegacyMods;
+
+/// Judgement counts of a mania score.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[repr(C)]
+pub struct Judgements {
+    // MAX / rainbow 300
+    pub perfect: u32,
+    pub great: u32,
+    pub good: u32,
+    pub ok: u32,
+    pub meh: u32,
+    pub miss: u32,
+}
+
+impl Judgements {
+    pub fn total(&self) -> u32 {
+        self.perfect + self.great + self.good + self.ok + self.meh + self.miss
+    }
+
+    /// Accuracy in `[0, 1]` as used for pp, where a perfect is worth 320 and a great 300.
+    pub fn accuracy(&self) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+        let points = self.perfect as f64 * 320.0
+            + self.great as f64 * 300.0
+            + self.good as f64 * 200.0
+            + self.ok as f64 * 100.0
+            + self.meh as f64 * 50.0;
+        points / (total as f64 * 320.0)
+    }
+}
+
+/// The score a pp value is calculated for.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ScoreInfo {
+    // 0-1, weighted as in `Judgements::accuracy`
+    pub accuracy: f64,
+    // Number of judgements; 0 uses notes + holds of the difficulty attributes (head and tail per hold)
+    pub total_hits: u32,
+    // osu! mod bits, see `LegacyMods`
+    pub mods: u32,
+}
+
+impl ScoreInfo {
+    pub fn from_judgements(judgements: &Judgements, mods: u32) -> Self {
+        ScoreInfo { accuracy: judgements.accuracy(), total_hits: judgements.total(), mods }
+    }
+}
+
+/// Shape of a pp curve: `multiplier * max(SR - sr_offset, min_base)^exponent`, scaled by
+/// accuracy above `accuracy_floor` and a length bonus.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PpCurve {
+    pub multiplier: f64,
+    pub sr_offset: f64,
+    pub min_base: f64,
+    pub exponent: f64,
+    // Accuracy giving 0 pp; pp grows linearly up to 100%
+    pub accuracy_floor: f64,
+    // Extra pp fraction for long maps, reached at `length_bonus_hits`
+    pub length_bonus: f64,
+    pub length_bonus_hits: f64,
+}
+
+impl Default for PpCurve {
+    /// The constants of osu!'s mania formula.
+    fn default() -> Self {
+        PpCurve {
+            multiplier: 8.0,
+            sr_offset: 0.15,
+            min_base: 0.05,
+            exponent: 2.2,
+            accuracy_floor: 0.8,
+            length_bonus: 0.1,
+            length_bonus_hits: 1500.0,
+        }
+    }
+}
+
+impl PpCurve {
+    fn difficulty_value(&self, star_rating: f64, accuracy: f64, total_hits: f64) -> f64 {
+        let strain = self.multiplier * (star_rating - self.sr_offset).max(self.min_base).powf(self.exponent);
+        let accuracy_factor = ((accuracy - self.accuracy_floor) / (1.0 - self.accuracy_floor)).max(0.0);
+        let length_factor = 1.0 + self.length_bonus * (total_hits / self.length_bonus_hits).min(1.0);
+        strain * accuracy_factor * length_factor
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum PpFormula {
+    /// The current osu! mania formula.
+    #[default]
+    Mania,
+    /// A custom curve, for tuning pp to SR-Rebirth values, which run higher than osu!'s SR.
+    Curve(PpCurve),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PerformanceAttributes {
+    pub pp: f64,
+    // pp before the NF/EZ multipliers
+    pub difficulty: f64,
+    pub accuracy: f64,
+}
+
+pub struct PerformanceCalculator;
+
+impl PerformanceCalculator {
+    /// pp of `score` on a map with the given attributes. The attributes should be calculated
+    /// with the score's mods (see `SrConfig::from_legacy_mods`).
+    pub fn calculate(attributes: &DifficultyAttributes, score: &ScoreInfo, formula: &PpFormula) -> PerformanceAttributes {
+        let total_hits = if score.total_hits > 0 {
+            score.total_hits as f64
+        } else {
+            (attributes.note_count + attributes.hold_count) as f64
+        };
+        let curve = match formula {
+            PpFormula::Mania => PpCurve::default(),
+            PpFormula::Curve(curve) => *curve,
+        };
+
+        let mut multiplier = 1.0;
+        if score.mods & LegacyMods::NO_FAIL != 0 {
+            multiplier *= 0.75;
+        }
+        if score.mods & LegacyMods::EASY != 0 {
+            multiplier *= 0.5;
+        }
+
+        let accuracy = score.accuracy.clamp(0.0, 1.0);
+        let difficulty = curve.difficulty_value(attributes.star_rating, accuracy, total_hits);
+        PerformanceAttributes { pp: difficulty * multiplier, difficulty, accuracy }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(star_rating: f64, note_count: usize) -> DifficultyAttributes {
+        DifficultyAttributes { star_rating, note_count, ..Default::default() }
+    }
+
+    #[test]
+    fn test_judgement_accuracy() {
+        let judgements = Judgements { perfect: 900, great: 80, good: 10, ok: 5, meh: 3, miss: 2 };
+        assert_eq!(judgements.total(), 1000);
+        let expected = (900.0 * 320.0 + 80.0 * 300.0 + 10.0 * 200.0 + 5.0 * 100.0 + 3.0 * 50.0) / 320000.0;
+        assert!((judgements.accuracy() - expected).abs() < 1e-12);
+        assert_eq!(Judgements::default().accuracy(), 0.0);
+    }
+
+    #[test]
+    fn test_mania_formula() {
+        let ss = ScoreInfo { accuracy: 1.0, total_hits: 1500, mods: 0 };
+        let pp = PerformanceCalculator::calculate(&attributes(5.15, 0), &ss, &PpFormula::Mania).pp;
+        // 8 * 5^2.2 * 1.1
+        assert!((pp - 8.0 * 5f64.powf(2.2) * 1.1).abs() < 1e-9);
+
+        // Nothing below 80%, half at 90%
+        let at = |accuracy: f64| PerformanceCalculator::calculate(&attributes(5.15, 0), &ScoreInfo { accuracy, ..ss }, &PpFormula::Mania).pp;
+        assert_eq!(at(0.8), 0.0);
+        assert!((at(0.9) - pp / 2.0).abs() < 1e-9);
+
+        let nf_ez = ScoreInfo { mods: LegacyMods::NO_FAIL | LegacyMods::EASY, ..ss };
+        assert!((PerformanceCalculator::calculate(&attributes(5.15, 0), &nf_ez, &PpFormula::Mania).pp - pp * 0.375).abs() < 1e-9);
+
+        // total_hits 0 falls back to the note count
+        let from_notes = PerformanceCalculator::calculate(&attributes(5.15, 750), &ScoreInfo { total_hits: 0, ..ss }, &PpFormula::Mania).pp;
+        assert!((from_notes - 8.0 * 5f64.powf(2.2) * 1.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_custom_curve() {
+        let ss = ScoreInfo { accuracy: 1.0, total_hits: 1500, mods: 0 };
+        assert_eq!(
+            PerformanceCalculator::calculate(&attributes(6.0, 0), &ss, &PpFormula::Curve(PpCurve::default())),
+            PerformanceCalculator::calculate(&attributes(6.0, 0), &ss, &PpFormula::Mania),
+        );
+
+        let rebirth = PpCurve { sr_offset: 1.0, exponent: 2.0, ..Default::default() };
+        let pp = PerformanceCalculator::calculate(&attributes(6.0, 0), &ss, &PpFormula::Curve(rebirth)).pp;
+        assert!((pp - 8.0 * 25.0 * 1.1).abs() < 1e-9);
+    }
+}
@@ -1,95 +1,344 @@
+use crate::attributes::{DifficultyAttributes, SkillSummary};
+use crate::timeline::{SkillValues, StrainPoint, StrainTimeline, MIN_TIMELINE_INTERVAL};
+use crate::beatmap::ManiaBeatmap;
+use crate::config::{SrAlgorithm, SrConfig};
+use crate::math::{self, SmoothMode};
+use crate::error::SrError;
+use crate::note::{Note, NoteComparerByT};
+use crate::params::SrParams;
 use crate::parser::ParsedData;
+use crate::sr_v30::SrV30;
+use rayon::prelude::*;
+
+#[cfg(any(test, feature = "dense-reference"))]
+#[doc(hidden)]
+pub mod dense;
+
+// Value that is constant over sorted, disjoint runs of corner indices and `default` elsewhere.
+// Stands in for a dense per-column vector, which costs K x corners on long maps
+struct CornerRuns<T> {
+    runs: Vec<(std::ops::Range<usize>, T)>,
+    default: T,
+}
+
+impl<T: Copy> CornerRuns<T> {
+    fn new(default: T) -> Self {
+        CornerRuns { runs: vec![], default }
+    }
+
+    // Runs must be pushed in corner order; empty ones are dropped
+    fn push(&mut self, range: std::ops::Range<usize>, value: T) {
+        if !range.is_empty() {
+            self.runs.push((range, value));
+        }
+    }
 
-#[derive(Clone, Copy, PartialEq)]
-enum SmoothMode {
-    Sum,
-    Average,
+    fn get(&self, idx: usize) -> T {
+        let i = self.runs.partition_point(|(range, _)| range.end <= idx);
+        match self.runs.get(i) {
+            Some((range, value)) if range.start <= idx => *value,
+            _ => self.default,
+        }
+    }
+
+    // Values at corners 0..len
+    fn iter(&self, len: usize) -> impl Iterator<Item = T> + '_ {
+        let mut runs = self.runs.iter().peekable();
+        (0..len).map(move |idx| {
+            while runs.next_if(|(range, _)| range.end <= idx).is_some() {}
+            match runs.peek() {
+                Some((range, value)) if range.start <= idx => *value,
+                _ => self.default,
+            }
+        })
+    }
+}
+
+// Per-corner skill values of one map, all sampled at `corners`
+pub(crate) struct SkillCurves {
+    pub(crate) corners: Vec<f64>,
+    pub(crate) jbar: Vec<f64>,
+    pub(crate) xbar: Vec<f64>,
+    pub(crate) pbar: Vec<f64>,
+    pub(crate) abar: Vec<f64>,
+    pub(crate) rbar: Vec<f64>,
+    pub(crate) c_arr: Vec<f64>,
+    pub(crate) ks_arr: Vec<f64>,
+    pub(crate) weights: Vec<f64>,
+    // Final difficulty D at each corner
+    pub(crate) difficulty: Vec<f64>,
+    pub(crate) note_seq: Vec<Note>,
+    pub(crate) ln_seq: Vec<Note>,
 }
 
 pub struct SRCalculator;
 
 impl SRCalculator {
-    pub fn calculate_sr_from_parsed_data(data: &ParsedData) -> Result<f64, String> {
-        let od = data.od;
-        let k = data.column_count;
+    pub fn calculate_sr_from_parsed_data(data: &ParsedData) -> Result<f64, SrError> {
+        Self::calculate_sr_for_beatmap(&ManiaBeatmap::from(data))
+    }
+
+    pub fn calculate_sr_for_beatmap(beatmap: &ManiaBeatmap) -> Result<f64, SrError> {
+        Self::calculate_sr(&beatmap.notes, beatmap.key_count, beatmap.od)
+    }
+
+    /// Calculates SR for notes that did not come from an .osu file.
+    /// Notes may be in any order; `Note::t` is -1 for single notes. Columns outside
+    /// `0..key_count` are clamped to the nearest column, as the parser does for x positions.
+    pub fn calculate_sr(notes: &[Note], key_count: i32, od: f64) -> Result<f64, SrError> {
+        Self::calculate_sr_with_config(notes, key_count, od, &SrConfig::default())
+    }
+
+    pub fn calculate_sr_with_config(notes: &[Note], key_count: i32, od: f64, config: &SrConfig) -> Result<f64, SrError> {
+        Self::calculate_attributes_with_config(notes, key_count, od, config).map(|a| a.star_rating)
+    }
+
+    /// SR of `beatmap` at each of `rates`, e.g. for a 0.5x-2.0x rate table.
+    pub fn calculate_rate_table(beatmap: &ManiaBeatmap, rates: &[f64]) -> Result<Vec<(f64, f64)>, SrError> {
+        rates
+            .iter()
+            .map(|&rate| {
+                let config = SrConfig { rate, ..Default::default() };
+                Self::calculate_sr_with_config(&beatmap.notes, beatmap.key_count, beatmap.od, &config).map(|sr| (rate, sr))
+            })
+            .collect()
+    }
+
+    pub fn calculate_attributes_from_parsed_data(data: &ParsedData) -> Result<DifficultyAttributes, SrError> {
+        Self::calculate_attributes_for_beatmap(&ManiaBeatmap::from(data))
+    }
+
+    pub fn calculate_attributes_for_beatmap(beatmap: &ManiaBeatmap) -> Result<DifficultyAttributes, SrError> {
+        Self::calculate_attributes(&beatmap.notes, beatmap.key_count, beatmap.od)
+    }
 
-        if k > 18 || k < 1 || (k > 10 && k % 2 == 1) {
-            return Err("Unsupported key count".to_string());
+    /// Same as [`SRCalculator::calculate_sr`], but also returns the skill curves
+    /// summarised over the map.
+    pub fn calculate_attributes(notes: &[Note], key_count: i32, od: f64) -> Result<DifficultyAttributes, SrError> {
+        Self::calculate_attributes_with_config(notes, key_count, od, &SrConfig::default())
+    }
+
+    pub fn calculate_attributes_with_config(
+        notes: &[Note],
+        key_count: i32,
+        od: f64,
+        config: &SrConfig,
+    ) -> Result<DifficultyAttributes, SrError> {
+        if !config.note_mods.is_empty() {
+            let beatmap = ManiaBeatmap::new(key_count, od, notes.to_vec()).with_mods(&config.note_mods);
+            let config = SrConfig { note_mods: vec![], ..config.clone() };
+            return Self::calculate_attributes_with_config(&beatmap.notes, beatmap.key_count, od, &config);
         }
 
-        // Build note_seq as (column, head_time, tail_time)
-        let mut note_seq: Vec<(i32, i32, i32)> = data.columns.iter().enumerate().map(|(i, &col)| {
-            let h = data.note_starts[i];
-            let t = if data.note_types[i] == 128 { data.note_ends[i] } else { -1 };
-            (col, h, t)
-        }).collect();
+        let params = &config.params;
+        let (curves, sr) = match config.algorithm {
+            SrAlgorithm::Rebirth => {
+                let curves = Self::compute_curves(notes, key_count, od, config)?;
+                let sr = Self::finalise_difficulty(&curves.difficulty, &curves.weights, &curves.note_seq, &curves.ln_seq, params);
+                (curves, sr)
+            }
+            SrAlgorithm::V30 => {
+                let curves = SrV30::compute_curves(notes, key_count, od, config)?;
+                let sr = SrV30::finalise_difficulty(&curves, key_count);
+                (curves, sr)
+            }
+        };
+
+        Ok(DifficultyAttributes {
+            star_rating: sr,
+            key_count,
+            od,
+            note_count: curves.note_seq.len(),
+            hold_count: curves.ln_seq.len(),
+            jack: Self::summarise(&curves.jbar, &curves.weights, params),
+            cross_column: Self::summarise(&curves.xbar, &curves.weights, params),
+            pressing_intensity: Self::summarise(&curves.pbar, &curves.weights, params),
+            unevenness: Self::summarise(&curves.abar, &curves.weights, params),
+            release: Self::summarise(&curves.rbar, &curves.weights, params),
+            density: Self::summarise(&curves.c_arr, &curves.weights, params),
+            active_keys: Self::summarise(&curves.ks_arr, &curves.weights, params),
+        })
+    }
+
+    pub fn calculate_strain_timeline_for_beatmap(beatmap: &ManiaBeatmap, interval: f64, include_skills: bool) -> Result<StrainTimeline, SrError> {
+        Self::calculate_strain_timeline(&beatmap.notes, beatmap.key_count, beatmap.od, interval, include_skills)
+    }
+
+    /// Difficulty over time, sampled every `interval` ms from 0 to the end of the map.
+    /// An `interval` of 0 (or less) returns every corner the algorithm evaluates; a positive
+    /// interval below [`MIN_TIMELINE_INTERVAL`] ms gives `InvalidConfig`.
+    pub fn calculate_strain_timeline(
+        notes: &[Note],
+        key_count: i32,
+        od: f64,
+        interval: f64,
+        include_skills: bool,
+    ) -> Result<StrainTimeline, SrError> {
+        Self::calculate_strain_timeline_with_config(notes, key_count, od, interval, include_skills, &SrConfig::default())
+    }
+
+    /// Same as [`SRCalculator::calculate_strain_timeline`] with the rate, mods and algorithm of `config`,
+    /// so the graph matches [`SRCalculator::calculate_sr_with_config`]. Times are in played time: at 1.5x
+    /// the timeline of a 90 s map ends at 60 s.
+    pub fn calculate_strain_timeline_with_config(
+        notes: &[Note],
+        key_count: i32,
+        od: f64,
+        interval: f64,
+        include_skills: bool,
+        config: &SrConfig,
+    ) -> Result<StrainTimeline, SrError> {
+        // A point per microsecond over a whole map would not fit in memory
+        if interval > 0.0 && interval < MIN_TIMELINE_INTERVAL {
+            return Err(SrError::InvalidConfig(format!(
+                "timeline interval must be at least {} ms, got {}",
+                MIN_TIMELINE_INTERVAL, interval
+            )));
+        }
+
+        if !config.note_mods.is_empty() {
+            let beatmap = ManiaBeatmap::new(key_count, od, notes.to_vec()).with_mods(&config.note_mods);
+            let config = SrConfig { note_mods: vec![], ..config.clone() };
+            return Self::calculate_strain_timeline_with_config(&beatmap.notes, beatmap.key_count, od, interval, include_skills, &config);
+        }
+
+        let curves = match config.algorithm {
+            SrAlgorithm::Rebirth => Self::compute_curves(notes, key_count, od, config)?,
+            SrAlgorithm::V30 => SrV30::compute_curves(notes, key_count, od, config)?,
+        };
+
+        let times: Vec<f64> = if interval > 0.0 && interval.is_finite() {
+            // V3.0 curves start at the first note when it is before 0 ms; the grid stays aligned to 0
+            let (first, end) = (curves.corners[0], *curves.corners.last().unwrap());
+            let start = (first / interval).floor() * interval;
+            (0..).map(|i| start + i as f64 * interval).take_while(|&time| time <= end).collect()
+        } else {
+            curves.corners.clone()
+        };
+        let sample = |values: &[f64]| math::interp_values(&times, &curves.corners, values);
+
+        let difficulty = sample(&curves.difficulty);
+        let skills = include_skills.then(|| {
+            let (jack, cross_column, pressing_intensity) = (sample(&curves.jbar), sample(&curves.xbar), sample(&curves.pbar));
+            let (unevenness, release) = (sample(&curves.abar), sample(&curves.rbar));
+            (0..times.len())
+                .map(|i| SkillValues {
+                    jack: jack[i],
+                    cross_column: cross_column[i],
+                    pressing_intensity: pressing_intensity[i],
+                    unevenness: unevenness[i],
+                    release: release[i],
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let points = times
+            .iter()
+            .enumerate()
+            .map(|(i, &time)| StrainPoint { time, difficulty: difficulty[i], skills: skills.as_ref().map(|s| s[i]) })
+            .collect();
+        Ok(StrainTimeline { interval: interval.max(0.0), points })
+    }
+
+    fn compute_curves(notes: &[Note], key_count: i32, od: f64, config: &SrConfig) -> Result<SkillCurves, SrError> {
+        let k = key_count;
+
+        if !(1..=18).contains(&k) {
+            return Err(SrError::UnsupportedKeyCount(k));
+        }
+        if !od.is_finite() || od < 0.0 {
+            return Err(SrError::InvalidOd(od));
+        }
+
+        config.validate()?;
+
+        // Own copy of the notes so they can be sorted, in played time and with columns inside 0..k
+        let mut note_seq: Vec<Note> = notes
+            .iter()
+            .map(|n| Note::new(n.k.clamp(0, k - 1), config.scale_time(n.h), if n.t >= 0 { config.scale_time(n.t) } else { -1 }))
+            .collect();
 
         if note_seq.is_empty() {
-            return Ok(0.0);
+            return Err(SrError::EmptyMap);
         }
 
         // Sort by (start_time, column) as in Python
-        note_seq.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+        note_seq.sort();
 
-        // Hit leniency x - exactly as in Python
-        let x = 0.3 * ((64.5 - (od * 3.0).ceil()) / 500.0).sqrt();
+        // Hit leniency x - exactly as in Python, with the window of the chosen model and mods
+        let great_window = config.great_window(od);
+        if great_window <= 0.0 {
+            return Err(SrError::InvalidOd(od));
+        }
+        let x = 0.3 * (great_window / 500.0).sqrt();
         let x = x.min(0.6 * (x - 0.09) + 0.09);
 
+        let params = &config.params;
+
         // Group notes by column
-        let mut note_seq_by_column: Vec<Vec<(i32, i32, i32)>> = vec![vec![]; k as usize];
+        let mut note_seq_by_column: Vec<Vec<Note>> = vec![vec![]; k as usize];
         for &note in &note_seq {
-            let col = note.0.max(0).min((k - 1) as i32) as usize;
-            note_seq_by_column[col].push(note);
+            note_seq_by_column[note.k as usize].push(note);
         }
 
         // LN sequences
-        let ln_seq: Vec<(i32, i32, i32)> = note_seq.iter().filter(|&&(_, _, t)| t >= 0).cloned().collect();
+        let ln_seq: Vec<Note> = note_seq.iter().filter(|n| n.t >= 0).cloned().collect();
         let mut tail_seq = ln_seq.clone();
-        tail_seq.sort_by(|a, b| a.2.cmp(&b.2)); // Sort by tail time
+        tail_seq.sort_by(NoteComparerByT::cmp); // Sort by tail time
 
         // Calculate T
-        let t = note_seq.iter().map(|&(_, h, t)| h.max(t)).max().unwrap() + 1;
+        let t = note_seq.iter().map(|n| n.h.max(n.t)).max().unwrap() + 1;
 
-        let (all_corners, base_corners, a_corners) = Self::get_corners(t, &note_seq);
+        let (all_corners, base_corners, a_corners) = Self::get_corners(t, &note_seq, params);
 
         // Get key usage
-        let key_usage = Self::get_key_usage(k, t, &note_seq, &base_corners);
-        let active_columns = Self::derive_active_columns(&key_usage);
-
-
-        let key_usage_400 = Self::get_key_usage_400(k, t, &note_seq, &base_corners);
-        let anchor = Self::compute_anchor(k, &key_usage_400, &base_corners);
-
-        // Compute Jbar and delta_ks
-        let (delta_ks, jbar) = Self::compute_jbar(k, &note_seq_by_column, &base_corners, x);
-
-        // Compute Xbar
-        let xbar = Self::compute_xbar(k, x, &note_seq_by_column, &active_columns, &base_corners);
-
-        // Compute Pbar
-        let ln_rep = if !ln_seq.is_empty() { Some(Self::build_ln_representation(&ln_seq, t)) } else { None };
-
-        let pbar = Self::compute_pbar(x, &note_seq, ln_rep.as_ref(), &anchor, &base_corners);
-
-        // Compute Abar
-        let abar = Self::compute_abar(k, &delta_ks, &active_columns, &a_corners, &base_corners);
-
-        // Compute Rbar
-        let rbar = Self::compute_rbar(x, &note_seq_by_column, &tail_seq, &base_corners);
+        let key_usage = Self::get_key_usage(k, t, &note_seq, &base_corners, params.key_usage_span);
+        let cross_matrix = config.cross_matrix(k as usize);
+        let parallel = config.parallel;
+
+        // The skill stages only read the shared inputs above, so in parallel mode they run side by side
+        let ((jbar, abar), (xbar, (pbar, rbar))) = Self::join(
+            parallel,
+            || {
+                // Compute Jbar and delta_ks, then Abar from delta_ks
+                let (delta_ks, jbar) = Self::compute_jbar(k, &note_seq_by_column, &base_corners, x, params.smoothing_window, parallel);
+                let abar = Self::compute_abar(k, &delta_ks, &key_usage, &a_corners, &base_corners, params.unevenness_window);
+                (jbar, abar)
+            },
+            || {
+                Self::join(
+                    parallel,
+                    || Self::compute_xbar(k, x, cross_matrix.as_deref(), &note_seq_by_column, &key_usage, &base_corners, params.smoothing_window),
+                    || {
+                        Self::join(
+                            parallel,
+                            || {
+                                // Compute Pbar
+                                let anchor = Self::compute_anchor(k, &note_seq_by_column, &base_corners, params.anchor_span);
+                                let ln_rep = if !ln_seq.is_empty() { Some(Self::build_ln_representation(&ln_seq, t)) } else { None };
+                                Self::compute_pbar(x, &note_seq, ln_rep.as_ref(), &anchor, &base_corners, params.smoothing_window)
+                            },
+                            || Self::compute_rbar(x, &note_seq_by_column, &tail_seq, &base_corners, params.smoothing_window),
+                        )
+                    },
+                )
+            },
+        );
 
         // Compute C and Ks
-        let (c_arr, ks_arr) = Self::compute_c_and_ks(k, &note_seq, &key_usage, &base_corners);
+        let (c_arr, ks_arr) = Self::compute_c_and_ks(&note_seq, &key_usage, &base_corners, params.density_window);
 
         // Final SR calculation
-        let jbar_interp = Self::interp_values(&all_corners, &base_corners, &jbar);
-        let xbar_interp = Self::interp_values(&all_corners, &base_corners, &xbar);
-        let pbar_interp = Self::interp_values(&all_corners, &base_corners, &pbar);
-        let abar_interp = Self::interp_values(&all_corners, &a_corners, &abar);
-        let rbar_interp = Self::interp_values(&all_corners, &base_corners, &rbar);
-        let c_arr_interp = Self::step_interp(&all_corners, &base_corners, &c_arr);
-        let ks_arr_interp = Self::step_interp(&all_corners, &base_corners, &ks_arr);
-
-        let sr = Self::calculate_final_sr(
+        let jbar_interp = math::interp_values(&all_corners, &base_corners, &jbar);
+        let xbar_interp = math::interp_values(&all_corners, &base_corners, &xbar);
+        let pbar_interp = math::interp_values(&all_corners, &base_corners, &pbar);
+        let abar_interp = math::interp_values(&all_corners, &a_corners, &abar);
+        let rbar_interp = math::interp_values(&all_corners, &base_corners, &rbar);
+        let c_arr_interp = math::step_interp(&all_corners, &base_corners, &c_arr);
+        let ks_arr_interp = math::step_interp(&all_corners, &base_corners, &ks_arr);
+        let weights = Self::effective_weights(&all_corners, &c_arr_interp);
+
+        let difficulty = Self::difficulty_curve(
             &jbar_interp,
             &xbar_interp,
             &pbar_interp,
@@ -97,17 +346,29 @@ impl SRCalculator {
             &rbar_interp,
             &c_arr_interp,
             &ks_arr_interp,
-            &note_seq,
-            &ln_seq,
-            &all_corners,
         );
 
-        Ok(sr)
+        Ok(SkillCurves {
+            corners: all_corners,
+            jbar: jbar_interp,
+            xbar: xbar_interp,
+            pbar: pbar_interp,
+            abar: abar_interp,
+            rbar: rbar_interp,
+            c_arr: c_arr_interp,
+            ks_arr: ks_arr_interp,
+            weights,
+            difficulty,
+            note_seq,
+            ln_seq,
+        })
     }
 
-    fn get_corners(t: i32, note_seq: &[(i32, i32, i32)]) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+    fn get_corners(t: i32, note_seq: &[Note], params: &SrParams) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+        // Corners just past the ends of the smoothing windows
+        let window = params.smoothing_window.round() as i32;
         let mut corners_base = std::collections::BTreeSet::new();
-        for &(_, h, tail) in note_seq {
+        for &Note { h, t: tail, .. } in note_seq {
             corners_base.insert(h);
             if tail >= 0 {
                 corners_base.insert(tail);
@@ -117,8 +378,8 @@ impl SRCalculator {
         corners_base.insert(t);
         let corners_base_list: Vec<i32> = corners_base.iter().cloned().collect();
         for &s in &corners_base_list {
-            corners_base.insert(s + 501);
-            corners_base.insert(s - 499);
+            corners_base.insert(s + window + 1);
+            corners_base.insert(s - window + 1);
             corners_base.insert(s + 1);
         }
         let mut corners_base_vec: Vec<f64> = corners_base.into_iter()
@@ -128,7 +389,7 @@ impl SRCalculator {
         corners_base_vec.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
         let mut corners_a = std::collections::BTreeSet::new();
-        for &(_, h, tail) in note_seq {
+        for &Note { h, t: tail, .. } in note_seq {
             corners_a.insert(h);
             if tail >= 0 {
                 corners_a.insert(tail);
@@ -160,9 +421,9 @@ impl SRCalculator {
         (all_corners, corners_base_vec, a_corners)
     }
 
-    fn build_ln_representation(ln_seq: &[(i32, i32, i32)], t: i32) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+    fn build_ln_representation(ln_seq: &[Note], t: i32) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
         let mut changes = vec![];
-        for &(_k, h, tail) in ln_seq {
+        for &Note { h, t: tail, .. } in ln_seq {
             let t0 = (h + 60).min(tail) as f64;
             let t1 = (h + 120).min(tail) as f64;
             changes.push((t0, 1.3));
@@ -197,72 +458,118 @@ impl SRCalculator {
 
         (points, cumsum, values)
     }
-    fn derive_active_columns(key_usage: &[Vec<bool>]) -> Vec<Vec<usize>> {
-        let length = key_usage[0].len();
-        let mut active = vec![vec![]; length];
-        for i in 0..length {
-            for col in 0..key_usage.len() {
-                if key_usage[col][i] {
-                    active[i].push(col);
-                }
-            }
-        }
-        active
+    // Bit `col` of a corner's mask is set while that column is in use
+    fn in_use(key_usage: u32, col: usize) -> bool {
+        key_usage & (1 << col) != 0
     }
 
-    fn get_key_usage(k: i32, total_time: i32, note_seq: &[(i32, i32, i32)], base_corners: &[f64]) -> Vec<Vec<bool>> {
-        let mut key_usage = vec![vec![false; base_corners.len()]; k as usize];
-        for &(col, h, tail) in note_seq {
-            let start = (h - 150).max(0) as f64;
-            let end = if tail >= 0 { ((tail + 150) as f64).min(total_time as f64 - 1.0) } else { ((h + 150) as f64).min(total_time as f64 - 1.0) };
-            let left = Self::bisect_left(base_corners, start);
-            let right = Self::bisect_left(base_corners, end);
-            for idx in left..right {
-                key_usage[col as usize][idx] = true;
+    fn get_key_usage(k: i32, total_time: i32, note_seq: &[Note], base_corners: &[f64], span: f64) -> Vec<u32> {
+        let mut key_usage = vec![0; base_corners.len()];
+        // Corners below this index are already marked for the column. Notes come sorted by head,
+        // so ranges only grow to the right and each corner is marked once
+        let mut marked = vec![0; k as usize];
+        for &Note { k: col, h, t: tail } in note_seq {
+            let start = (h as f64 - span).max(0.0);
+            let end = if tail >= 0 { (tail as f64 + span).min(total_time as f64 - 1.0) } else { (h as f64 + span).min(total_time as f64 - 1.0) };
+            let left = math::bisect_left(base_corners, start);
+            let right = math::bisect_left(base_corners, end);
+            let from = left.max(marked[col as usize]);
+            if from < right {
+                for usage in &mut key_usage[from..right] {
+                    *usage |= 1 << col;
+                }
             }
+            marked[col as usize] = marked[col as usize].max(right);
         }
         key_usage
     }
 
-    fn add_falloff_contribution(usage: &mut [Vec<f64>], col: usize, base_corners: &[f64], range: std::ops::Range<usize>, ref_time: f64) {
-        for idx in range {
-            let offset = base_corners[idx] - ref_time;
-            let falloff_contribution = 3.75 / (400.0 * 400.0) * offset * offset;
-            let value = 3.75 - falloff_contribution;
-            let clamped = value.max(0.0);
-            usage[col][idx] += clamped;
-        }
+    fn falloff_contribution(corner: f64, ref_time: f64, span: f64) -> f64 {
+        let offset = corner - ref_time;
+        let falloff_contribution = 3.75 / (span * span) * offset * offset;
+        let value = 3.75 - falloff_contribution;
+        value.max(0.0)
     }
 
-    fn get_key_usage_400(k: i32, _total_time: i32, note_seq: &[(i32, i32, i32)], base_corners: &[f64]) -> Vec<Vec<f64>> {
-        let mut usage = vec![vec![0.0; base_corners.len()]; k as usize];
-        for &(col, h, tail) in note_seq {
-            let start_time = h.max(0) as f64;
-            let end_time = if tail < 0 { h as f64 } else { tail as f64 };
-            let left400_idx = Self::bisect_left(base_corners, start_time - 400.0);
-            let left_idx = Self::bisect_left(base_corners, start_time);
-            let right_idx = Self::bisect_left(base_corners, end_time);
-            let right400_idx = Self::bisect_left(base_corners, end_time + 400.0);
-
-            let duration = end_time - start_time;
-            let clamped_duration = duration.min(1500.0);
-            let extension = clamped_duration / 150.0;
-            let contribution = 3.75 + extension;
+    fn compute_anchor(k: i32, note_seq_by_column: &[Vec<Note>], base_corners: &[f64], span: f64) -> Vec<f64> {
+        // Corner ranges one note adds to its column's usage over: held, and falling off before and after
+        struct NoteUsage {
+            left400: usize,
+            left: usize,
+            right: usize,
+            right400: usize,
+            start_time: f64,
+            end_time: f64,
+            contribution: f64,
+        }
 
-            for idx in left_idx..right_idx {
-                usage[col as usize][idx] += contribution;
-            }
+        let columns: Vec<Vec<NoteUsage>> = note_seq_by_column
+            .iter()
+            .take(k as usize)
+            .map(|notes| {
+                notes
+                    .iter()
+                    .map(|&Note { h, t: tail, .. }| {
+                        let start_time = h.max(0) as f64;
+                        let end_time = if tail < 0 { h as f64 } else { tail as f64 };
+                        let duration = end_time - start_time;
+                        let clamped_duration = duration.min(1500.0);
+                        let extension = clamped_duration / 150.0;
+                        NoteUsage {
+                            left400: math::bisect_left(base_corners, start_time - span),
+                            left: math::bisect_left(base_corners, start_time),
+                            right: math::bisect_left(base_corners, end_time),
+                            right400: math::bisect_left(base_corners, end_time + span),
+                            start_time,
+                            end_time,
+                            contribution: 3.75 + extension,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
 
-            Self::add_falloff_contribution(&mut usage, col as usize, base_corners, left400_idx..left_idx, start_time);
-            Self::add_falloff_contribution(&mut usage, col as usize, base_corners, right_idx..right400_idx, end_time);
-        }
-        usage
-    }
+        // Sweep the corners, keeping per column the notes whose ranges cover the current corner.
+        // Active notes stay in note order so each column's usage is summed in the same order as a dense pass
+        let by_start: Vec<Vec<usize>> = columns
+            .iter()
+            .map(|usages| {
+                let mut order: Vec<usize> = (0..usages.len()).collect();
+                order.sort_by_key(|&n| usages[n].left400.min(usages[n].right));
+                order
+            })
+            .collect();
+        let mut next = vec![0; columns.len()];
+        let mut active: Vec<Vec<usize>> = vec![vec![]; columns.len()];
 
-    fn compute_anchor(k: i32, key_usage_400: &[Vec<f64>], base_corners: &[f64]) -> Vec<f64> {
         let mut anchor = vec![0.0; base_corners.len()];
         for i in 0..base_corners.len() {
-            let mut counts: Vec<f64> = (0..k as usize).map(|col| key_usage_400[col][i]).collect();
+            let mut counts = Vec::with_capacity(columns.len());
+            for col in 0..columns.len() {
+                let usages = &columns[col];
+                while let Some(&n) = by_start[col].get(next[col]).filter(|&&n| usages[n].left400.min(usages[n].right) <= i) {
+                    let at = active[col].partition_point(|&m| m < n);
+                    active[col].insert(at, n);
+                    next[col] += 1;
+                }
+                active[col].retain(|&n| usages[n].right400 > i);
+
+                let mut value = 0.0;
+                for &n in &active[col] {
+                    let usage = &usages[n];
+                    if (usage.left..usage.right).contains(&i) {
+                        value += usage.contribution;
+                    }
+                    if (usage.left400..usage.left).contains(&i) {
+                        value += Self::falloff_contribution(base_corners[i], usage.start_time, span);
+                    }
+                    if (usage.right..usage.right400).contains(&i) {
+                        value += Self::falloff_contribution(base_corners[i], usage.end_time, span);
+                    }
+                }
+                counts.push(value);
+            }
+
             counts.sort_by(|a, b| b.partial_cmp(a).unwrap());
             let non_zero: Vec<f64> = counts.into_iter().filter(|&c| c > 0.0).collect();
             if non_zero.len() <= 1 {
@@ -287,15 +594,15 @@ impl SRCalculator {
         anchor
     }
 
-    fn compute_jbar(k: i32, note_seq_by_column: &[Vec<(i32, i32, i32)>], base_corners: &[f64], x: f64) -> (Vec<Vec<f64>>, Vec<f64>) {
-        let mut j_ks = vec![vec![0.0; base_corners.len()]; k as usize];
-        let mut delta_ks = vec![vec![1e9; base_corners.len()]; k as usize];
-
-        for col in 0..k as usize {
+    fn compute_jbar(k: i32, note_seq_by_column: &[Vec<Note>], base_corners: &[f64], x: f64, window: f64, parallel: bool) -> (Vec<CornerRuns<f64>>, Vec<f64>) {
+        // Smoothed J of one column and the runs of its note gaps; only J is dense, and only while it is smoothed
+        let column = |col: usize| {
+            let mut j_k = vec![0.0; base_corners.len()];
+            let mut delta_k = CornerRuns::new(1e9);
             let notes = &note_seq_by_column[col];
             for i in 0..notes.len().saturating_sub(1) {
-                let (_, h1, _) = notes[i];
-                let (_, h2, _) = notes[i + 1];
+                let h1 = notes[i].h;
+                let h2 = notes[i + 1].h;
                 let delta = 0.001 * (h2 - h1) as f64;
 
                 if delta < 1e-9 { continue; }
@@ -308,73 +615,66 @@ impl SRCalculator {
                 let jack = 1.0 - 7e-5 / temp4;
                 let val = 1.0 / (delta * (delta + lambda1_x)) * jack;
 
-                let left_idx = base_corners.partition_point(|&x| x < h1 as f64);
-                let right_idx = base_corners.partition_point(|&x| x < h2 as f64);
+                let left_idx = math::bisect_left(base_corners, h1 as f64);
+                let right_idx = math::bisect_left(base_corners, h2 as f64);
 
-                for j in left_idx..right_idx {
-                    j_ks[col][j] = val;
-                    delta_ks[col][j] = f64::min(delta_ks[col][j], delta);
-                }
+                j_k[left_idx..right_idx].fill(val);
+                delta_k.push(left_idx..right_idx, f64::min(1e9, delta));
             }
-        }
-
-        // Smooth each column's J_ks
-        let mut jbar_ks = vec![vec![0.0; base_corners.len()]; k as usize];
-        for col in 0..k as usize {
-            jbar_ks[col] = Self::smooth_on_corners(&j_ks[col], base_corners, 500.0, 0.001, SmoothMode::Sum);
-        }
-
-        // Aggregate across columns
-        let mut jbar = vec![0.0; base_corners.len()];
-        for i in 0..base_corners.len() {
-            let mut vals = vec![];
-            let mut weights = vec![];
-            for col in 0..k as usize {
-                let val = jbar_ks[col][i].max(0.0);
-                vals.push(val.powf(5.0));
-                weights.push(1.0 / f64::max(delta_ks[col][i], 1e-9));
+            (math::smooth_on_corners(base_corners, &j_k, window, 0.001, SmoothMode::Sum), delta_k)
+        };
+
+        // Aggregate across columns, one column at a time
+        let mut weighted_sum = vec![0.0; base_corners.len()];
+        let mut total_weight = vec![0.0; base_corners.len()];
+        let mut delta_ks = Vec::with_capacity(k as usize);
+        let mut aggregate = |(jbar_k, delta_k): (Vec<f64>, CornerRuns<f64>)| {
+            for (i, delta) in delta_k.iter(base_corners.len()).enumerate() {
+                let weight = 1.0 / f64::max(delta, 1e-9);
+                weighted_sum[i] += jbar_k[i].max(0.0).powf(5.0) * weight;
+                total_weight[i] += weight;
             }
-            let weighted_sum: f64 = vals.iter().zip(weights.iter()).map(|(v, w)| v * w).sum();
-            let total_weight: f64 = weights.iter().sum();
-            let combined = if total_weight <= 0.0 { 0.0 } else { weighted_sum / total_weight };
-            jbar[i] = combined.max(0.0).powf(0.2);
+            delta_ks.push(delta_k);
+        };
+        if parallel {
+            // Every smoothed column is held at once here, trading memory for latency
+            let columns: Vec<(Vec<f64>, CornerRuns<f64>)> = (0..k as usize).into_par_iter().map(column).collect();
+            columns.into_iter().for_each(&mut aggregate);
+        } else {
+            (0..k as usize).map(column).for_each(&mut aggregate);
         }
 
+        let jbar = weighted_sum
+            .iter()
+            .zip(&total_weight)
+            .map(|(&weighted_sum, &total_weight)| {
+                let combined = if total_weight <= 0.0 { 0.0 } else { weighted_sum / total_weight };
+                combined.max(0.0).powf(0.2)
+            })
+            .collect();
+
         (delta_ks, jbar)
     }
 
-    fn compute_xbar(k: i32, x: f64, note_seq_by_column: &[Vec<(i32, i32, i32)>], active_columns: &[Vec<usize>], base_corners: &[f64]) -> Vec<f64> {
-        let cross_matrices: Vec<Vec<f64>> = vec![
-            vec![-1.0], // 0: unsupported
-            vec![0.075, 0.075], // 1k
-            vec![0.125, 0.05, 0.125], // 2k
-            vec![0.125, 0.125, 0.125, 0.125], // 3k
-            vec![0.175, 0.25, 0.05, 0.25, 0.175], // 4k
-            vec![0.175, 0.25, 0.175, 0.175, 0.25, 0.175], // 5k
-            vec![0.225, 0.35, 0.25, 0.05, 0.25, 0.35, 0.225], // 6k
-            vec![0.225, 0.35, 0.25, 0.225, 0.225, 0.25, 0.35, 0.225], // 7k
-            vec![0.275, 0.45, 0.35, 0.25, 0.05, 0.25, 0.35, 0.45, 0.275], // 8k
-            vec![0.275, 0.45, 0.35, 0.25, 0.275, 0.275, 0.25, 0.35, 0.45, 0.275], // 9k
-            vec![0.325, 0.55, 0.45, 0.35, 0.25, 0.05, 0.25, 0.35, 0.45, 0.55, 0.325], // 10k
-            vec![-1.0; 12], // 11k unsupported
-            vec![0.8, 0.8, 0.8, 0.6, 0.4, 0.2, 0.05, 0.2, 0.4, 0.6, 0.8, 0.8, 0.8], // 12k
-            vec![-1.0; 14], // 13k unsupported
-            vec![0.4, 0.4, 0.2, 0.2, 0.3, 0.3, 0.1, 0.1, 0.3, 0.3, 0.2, 0.2, 0.4, 0.4, 0.4], // 14k
-            vec![-1.0; 16], // 15k unsupported
-            vec![0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.2, 0.1, 0.1, 0.2, 0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.4], // 16k
-            vec![-1.0; 18], // 17k unsupported
-            vec![0.4, 0.4, 0.2, 0.4, 0.2, 0.4, 0.2, 0.3, 0.1, 0.1, 0.3, 0.2, 0.4, 0.2, 0.4, 0.2, 0.4, 0.4, 0.4], // 18k
-        ];
-
-        if k as usize >= cross_matrices.len() || cross_matrices[k as usize][0] == -1.0 {
+    fn compute_xbar(
+        k: i32,
+        x: f64,
+        matrix: Option<&[f64]>,
+        note_seq_by_column: &[Vec<Note>],
+        key_usage: &[u32],
+        base_corners: &[f64],
+        window: f64,
+    ) -> Vec<f64> {
+        // Without a matrix there is no cross-column difficulty
+        let Some(matrix) = matrix.filter(|m| m.len() == (k + 1) as usize) else {
             return vec![0.0; base_corners.len()];
-        }
+        };
 
-        let matrix = &cross_matrices[k as usize];
-        let mut x_ks = vec![vec![0.0; base_corners.len()]; (k + 1) as usize];
-        let mut fast_cross = vec![vec![0.0; base_corners.len()]; (k + 1) as usize];
+        // X and fast cross of each column pair, as runs between consecutive notes of the pair
+        let mut x_ks: Vec<CornerRuns<(f64, f64)>> = Vec::with_capacity(matrix.len());
 
-        for col in 0..(k + 1) as usize {
+        for (col, &weight) in matrix.iter().enumerate() {
+            let mut runs = CornerRuns::new((0.0, 0.0));
             let mut notes_in_pair = vec![];
             if col == 0 {
                 if !note_seq_by_column.is_empty() {
@@ -390,55 +690,58 @@ impl SRCalculator {
                 let mut left_notes = if left_col < note_seq_by_column.len() { note_seq_by_column[left_col].clone() } else { vec![] };
                 let mut right_notes = if right_col < note_seq_by_column.len() { note_seq_by_column[right_col].clone() } else { vec![] };
                 left_notes.append(&mut right_notes);
-                left_notes.sort_by(|a, b| a.1.cmp(&b.1));
+                left_notes.sort_by_key(|a| a.h);
                 notes_in_pair = left_notes;
             }
 
             for i in 1..notes_in_pair.len() {
-                let (_, h1, _) = notes_in_pair[i - 1];
-                let (_, h2, _) = notes_in_pair[i];
+                let h1 = notes_in_pair[i - 1].h;
+                let h2 = notes_in_pair[i].h;
                 let delta = 0.001 * (h2 - h1) as f64;
                 let max_xd = x.max(delta);
                 let mut val = 0.16 / (max_xd * max_xd);
 
-                let left_idx = base_corners.partition_point(|&x| x < h1 as f64);
-                let right_idx = base_corners.partition_point(|&x| x < h2 as f64);
+                let left_idx = math::bisect_left(base_corners, h1 as f64);
+                let right_idx = math::bisect_left(base_corners, h2 as f64);
 
-                let idx_start = left_idx.min(active_columns.len().saturating_sub(1));
-                let idx_end = right_idx.min(active_columns.len().saturating_sub(1));
+                let idx_start = left_idx.min(key_usage.len().saturating_sub(1));
+                let idx_end = right_idx.min(key_usage.len().saturating_sub(1));
 
-                let condition1 = if col == 0 { true } else { !active_columns[idx_start].contains(&(col - 1)) && !active_columns[idx_end].contains(&(col - 1)) };
-                let condition2 = !active_columns[idx_start].contains(&col) && !active_columns[idx_end].contains(&col);
+                let condition1 = if col == 0 { true } else { !Self::in_use(key_usage[idx_start], col - 1) && !Self::in_use(key_usage[idx_end], col - 1) };
+                let condition2 = !Self::in_use(key_usage[idx_start], col) && !Self::in_use(key_usage[idx_end], col);
                 if condition1 || condition2 {
-                    val *= 1.0 - matrix[col];
+                    val *= 1.0 - weight;
                 }
 
-                for j in left_idx..right_idx {
-                    x_ks[col][j] = val;
-                    fast_cross[col][j] = (0.4 * (delta.max(0.06).max(0.75 * x)).powf(-2.0) - 80.0).max(0.0);
-                }
+                let fast_cross = (0.4 * (delta.max(0.06).max(0.75 * x)).powf(-2.0) - 80.0).max(0.0);
+                runs.push(left_idx..right_idx, (val, fast_cross));
             }
+            x_ks.push(runs);
         }
 
-        let mut x_base = vec![0.0; base_corners.len()];
-        for i in 0..base_corners.len() {
-            for col in 0..(k + 1) as usize {
-                x_base[i] += x_ks[col][i] * matrix[col];
+        // Same per-corner sums as a corner-by-corner pass, taken one column at a time
+        let len = base_corners.len();
+        let mut x_base = vec![0.0; len];
+        for (col, runs) in x_ks.iter().enumerate() {
+            for (x_base, (x_k, _)) in x_base.iter_mut().zip(runs.iter(len)) {
+                *x_base += x_k * matrix[col];
             }
-            for col in 0..k as usize {
-                let left_contrib = fast_cross[col][i] * matrix[col];
-                let right_contrib = fast_cross[col + 1][i] * matrix[col + 1];
-                x_base[i] += (left_contrib * right_contrib).max(0.0).sqrt();
+        }
+        for col in 0..k as usize {
+            for (x_base, ((_, left), (_, right))) in x_base.iter_mut().zip(x_ks[col].iter(len).zip(x_ks[col + 1].iter(len))) {
+                let left_contrib = left * matrix[col];
+                let right_contrib = right * matrix[col + 1];
+                *x_base += (left_contrib * right_contrib).max(0.0).sqrt();
             }
         }
 
-        Self::smooth_on_corners(&x_base, base_corners, 500.0, 0.001, SmoothMode::Sum)
+        math::smooth_on_corners(base_corners, &x_base, window, 0.001, SmoothMode::Sum)
     }
 
     fn ln_sum(a: f64, b: f64, ln_rep: &(Vec<f64>, Vec<f64>, Vec<f64>)) -> f64 {
         let (points, cumsum, values) = ln_rep;
-        let i = (points.partition_point(|&x| x < a) as i32) - 1;
-        let j = (points.partition_point(|&x| x < b) as i32) - 1;
+        let i = (math::bisect_left(points, a) as i32) - 1;
+        let j = (math::bisect_left(points, b) as i32) - 1;
         let mut total = 0.0;
         if i == j {
             let idx = i.max(0) as usize;
@@ -454,18 +757,18 @@ impl SRCalculator {
         total
     }
 
-    fn compute_pbar(x: f64, note_seq: &[(i32, i32, i32)], ln_rep: Option<&(Vec<f64>, Vec<f64>, Vec<f64>)>, anchor: &[f64], base_corners: &[f64]) -> Vec<f64> {
+    fn compute_pbar(x: f64, note_seq: &[Note], ln_rep: Option<&(Vec<f64>, Vec<f64>, Vec<f64>)>, anchor: &[f64], base_corners: &[f64], window: f64) -> Vec<f64> {
         let mut p = vec![0.0; base_corners.len()];
 
         for i in 0..note_seq.len() - 1 {
-            let (_, h1, _) = note_seq[i];
-            let (_, h2, _) = note_seq[i + 1];
+            let h1 = note_seq[i].h;
+            let h2 = note_seq[i + 1].h;
             let delta_time = (h2 - h1) as f64;
 
             if delta_time < 1e-9 {
                 let spike = 1000.0 * (0.02 * (4.0 / x - 24.0)).powf(0.25);
-                let left_idx = base_corners.partition_point(|&x| x < h1 as f64);
-                let right_idx = base_corners.partition_point(|&x| x <= h1 as f64);
+                let left_idx = math::bisect_left(base_corners, h1 as f64);
+                let right_idx = math::bisect_right(base_corners, h1 as f64);
                 for j in left_idx..right_idx {
                     if j < p.len() {
                         p[j] += spike;
@@ -496,8 +799,8 @@ impl SRCalculator {
                 1.0 / delta * (0.08 / x * temp).powf(0.25) * b_val.max(v)
             };
 
-            let left_idx = base_corners.partition_point(|&x| x < h1 as f64);
-            let right_idx = base_corners.partition_point(|&x| x < h2 as f64);
+            let left_idx = math::bisect_left(base_corners, h1 as f64);
+            let right_idx = math::bisect_left(base_corners, h2 as f64);
 
             for j in left_idx..right_idx {
                 if j < anchor.len() && j < p.len() {
@@ -506,21 +809,21 @@ impl SRCalculator {
             }
         }
 
-        Self::smooth_on_corners(&p, base_corners, 500.0, 0.001, SmoothMode::Sum)
+        math::smooth_on_corners(base_corners, &p, window, 0.001, SmoothMode::Sum)
     }
 
-    fn compute_abar(_k: i32, delta_ks: &[Vec<f64>], active_columns: &[Vec<usize>], a_corners: &[f64], base_corners: &[f64]) -> Vec<f64> {
+    fn compute_abar(k: i32, delta_ks: &[CornerRuns<f64>], key_usage: &[u32], a_corners: &[f64], base_corners: &[f64], window: f64) -> Vec<f64> {
         let mut a_step = vec![1.0; a_corners.len()];
         for i in 0..a_corners.len() {
             let s = a_corners[i];
-            let idx = Self::bisect_left(base_corners, s);
-            let idx = idx.min(active_columns.len().saturating_sub(1));
-            let cols = &active_columns[idx];
+            let idx = math::bisect_left(base_corners, s);
+            let idx = idx.min(key_usage.len().saturating_sub(1));
+            let cols: Vec<usize> = (0..k as usize).filter(|&col| Self::in_use(key_usage[idx], col)).collect();
             for j in 0..cols.len().saturating_sub(1) {
-                let c0 = cols[j];
-                let c1 = cols[j + 1];
-                let delta_gap = (delta_ks[c0][idx] - delta_ks[c1][idx]).abs();
-                let max_delta = delta_ks[c0][idx].max(delta_ks[c1][idx]);
+                let delta0 = delta_ks[cols[j]].get(idx);
+                let delta1 = delta_ks[cols[j + 1]].get(idx);
+                let delta_gap = (delta0 - delta1).abs();
+                let max_delta = delta0.max(delta1);
                 let offset = (max_delta - 0.11).max(0.0);
                 let offset_contribution = 0.4 * offset;
                 let diff = delta_gap + offset_contribution;
@@ -538,18 +841,20 @@ impl SRCalculator {
                 }
             }
         }
-        Self::smooth_on_corners(&a_step, a_corners, 250.0, 0.0, SmoothMode::Average)
+        math::smooth_on_corners(a_corners, &a_step, window, 0.0, SmoothMode::Average)
     }
 
-    fn compute_rbar(x: f64, note_seq_by_column: &[Vec<(i32, i32, i32)>], tail_seq: &[(i32, i32, i32)], base_corners: &[f64]) -> Vec<f64> {
+    fn compute_rbar(x: f64, note_seq_by_column: &[Vec<Note>], tail_seq: &[Note], base_corners: &[f64], window: f64) -> Vec<f64> {
         let mut i_vals = vec![0.0; tail_seq.len()];
 
         for i in 0..tail_seq.len() {
-            let (k, h_i, t_i) = tail_seq[i];
+            let Note { k, h: h_i, t: t_i } = tail_seq[i];
             let column_notes = if (k as usize) < note_seq_by_column.len() { &note_seq_by_column[k as usize] } else { &vec![] };
 
-            let index = column_notes.iter().position(|&n| n.1 == h_i).unwrap_or(0);
-            let next_note_time = if index + 1 < column_notes.len() { column_notes[index + 1].1 } else { 1000000000 };
+            // First note of the column with this head; the column is sorted by head
+            let index = column_notes.partition_point(|n| n.h < h_i);
+            let index = if column_notes.get(index).is_some_and(|n| n.h == h_i) { index } else { 0 };
+            let next_note_time = if index + 1 < column_notes.len() { column_notes[index + 1].h } else { 1000000000 };
 
             let i_h = 0.001 * ((t_i - h_i - 80) as f64).abs() / x;
             let i_t = 0.001 * ((next_note_time - t_i - 80) as f64).abs() / x;
@@ -559,13 +864,13 @@ impl SRCalculator {
         let mut r = vec![0.0; base_corners.len()];
 
         for i in 0..tail_seq.len().saturating_sub(1) {
-            let (_, _, t_i) = tail_seq[i];
-            let (_, _, t_next) = tail_seq[i + 1];
+            let t_i = tail_seq[i].t;
+            let t_next = tail_seq[i + 1].t;
             let delta_r = 0.001 * (t_next - t_i) as f64;
             let r_val = 0.08 * delta_r.powf(-0.5) / x * (1.0 + 0.8 * (i_vals[i] + i_vals[i + 1]));
 
-            let left_idx = base_corners.partition_point(|&x| x < t_i as f64);
-            let right_idx = base_corners.partition_point(|&x| x < t_next as f64);
+            let left_idx = math::bisect_left(base_corners, t_i as f64);
+            let right_idx = math::bisect_left(base_corners, t_next as f64);
 
             for s in left_idx..right_idx {
                 if s < r.len() {
@@ -574,94 +879,29 @@ impl SRCalculator {
             }
         }
 
-        Self::smooth_on_corners(&r, base_corners, 500.0, 0.001, SmoothMode::Sum)
+        math::smooth_on_corners(base_corners, &r, window, 0.001, SmoothMode::Sum)
     }
 
-    fn compute_c_and_ks(k: i32, note_seq: &[(i32, i32, i32)], key_usage: &[Vec<bool>], base_corners: &[f64]) -> (Vec<f64>, Vec<f64>) {
-        let mut note_times: Vec<f64> = note_seq.iter().map(|&(_, h, _)| h as f64).collect();
+    fn compute_c_and_ks(note_seq: &[Note], key_usage: &[u32], base_corners: &[f64], window: f64) -> (Vec<f64>, Vec<f64>) {
+        let mut note_times: Vec<f64> = note_seq.iter().map(|n| n.h as f64).collect();
         note_times.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
         let mut c_step = vec![0.0; base_corners.len()];
         for i in 0..base_corners.len() {
-            let left = base_corners[i] - 500.0;
-            let right = base_corners[i] + 500.0;
-            let left_index = Self::bisect_left(&note_times, left);
-            let right_index = Self::bisect_left(&note_times, right);
+            let left = base_corners[i] - window;
+            let right = base_corners[i] + window;
+            let left_index = math::bisect_left(&note_times, left);
+            let right_index = math::bisect_left(&note_times, right);
             c_step[i] = (right_index - left_index) as f64;
         }
 
-        let mut ks_step = vec![0.0; base_corners.len()];
-        for i in 0..base_corners.len() {
-            let mut active_count = 0;
-            for col in 0..k as usize {
-                if key_usage[col][i] {
-                    active_count += 1;
-                }
-            }
-            ks_step[i] = active_count.max(1) as f64;
-        }
+        let ks_step = key_usage.iter().map(|usage| usage.count_ones().max(1) as f64).collect();
 
         (c_step, ks_step)
     }
 
-    fn interp_values(new_x: &[f64], old_x: &[f64], old_vals: &[f64]) -> Vec<f64> {
-        new_x.iter().map(|&x| {
-            if x <= old_x[0] {
-                old_vals[0]
-            } else if x >= old_x[old_x.len() - 1] {
-                old_vals[old_vals.len() - 1]
-            } else {
-                let idx = old_x.partition_point(|&val| val < x) - 1;
-                let frac = (x - old_x[idx]) / (old_x[idx + 1] - old_x[idx]);
-                old_vals[idx] + frac * (old_vals[idx + 1] - old_vals[idx])
-            }
-        }).collect()
-    }
-
-    fn step_interp(new_x: &[f64], old_x: &[f64], old_vals: &[f64]) -> Vec<f64> {
-        new_x.iter().map(|&x| {
-            let idx = old_x.partition_point(|&val| val < x).saturating_sub(1);
-            old_vals[idx.min(old_vals.len() - 1)]
-        }).collect()
-    }
-
-    fn cumulative_sum(x: &[f64], f: &[f64]) -> Vec<f64> {
-        let mut f_cum = vec![0.0; x.len()];
-        for i in 1..x.len() {
-            f_cum[i] = f_cum[i - 1] + f[i - 1] * (x[i] - x[i - 1]);
-        }
-        f_cum
-    }
-
-    fn query_cumsum(q: f64, x: &[f64], f_cum: &[f64], f: &[f64]) -> f64 {
-        if q <= x[0] {
-            return 0.0;
-        }
-        if q >= x[x.len() - 1] {
-            return f_cum[f_cum.len() - 1];
-        }
-        let i = x.partition_point(|&val| val < q).saturating_sub(1);
-        f_cum[i] + f[i] * (q - x[i])
-    }
-
-    fn smooth_on_corners(f: &[f64], x: &[f64], window: f64, scale: f64, mode: SmoothMode) -> Vec<f64> {
-        let f_cum = Self::cumulative_sum(x, f);
-        let mut g = vec![0.0; f.len()];
-        for i in 0..x.len() {
-            let s = x[i];
-            let a = (s - window).max(x[0]);
-            let b = (s + window).min(x[x.len() - 1]);
-            let integral = Self::query_cumsum(b, x, &f_cum, f) - Self::query_cumsum(a, x, &f_cum, f);
-            if mode == SmoothMode::Average {
-                g[i] = integral / (b - a).max(1e-9);
-            } else {
-                g[i] = integral * scale;
-            }
-        }
-        g
-    }
-
-    fn calculate_final_sr(
+    /// Combines the skill curves into the difficulty D at each corner.
+    fn difficulty_curve(
         jbar: &[f64],
         xbar: &[f64],
         pbar: &[f64],
@@ -669,29 +909,10 @@ impl SRCalculator {
         rbar: &[f64],
         c_arr: &[f64],
         ks_arr: &[f64],
-        note_seq: &[(i32, i32, i32)],
-        ln_seq: &[(i32, i32, i32)],
-        all_corners: &[f64],
-    ) -> f64 {
-        // Compute gaps for all_corners
-        let mut gaps = vec![0.0; all_corners.len()];
-        if all_corners.len() > 1 {
-            gaps[0] = (all_corners[1] - all_corners[0]) / 2.0;
-            gaps[all_corners.len() - 1] = (all_corners[all_corners.len() - 1] - all_corners[all_corners.len() - 2]) / 2.0;
-            for i in 1..all_corners.len() - 1 {
-                gaps[i] = (all_corners[i + 1] - all_corners[i - 1]) / 2.0;
-            }
-        }
-
-        // Compute effective weights
-        let mut effective_weights = vec![0.0; all_corners.len()];
-        for i in 0..all_corners.len() {
-            effective_weights[i] = c_arr[i] * gaps[i];
-        }
-
+    ) -> Vec<f64> {
         // Compute d_all
-        let mut d_all = vec![0.0; all_corners.len()];
-        for i in 0..all_corners.len() {
+        let mut d_all = vec![0.0; jbar.len()];
+        for i in 0..jbar.len() {
             let abar_exp = 3.0 / ks_arr[i].max(1e-6);
             let abar_pow = if abar[i] <= 0.0 { 0.0 } else { abar[i].powf(abar_exp) };
             let min_candidate_contribution = 0.85 * jbar[i];
@@ -719,18 +940,42 @@ impl SRCalculator {
             d_all[i] = primary_impact + secondary_impact;
         }
 
-        // Finalise difficulty
-        Self::finalise_difficulty(&d_all, &effective_weights, note_seq, ln_seq)
+        d_all
     }
 
-    fn finalise_difficulty(
-        difficulties: &[f64],
-        weights: &[f64],
-        note_seq: &[(i32, i32, i32)],
-        ln_seq: &[(i32, i32, i32)],
-    ) -> f64 {
+    /// Weight of each corner: local note density times the time the corner stands for.
+    fn effective_weights(all_corners: &[f64], c_arr: &[f64]) -> Vec<f64> {
+        // Compute gaps for all_corners
+        let mut gaps = vec![0.0; all_corners.len()];
+        if all_corners.len() > 1 {
+            gaps[0] = (all_corners[1] - all_corners[0]) / 2.0;
+            gaps[all_corners.len() - 1] = (all_corners[all_corners.len() - 1] - all_corners[all_corners.len() - 2]) / 2.0;
+            for i in 1..all_corners.len() - 1 {
+                gaps[i] = (all_corners[i + 1] - all_corners[i - 1]) / 2.0;
+            }
+        }
+
+        let mut effective_weights = vec![0.0; all_corners.len()];
+        for i in 0..all_corners.len() {
+            effective_weights[i] = c_arr[i] * gaps[i];
+        }
+        effective_weights
+    }
+
+    fn summarise(values: &[f64], weights: &[f64], params: &SrParams) -> SkillSummary {
+        let (sorted, sorted_weights, norm, total_weight) = Self::sort_weighted(values, weights);
+        if sorted.is_empty() {
+            return SkillSummary::default();
+        }
+        let mean = sorted.iter().zip(&sorted_weights).map(|(v, w)| v * w).sum::<f64>() / total_weight;
+        SkillSummary { mean, percentile93: Self::weighted_percentile(&sorted, &norm, &params.top_percentiles) }
+    }
+
+    /// Sorts `values` ascending with their weights. Returns the sorted values and weights,
+    /// the normalised cumulative weights and the total weight.
+    fn sort_weighted(values: &[f64], weights: &[f64]) -> (Vec<f64>, Vec<f64>, Vec<f64>, f64) {
         // Combine and sort by difficulty, stable sort to match C#
-        let mut combined: Vec<(usize, f64, f64)> = difficulties.iter().enumerate().map(|(idx, &d)| (idx, d, weights[idx].max(0.0))).collect();
+        let mut combined: Vec<(usize, f64, f64)> = values.iter().enumerate().map(|(idx, &d)| (idx, d, weights[idx].max(0.0))).collect();
         combined.sort_by(|a, b| {
             use std::cmp::Ordering;
             if a.1.is_nan() && b.1.is_nan() {
@@ -746,11 +991,11 @@ impl SRCalculator {
                 }
             }
         });
-        
+
         if combined.is_empty() {
-            return 0.0;
+            return (vec![], vec![], vec![], 0.0);
         }
-        
+
         let sorted_d: Vec<f64> = combined.iter().map(|(_, d, _)| *d).collect();
         let sorted_weights: Vec<f64> = combined.iter().map(|(_, _, w)| *w).collect();
 
@@ -763,24 +1008,33 @@ impl SRCalculator {
 
         let total_weight = cumulative.last().unwrap().max(1e-9);
         let norm: Vec<f64> = cumulative.iter().map(|&v| v / total_weight).collect();
+        (sorted_d, sorted_weights, norm, total_weight)
+    }
 
-        let targets = [0.945, 0.935, 0.925, 0.915, 0.845, 0.835, 0.825, 0.815];
-
-        // Percentile 93
-        let mut percentile93 = 0.0;
-        for i in 0..4 {
-            let index = Self::bisect_left(&norm, targets[i]).min(sorted_d.len() - 1);
-            percentile93 += sorted_d[index];
+    /// Average of the values at the given normalised cumulative weights.
+    fn weighted_percentile(sorted_d: &[f64], norm: &[f64], targets: &[f64]) -> f64 {
+        let mut sum = 0.0;
+        for &target in targets {
+            let index = math::bisect_left(norm, target).min(sorted_d.len() - 1);
+            sum += sorted_d[index];
         }
-        percentile93 /= 4.0;
+        sum / targets.len() as f64
+    }
 
-        // Percentile 83
-        let mut percentile83 = 0.0;
-        for i in 4..8 {
-            let index = Self::bisect_left(&norm, targets[i]).min(sorted_d.len() - 1);
-            percentile83 += sorted_d[index];
+    fn finalise_difficulty(
+        difficulties: &[f64],
+        weights: &[f64],
+        note_seq: &[Note],
+        ln_seq: &[Note],
+        params: &SrParams,
+    ) -> f64 {
+        let (sorted_d, sorted_weights, norm, total_weight) = Self::sort_weighted(difficulties, weights);
+        if sorted_d.is_empty() {
+            return 0.0;
         }
-        percentile83 /= 4.0;
+
+        let percentile93 = Self::weighted_percentile(&sorted_d, &norm, &params.top_percentiles);
+        let percentile83 = Self::weighted_percentile(&sorted_d, &norm, &params.middle_percentiles);
 
         // Weighted mean
         let mut weighted_mean_numerator = 0.0;
@@ -790,48 +1044,38 @@ impl SRCalculator {
         let weighted_mean = (weighted_mean_numerator / total_weight).max(0.0).powf(0.2);
 
         // SR calculation
-        let top_component = 0.25 * 0.88 * percentile93;
-        let middle_component = 0.2 * 0.94 * percentile83;
-        let mean_component = 0.55 * weighted_mean;
+        let top_component = params.top_weight * params.top_scale * percentile93;
+        let middle_component = params.middle_weight * params.middle_scale * percentile83;
+        let mean_component = params.mean_weight * weighted_mean;
         let mut sr = top_component + middle_component + mean_component;
         sr = sr.powf(1.0) / 8.0_f64.powf(1.0) * 8.0;
 
         // Total notes adjustment
         let mut total_notes = note_seq.len() as f64;
-        for &(_, h, t) in ln_seq {
+        for &Note { h, t, .. } in ln_seq {
             if t >= 0 {
                 let len = (t - h).min(1000) as f64;
                 total_notes += 0.5 * (len / 200.0);
             }
         }
 
-        sr *= total_notes / (total_notes + 60.0);
-        sr = Self::rescale_high(sr);
-        sr *= 0.975;
+        sr *= total_notes / (total_notes + params.note_count_damping);
+        sr = Self::rescale_high(sr, params);
+        sr *= params.final_scale;
 
         sr
     }
 
-    fn bisect_left(arr: &[f64], target: f64) -> usize {
-        let mut low = 0;
-        let mut high = arr.len();
-
-        while low < high {
-            let mid = (low + high) / 2;
-            if arr[mid] < target {
-                low = mid + 1;
-            } else {
-                high = mid;
-            }
-        }
-        low
+    /// Runs `a` and `b` with `rayon::join` in parallel mode, otherwise one after the other.
+    pub(crate) fn join<A: Send, B: Send>(parallel: bool, a: impl FnOnce() -> A + Send, b: impl FnOnce() -> B + Send) -> (A, B) {
+        if parallel { rayon::join(a, b) } else { (a(), b()) }
     }
 
-    fn rescale_high(sr: f64) -> f64 {
-        if sr <= 9.0 {
+    fn rescale_high(sr: f64, params: &SrParams) -> f64 {
+        if sr <= params.rescale_knee {
             sr
         } else {
-            9.0 + (sr - 9.0) * (1.0 / 1.2)
+            params.rescale_knee + (sr - params.rescale_knee) * (1.0 / params.rescale_divisor)
         }
     }
 }
@@ -0,0 +1,292 @@
+//! The dense formulation the corner runs in `sr.rs` replaced: key usage, anchor usage, column gaps and
+//! cross-column values each held as a `K × corners` matrix. Only kept as the baseline for
+//! `test_sparse_matches_dense` and `benches/dense.rs`, which need `--features dense-reference`.
+
+use super::SRCalculator;
+use crate::config::SrConfig;
+use crate::error::SrError;
+use crate::math::{self, SmoothMode};
+use crate::note::{Note, NoteComparerByT};
+
+/// SR of `notes` with the dense stages, otherwise the same steps as [`SRCalculator::calculate_sr_with_config`].
+/// Always sequential; `config.parallel`, `note_mods` and `algorithm` are ignored.
+pub fn calculate_sr_with_config(notes: &[Note], key_count: i32, od: f64, config: &SrConfig) -> Result<f64, SrError> {
+    let k = key_count;
+    if !(1..=18).contains(&k) {
+        return Err(SrError::UnsupportedKeyCount(k));
+    }
+    if !od.is_finite() || od < 0.0 {
+        return Err(SrError::InvalidOd(od));
+    }
+    config.validate()?;
+
+    let mut note_seq: Vec<Note> = notes
+        .iter()
+        .map(|n| Note::new(n.k.clamp(0, k - 1), config.scale_time(n.h), if n.t >= 0 { config.scale_time(n.t) } else { -1 }))
+        .collect();
+    if note_seq.is_empty() {
+        return Err(SrError::EmptyMap);
+    }
+    note_seq.sort();
+
+    let great_window = config.great_window(od);
+    if great_window <= 0.0 {
+        return Err(SrError::InvalidOd(od));
+    }
+    let x = 0.3 * (great_window / 500.0).sqrt();
+    let x = x.min(0.6 * (x - 0.09) + 0.09);
+    let params = &config.params;
+
+    let mut note_seq_by_column: Vec<Vec<Note>> = vec![vec![]; k as usize];
+    for &note in &note_seq {
+        note_seq_by_column[note.k as usize].push(note);
+    }
+    let ln_seq: Vec<Note> = note_seq.iter().filter(|n| n.t >= 0).cloned().collect();
+    let mut tail_seq = ln_seq.clone();
+    tail_seq.sort_by(NoteComparerByT::cmp);
+    let t = note_seq.iter().map(|n| n.h.max(n.t)).max().unwrap() + 1;
+
+    let (all_corners, base_corners, a_corners) = SRCalculator::get_corners(t, &note_seq, params);
+    let key_usage = get_key_usage(k, t, &note_seq, &base_corners, params.key_usage_span);
+    let active_columns = derive_active_columns(&key_usage);
+    let cross_matrix = config.cross_matrix(k as usize);
+
+    let (delta_ks, jbar) = compute_jbar(k, &note_seq_by_column, &base_corners, x, params.smoothing_window);
+    let abar = compute_abar(&delta_ks, &active_columns, &a_corners, &base_corners, params.unevenness_window);
+    let xbar = compute_xbar(k, x, cross_matrix.as_deref(), &note_seq_by_column, &active_columns, &base_corners, params.smoothing_window);
+    let key_usage_400 = get_key_usage_400(k, &note_seq, &base_corners, params.anchor_span);
+    let anchor = compute_anchor(k, &key_usage_400, &base_corners);
+    let ln_rep = if !ln_seq.is_empty() { Some(SRCalculator::build_ln_representation(&ln_seq, t)) } else { None };
+    let pbar = SRCalculator::compute_pbar(x, &note_seq, ln_rep.as_ref(), &anchor, &base_corners, params.smoothing_window);
+    let rbar = SRCalculator::compute_rbar(x, &note_seq_by_column, &tail_seq, &base_corners, params.smoothing_window);
+    let (c_arr, ks_arr) = compute_c_and_ks(k, &note_seq, &key_usage, &base_corners, params.density_window);
+
+    let c_arr_interp = math::step_interp(&all_corners, &base_corners, &c_arr);
+    let difficulty = SRCalculator::difficulty_curve(
+        &math::interp_values(&all_corners, &base_corners, &jbar),
+        &math::interp_values(&all_corners, &base_corners, &xbar),
+        &math::interp_values(&all_corners, &base_corners, &pbar),
+        &math::interp_values(&all_corners, &a_corners, &abar),
+        &math::interp_values(&all_corners, &base_corners, &rbar),
+        &c_arr_interp,
+        &math::step_interp(&all_corners, &base_corners, &ks_arr),
+    );
+    let weights = SRCalculator::effective_weights(&all_corners, &c_arr_interp);
+    Ok(SRCalculator::finalise_difficulty(&difficulty, &weights, &note_seq, &ln_seq, params))
+}
+
+fn derive_active_columns(key_usage: &[Vec<bool>]) -> Vec<Vec<usize>> {
+    let length = key_usage[0].len();
+    let mut active = vec![vec![]; length];
+    for (i, cols) in active.iter_mut().enumerate() {
+        for (col, usage) in key_usage.iter().enumerate() {
+            if usage[i] {
+                cols.push(col);
+            }
+        }
+    }
+    active
+}
+
+fn get_key_usage(k: i32, total_time: i32, note_seq: &[Note], base_corners: &[f64], span: f64) -> Vec<Vec<bool>> {
+    let mut key_usage = vec![vec![false; base_corners.len()]; k as usize];
+    for &Note { k: col, h, t: tail } in note_seq {
+        let start = (h as f64 - span).max(0.0);
+        let end = if tail >= 0 { (tail as f64 + span).min(total_time as f64 - 1.0) } else { (h as f64 + span).min(total_time as f64 - 1.0) };
+        let left = math::bisect_left(base_corners, start);
+        let right = math::bisect_left(base_corners, end);
+        for used in &mut key_usage[col as usize][left..right] {
+            *used = true;
+        }
+    }
+    key_usage
+}
+
+fn add_falloff_contribution(usage: &mut [f64], base_corners: &[f64], range: std::ops::Range<usize>, ref_time: f64, span: f64) {
+    for idx in range {
+        usage[idx] += SRCalculator::falloff_contribution(base_corners[idx], ref_time, span);
+    }
+}
+
+fn get_key_usage_400(k: i32, note_seq: &[Note], base_corners: &[f64], span: f64) -> Vec<Vec<f64>> {
+    let mut usage = vec![vec![0.0; base_corners.len()]; k as usize];
+    for &Note { k: col, h, t: tail } in note_seq {
+        let start_time = h.max(0) as f64;
+        let end_time = if tail < 0 { h as f64 } else { tail as f64 };
+        let left400_idx = math::bisect_left(base_corners, start_time - span);
+        let left_idx = math::bisect_left(base_corners, start_time);
+        let right_idx = math::bisect_left(base_corners, end_time);
+        let right400_idx = math::bisect_left(base_corners, end_time + span);
+
+        let duration = end_time - start_time;
+        let contribution = 3.75 + duration.min(1500.0) / 150.0;
+        let column = &mut usage[col as usize];
+        for value in &mut column[left_idx..right_idx] {
+            *value += contribution;
+        }
+        add_falloff_contribution(column, base_corners, left400_idx..left_idx, start_time, span);
+        add_falloff_contribution(column, base_corners, right_idx..right400_idx, end_time, span);
+    }
+    usage
+}
+
+fn compute_anchor(k: i32, key_usage_400: &[Vec<f64>], base_corners: &[f64]) -> Vec<f64> {
+    let mut anchor = vec![0.0; base_corners.len()];
+    for (i, anchor) in anchor.iter_mut().enumerate() {
+        let mut counts: Vec<f64> = (0..k as usize).map(|col| key_usage_400[col][i]).collect();
+        counts.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        let non_zero: Vec<f64> = counts.into_iter().filter(|&c| c > 0.0).collect();
+        if non_zero.len() <= 1 {
+            continue;
+        }
+        let mut walk = 0.0;
+        let mut max_walk = 0.0;
+        for pair in non_zero.windows(2) {
+            let offset = 0.5 - pair[1] / pair[0];
+            walk += pair[0] * (1.0 - 4.0 * offset * offset);
+            max_walk += pair[0];
+        }
+        let value = walk / f64::max(max_walk, 1e-9);
+        *anchor = 1.0 + (value - 0.18).min(5.0 * (value - 0.22).powf(3.0));
+    }
+    anchor
+}
+
+fn compute_jbar(k: i32, note_seq_by_column: &[Vec<Note>], base_corners: &[f64], x: f64, window: f64) -> (Vec<Vec<f64>>, Vec<f64>) {
+    let mut jbar_ks = Vec::with_capacity(k as usize);
+    let mut delta_ks = Vec::with_capacity(k as usize);
+    for notes in note_seq_by_column.iter().take(k as usize) {
+        let mut j_k = vec![0.0; base_corners.len()];
+        let mut delta_k = vec![1e9; base_corners.len()];
+        for pair in notes.windows(2) {
+            let (h1, h2) = (pair[0].h, pair[1].h);
+            let delta = 0.001 * (h2 - h1) as f64;
+            if delta < 1e-9 {
+                continue;
+            }
+            let temp = 0.15 + (delta - 0.08).abs();
+            let jack = 1.0 - 7e-5 / (temp * temp * temp * temp);
+            let val = 1.0 / (delta * (delta + 0.11 * x.powf(0.25))) * jack;
+
+            let left_idx = base_corners.partition_point(|&x| x < h1 as f64);
+            let right_idx = base_corners.partition_point(|&x| x < h2 as f64);
+            for j in left_idx..right_idx {
+                j_k[j] = val;
+                delta_k[j] = f64::min(delta_k[j], delta);
+            }
+        }
+        jbar_ks.push(math::smooth_on_corners(base_corners, &j_k, window, 0.001, SmoothMode::Sum));
+        delta_ks.push(delta_k);
+    }
+
+    let jbar = (0..base_corners.len())
+        .map(|i| {
+            let mut weighted_sum = 0.0;
+            let mut total_weight = 0.0;
+            for col in 0..k as usize {
+                let weight = 1.0 / f64::max(delta_ks[col][i], 1e-9);
+                weighted_sum += jbar_ks[col][i].max(0.0).powf(5.0) * weight;
+                total_weight += weight;
+            }
+            let combined = if total_weight <= 0.0 { 0.0 } else { weighted_sum / total_weight };
+            combined.max(0.0).powf(0.2)
+        })
+        .collect();
+    (delta_ks, jbar)
+}
+
+fn compute_xbar(
+    k: i32,
+    x: f64,
+    matrix: Option<&[f64]>,
+    note_seq_by_column: &[Vec<Note>],
+    active_columns: &[Vec<usize>],
+    base_corners: &[f64],
+    window: f64,
+) -> Vec<f64> {
+    let Some(matrix) = matrix.filter(|m| m.len() == (k + 1) as usize) else {
+        return vec![0.0; base_corners.len()];
+    };
+
+    let mut x_ks = vec![vec![0.0; base_corners.len()]; (k + 1) as usize];
+    let mut fast_cross = vec![vec![0.0; base_corners.len()]; (k + 1) as usize];
+    for col in 0..(k + 1) as usize {
+        let notes_in_pair = if col == 0 {
+            note_seq_by_column[0].clone()
+        } else if col == k as usize {
+            note_seq_by_column[k as usize - 1].clone()
+        } else {
+            let mut notes = [note_seq_by_column[col - 1].as_slice(), note_seq_by_column[col].as_slice()].concat();
+            notes.sort_by_key(|a| a.h);
+            notes
+        };
+
+        for pair in notes_in_pair.windows(2) {
+            let (h1, h2) = (pair[0].h, pair[1].h);
+            let delta = 0.001 * (h2 - h1) as f64;
+            let max_xd = x.max(delta);
+            let mut val = 0.16 / (max_xd * max_xd);
+
+            let left_idx = base_corners.partition_point(|&x| x < h1 as f64);
+            let right_idx = base_corners.partition_point(|&x| x < h2 as f64);
+            let idx_start = left_idx.min(active_columns.len().saturating_sub(1));
+            let idx_end = right_idx.min(active_columns.len().saturating_sub(1));
+
+            let condition1 = col == 0 || (!active_columns[idx_start].contains(&(col - 1)) && !active_columns[idx_end].contains(&(col - 1)));
+            let condition2 = !active_columns[idx_start].contains(&col) && !active_columns[idx_end].contains(&col);
+            if condition1 || condition2 {
+                val *= 1.0 - matrix[col];
+            }
+
+            for j in left_idx..right_idx {
+                x_ks[col][j] = val;
+                fast_cross[col][j] = (0.4 * (delta.max(0.06).max(0.75 * x)).powf(-2.0) - 80.0).max(0.0);
+            }
+        }
+    }
+
+    let mut x_base = vec![0.0; base_corners.len()];
+    for (i, x_base) in x_base.iter_mut().enumerate() {
+        for col in 0..(k + 1) as usize {
+            *x_base += x_ks[col][i] * matrix[col];
+        }
+        for col in 0..k as usize {
+            let left_contrib = fast_cross[col][i] * matrix[col];
+            let right_contrib = fast_cross[col + 1][i] * matrix[col + 1];
+            *x_base += (left_contrib * right_contrib).max(0.0).sqrt();
+        }
+    }
+    math::smooth_on_corners(base_corners, &x_base, window, 0.001, SmoothMode::Sum)
+}
+
+fn compute_abar(delta_ks: &[Vec<f64>], active_columns: &[Vec<usize>], a_corners: &[f64], base_corners: &[f64], window: f64) -> Vec<f64> {
+    let mut a_step = vec![1.0; a_corners.len()];
+    for (i, a_step) in a_step.iter_mut().enumerate() {
+        let idx = math::bisect_left(base_corners, a_corners[i]).min(active_columns.len().saturating_sub(1));
+        for pair in active_columns[idx].windows(2) {
+            let (delta0, delta1) = (delta_ks[pair[0]][idx], delta_ks[pair[1]][idx]);
+            let max_delta = delta0.max(delta1);
+            let diff = (delta0 - delta1).abs() + 0.4 * (max_delta - 0.11).max(0.0);
+            if diff < 0.02 {
+                *a_step *= (0.75 + 0.5 * max_delta).min(1.0);
+            } else if diff < 0.07 {
+                *a_step *= (0.65 + 5.0 * diff + 0.5 * max_delta).min(1.0);
+            }
+        }
+    }
+    math::smooth_on_corners(a_corners, &a_step, window, 0.0, SmoothMode::Average)
+}
+
+fn compute_c_and_ks(k: i32, note_seq: &[Note], key_usage: &[Vec<bool>], base_corners: &[f64], window: f64) -> (Vec<f64>, Vec<f64>) {
+    let mut note_times: Vec<f64> = note_seq.iter().map(|n| n.h as f64).collect();
+    note_times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let c_step = base_corners
+        .iter()
+        .map(|&corner| (math::bisect_left(&note_times, corner + window) - math::bisect_left(&note_times, corner - window)) as f64)
+        .collect();
+    let ks_step = (0..base_corners.len())
+        .map(|i| key_usage.iter().take(k as usize).filter(|usage| usage[i]).count().max(1) as f64)
+        .collect();
+    (c_step, ks_step)
+}
@@ -0,0 +1,368 @@
+use crate::config::{SrConfig, MAX_V30_SPAN_MS};
+use crate::error::SrError;
+use crate::math::{self, SmoothMode};
+use crate::note::{Note, NoteComparerByT};
+use crate::sr::{SRCalculator, SkillCurves};
+
+// Cross matrices of SR-V3.0, indexed by K. Differs from `CrossMatrixProvider` at 10K and has no odd K above 9.
+const V30_MATRICES: [&[f64]; 19] = [
+    &[-1.0],
+    &[0.075, 0.075],
+    &[0.125, 0.05, 0.125],
+    &[0.125, 0.125, 0.125, 0.125],
+    &[0.175, 0.25, 0.05, 0.25, 0.175],
+    &[0.175, 0.25, 0.175, 0.175, 0.25, 0.175],
+    &[0.225, 0.35, 0.25, 0.05, 0.25, 0.35, 0.225],
+    &[0.225, 0.35, 0.25, 0.225, 0.225, 0.25, 0.35, 0.225],
+    &[0.275, 0.45, 0.35, 0.25, 0.05, 0.25, 0.35, 0.45, 0.275],
+    &[0.275, 0.45, 0.35, 0.25, 0.275, 0.275, 0.25, 0.35, 0.45, 0.275],
+    &[0.625, 0.55, 0.45, 0.35, 0.25, 0.05, 0.25, 0.35, 0.45, 0.55, 0.625],
+    &[],
+    &[0.8, 0.8, 0.8, 0.6, 0.4, 0.2, 0.05, 0.2, 0.4, 0.6, 0.8, 0.8, 0.8],
+    &[],
+    &[0.4, 0.4, 0.2, 0.2, 0.3, 0.3, 0.1, 0.1, 0.3, 0.3, 0.2, 0.2, 0.4, 0.4, 0.4],
+    &[],
+    &[0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.2, 0.1, 0.1, 0.2, 0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.4],
+    &[],
+    &[0.4, 0.4, 0.2, 0.4, 0.2, 0.4, 0.2, 0.3, 0.1, 0.1, 0.3, 0.2, 0.4, 0.2, 0.4, 0.2, 0.4, 0.4, 0.4],
+];
+
+const LAMBDA_N: f64 = 5.0;
+const LAMBDA_1: f64 = 0.11;
+const LAMBDA_2: f64 = 7.0;
+const LAMBDA_3: f64 = 24.0;
+const LAMBDA_4: f64 = 0.1;
+const W_0: f64 = 0.4;
+const W_1: f64 = 2.7;
+const W_2: f64 = 0.27;
+const P_0: f64 = 1.0;
+const P_1: f64 = 1.5;
+
+/// SR-V3.0, ported from the C# implementation including its quirks, so old ratings reproduce.
+pub(crate) struct SrV30;
+
+impl SrV30 {
+    /// Skill curves sampled every millisecond. `weights` holds the note density C the SR is weighted by.
+    pub(crate) fn compute_curves(notes: &[Note], key_count: i32, od: f64, config: &SrConfig) -> Result<SkillCurves, SrError> {
+        let k = key_count;
+        if !(1..=18).contains(&k) || (k > 10 && k % 2 == 1) {
+            return Err(SrError::UnsupportedKeyCount(k));
+        }
+        if !od.is_finite() || od < 0.0 {
+            return Err(SrError::InvalidOd(od));
+        }
+        config.validate()?;
+
+        // Only tails after the head make a hold, as in V3.0
+        let mut note_seq: Vec<Note> = notes
+            .iter()
+            .map(|n| {
+                let (h, t) = (config.scale_time(n.h), config.scale_time(n.t));
+                Note::new(n.k.clamp(0, k - 1), h, if n.t >= 0 && t > h { t } else { -1 })
+            })
+            .collect();
+        if note_seq.is_empty() {
+            return Err(SrError::EmptyMap);
+        }
+        // Checked before anything per-millisecond is allocated; a failed allocation would abort the host
+        let first = note_seq.iter().map(|n| n.h).min().unwrap().min(0) as i64;
+        let last = note_seq.iter().map(|n| n.h.max(n.t)).max().unwrap() as i64;
+        if last - first + 1 > MAX_V30_SPAN_MS {
+            return Err(SrError::MapTooLong { span_ms: last - first + 1, limit_ms: MAX_V30_SPAN_MS });
+        }
+        // The dense arrays start at 0 ms, so notes before 0 (a negative audio offset) are moved for the
+        // first one to land on 0 and the corners moved back after. V3.0 only uses time differences.
+        let origin = first as i32;
+        for note in &mut note_seq {
+            note.h -= origin;
+            if note.t >= 0 {
+                note.t -= origin;
+            }
+        }
+        note_seq.sort();
+
+        let great_window = config.great_window(od);
+        if great_window <= 0.0 {
+            return Err(SrError::InvalidOd(od));
+        }
+        let x = 0.3 * (great_window / 500.0).sqrt();
+
+        // V3.0 groups notes by column and drops empty columns, so with an unused column the group
+        // index no longer matches the column. Kept as is, since ratings depend on it.
+        let mut groups: Vec<Vec<Note>> = vec![vec![]; k as usize];
+        for &note in &note_seq {
+            groups[note.k as usize].push(note);
+        }
+        groups.retain(|group| !group.is_empty());
+
+        // V3.0 sorts tails with an unstable sort; holds ending together may come out in another order
+        let mut ln_seq: Vec<Note> = note_seq.iter().filter(|n| n.t >= 0).cloned().collect();
+        ln_seq.sort_by(NoteComparerByT::cmp);
+
+        let too_long = SrError::MapTooLong { span_ms: last - first + 1, limit_ms: MAX_V30_SPAN_MS };
+        let t = note_seq.iter().map(|n| n.h.max(n.t)).max().unwrap().checked_add(1).ok_or(too_long)?;
+        let len = t as usize;
+
+        let parallel = config.parallel;
+        let (((jbar, (abar, ks)), xbar), (pbar, rbar)) = SRCalculator::join(
+            parallel,
+            || {
+                SRCalculator::join(
+                    parallel,
+                    || {
+                        let (jbar, delta_ks) = Self::jbar(k, &groups, len, x);
+                        (jbar, Self::abar(&delta_ks, k, len, &note_seq))
+                    },
+                    || Self::xbar(k, &groups, len, x),
+                )
+            },
+            || SRCalculator::join(parallel, || Self::pbar(&ln_seq, &note_seq, len, x), || Self::rbar(&ln_seq, &groups, len, x)),
+        );
+
+        let mut c = vec![0.0; len];
+        let (mut start, mut end) = (0, 0);
+        for (time, value) in c.iter_mut().enumerate() {
+            let time = time as i32;
+            while start < note_seq.len() && note_seq[start].h < time - 500 {
+                start += 1;
+            }
+            while end < note_seq.len() && note_seq[end].h < time + 500 {
+                end += 1;
+            }
+            *value = (end - start) as f64;
+        }
+
+        let (jbar, xbar, pbar, abar, rbar) = (Self::non_negative(jbar), Self::non_negative(xbar), Self::non_negative(pbar), Self::non_negative(abar), Self::non_negative(rbar));
+        let mut difficulty = vec![0.0; len];
+        for s in 0..len {
+            let abar_pow = abar[s].powf(3.0 / ks[s]);
+            let term1 = (W_0 * (abar_pow * jbar[s]).powf(1.5)).powf(1.0);
+            let term2 = ((1.0 - W_0) * (abar[s].powf(2.0 / 3.0) * (0.8 * pbar[s] + rbar[s])).powf(1.5)).powf(1.0);
+            let strain = (term1 + term2).powf(2.0 / 3.0);
+            let t_value = abar_pow * xbar[s] / (xbar[s] + strain + 1.0);
+            difficulty[s] = W_1 * strain.powf(0.5) * t_value.powf(P_1) + strain * W_2;
+        }
+        Self::forward_fill(&mut difficulty);
+        Self::forward_fill(&mut c);
+
+        Ok(SkillCurves {
+            corners: (0..t).map(|time| (time + origin) as f64).collect(),
+            jbar,
+            xbar,
+            pbar,
+            abar,
+            rbar,
+            c_arr: c.clone(),
+            ks_arr: ks,
+            weights: c,
+            difficulty,
+            note_seq,
+            ln_seq,
+        })
+    }
+
+    /// Power mean of the difficulty weighted by C, then V3.0's length and key count scaling.
+    pub(crate) fn finalise_difficulty(curves: &SkillCurves, key_count: i32) -> f64 {
+        let weight_sum: f64 = curves.weights.iter().sum();
+        let mut weighted_sum = 0.0;
+        for (d, c) in curves.difficulty.iter().zip(&curves.weights) {
+            weighted_sum += d.powf(LAMBDA_N) * c;
+        }
+
+        let mut sr = (weighted_sum / weight_sum).powf(1.0 / LAMBDA_N);
+        sr = sr.powf(P_0) / 8.0_f64.powf(P_0) * 8.0;
+        let notes = curves.note_seq.len() as f64 + 0.5 * curves.ln_seq.len() as f64;
+        sr *= notes / (notes + 60.0);
+        if sr <= 2.0 {
+            sr = (sr * 2.0).sqrt();
+        }
+        sr * (0.96 + 0.01 * key_count as f64)
+    }
+
+    // V3.0 smooths over whole milliseconds `[s - 500, s + 500)`, clipped to the map. Taking the
+    // milliseconds 0..=len as corners, the value at `len` is never integrated and the clipped window
+    // ends at `len`, so `math::smooth_on_corners` gives exactly that window.
+    fn smooth(values: &[f64], mode: SmoothMode) -> Vec<f64> {
+        let corners: Vec<f64> = (0..=values.len()).map(|s| s as f64).collect();
+        let padded = [values, &[0.0]].concat();
+        let mut smoothed = math::smooth_on_corners(&corners, &padded, 500.0, 0.001, mode);
+        smoothed.pop();
+        smoothed
+    }
+
+    fn jbar(k: i32, groups: &[Vec<Note>], len: usize, x: f64) -> (Vec<f64>, Vec<Vec<f64>>) {
+        let lambda1_x = LAMBDA_1 * x.sqrt().sqrt();
+        let mut j_ks = vec![vec![0.0; len]; k as usize];
+        let mut delta_ks = vec![vec![1e9; len]; k as usize];
+        for (col, notes) in groups.iter().enumerate().take(k as usize) {
+            for pair in notes.windows(2) {
+                let delta = 0.001 * (pair[1].h - pair[0].h) as f64;
+                let temp = 0.15 + (delta - 0.08).abs();
+                let temp4 = temp * temp * temp * temp;
+                let jack = 1.0 - 7e-5 * (1.0 / temp4);
+                let val = 1.0 / (delta * (delta + lambda1_x)) * jack;
+                let range = pair[0].h as usize..pair[1].h as usize;
+                delta_ks[col][range.clone()].fill(delta);
+                j_ks[col][range].fill(val);
+            }
+        }
+
+        let jbar_ks: Vec<Vec<f64>> = j_ks.iter().map(|j| Self::smooth(j, SmoothMode::Sum)).collect();
+        let mut jbar = vec![0.0; len];
+        for (s, value) in jbar.iter_mut().enumerate() {
+            let mut weighted_sum = 0.0;
+            let mut weight_sum = 0.0;
+            for col in 0..k as usize {
+                let weight = 1.0 / delta_ks[col][s];
+                weight_sum += weight;
+                weighted_sum += jbar_ks[col][s].max(0.0).powf(LAMBDA_N) * weight;
+            }
+            *value = (weighted_sum / weight_sum.max(1e-9)).powf(1.0 / LAMBDA_N);
+        }
+        (jbar, delta_ks)
+    }
+
+    fn xbar(k: i32, groups: &[Vec<Note>], len: usize, x: f64) -> Vec<f64> {
+        let mut x_sum = vec![0.0; len];
+        let mut x_k = vec![0.0; len];
+        for (pair_index, &weight) in V30_MATRICES[k as usize].iter().enumerate() {
+            let notes: Vec<Note> = if pair_index == 0 {
+                groups.first().cloned().unwrap_or_default()
+            } else if pair_index == k as usize {
+                groups.last().cloned().unwrap_or_default()
+            } else {
+                let mut notes: Vec<Note> = groups.get(pair_index - 1).into_iter().chain(groups.get(pair_index)).flatten().cloned().collect();
+                notes.sort_by_key(|n| n.h);
+                notes
+            };
+
+            x_k.fill(0.0);
+            for pair in notes.windows(2) {
+                let delta = 0.001 * (pair[1].h - pair[0].h) as f64;
+                let max_xd = x.max(delta);
+                x_k[pair[0].h as usize..pair[1].h as usize].fill(0.16 / (max_xd * max_xd));
+            }
+            for s in 0..len {
+                x_sum[s] += x_k[s] * weight;
+            }
+        }
+        Self::smooth(&x_sum, SmoothMode::Sum)
+    }
+
+    fn pbar(ln_seq: &[Note], note_seq: &[Note], len: usize, x: f64) -> Vec<f64> {
+        let mut ln_bodies = vec![0.0; len];
+        for note in ln_seq {
+            let t1 = (note.h + 80).min(note.t);
+            for body in &mut ln_bodies[note.h as usize..t1 as usize] {
+                *body += 0.5;
+            }
+            for body in &mut ln_bodies[t1 as usize..note.t as usize] {
+                *body += 1.0;
+            }
+        }
+        let mut prefix = vec![0.0; len + 1];
+        for i in 1..=len {
+            prefix[i] = prefix[i - 1] + ln_bodies[i - 1];
+        }
+
+        let b = |delta: f64| {
+            let val = 7.5 / delta;
+            if val > 160.0 && val < 360.0 { 1.0 + 1.4e-7 * (val - 160.0) * ((val - 360.0) * (val - 360.0)) } else { 1.0 }
+        };
+
+        let lambda2_scaled = LAMBDA_2 * 0.001;
+        let mut p = vec![0.0; len];
+        for pair in note_seq.windows(2) {
+            let delta = 0.001 * (pair[1].h - pair[0].h) as f64;
+            if delta < 1e-9 {
+                p[pair[0].h as usize] += 1000.0 * (0.02 * (4.0 / x - LAMBDA_3)).sqrt().sqrt();
+                continue;
+            }
+            let (h_l, h_r) = (pair[0].h as usize, pair[1].h as usize);
+            let v = 1.0 + lambda2_scaled * (prefix[h_r] - prefix[h_l]);
+            let offset = if delta < 2.0 * x / 3.0 { delta - x / 2.0 } else { x / 6.0 };
+            let base = (0.08 / x * (1.0 - LAMBDA_3 / x * offset * offset)).sqrt().sqrt() * b(delta) * v / delta;
+            for value in &mut p[h_l..h_r] {
+                *value += base;
+            }
+        }
+        Self::smooth(&p, SmoothMode::Sum)
+    }
+
+    fn abar(delta_ks: &[Vec<f64>], k: i32, len: usize, note_seq: &[Note]) -> (Vec<f64>, Vec<f64>) {
+        let mut key_usage = vec![vec![false; len]; k as usize];
+        for note in note_seq {
+            let start = (note.h - 500).max(0) as usize;
+            let end = if note.t < 0 { (note.h + 500).min(len as i32 - 1) } else { (note.t + 500).min(len as i32 - 1) } as usize;
+            if start < end {
+                key_usage[note.k as usize][start..end].fill(true);
+            }
+        }
+
+        let mut ks = vec![0.0; len];
+        let mut a = vec![1.0; len];
+        let mut columns = Vec::with_capacity(k as usize);
+        for s in 0..len {
+            columns.clear();
+            columns.extend((0..k as usize).filter(|&col| key_usage[col][s]));
+            ks[s] = columns.len().max(1) as f64;
+
+            for pair in columns.windows(2) {
+                let (d1, d2) = (delta_ks[pair[0]][s], delta_ks[pair[1]][s]);
+                let max_delta = d1.max(d2);
+                let dks = (d1 - d2).abs() + (max_delta - 0.3).max(0.0);
+                if dks < 0.02 {
+                    a[s] *= (0.75 + 0.5 * max_delta).min(1.0);
+                } else if dks < 0.07 {
+                    a[s] *= (0.65 + 5.0 * dks + 0.5 * max_delta).min(1.0);
+                }
+            }
+        }
+        (Self::smooth(&a, SmoothMode::Average), ks)
+    }
+
+    fn rbar(tail_seq: &[Note], groups: &[Vec<Note>], len: usize, x: f64) -> Vec<f64> {
+        let heads: Vec<Vec<f64>> = groups.iter().map(|group| group.iter().map(|n| n.h as f64).collect()).collect();
+        let i_vals: Vec<f64> = tail_seq
+            .iter()
+            .map(|note| {
+                let column = groups.get(note.k as usize).map(Vec::as_slice).unwrap_or_default();
+                // As in V3.0, a head missing from the group skips the first later note. Of stacked
+                // heads this takes the first, where V3.0's binary search could land on any of them
+                let index = heads.get(note.k as usize).map_or(0, |heads| math::bisect_left(heads, note.h as f64));
+                let next_head = column.get(index + 1).map_or(1_000_000_000, |n| n.h);
+                let i_h = 0.001 * ((note.t - note.h - 80) as f64).abs() / x;
+                let i_t = 0.001 * ((next_head - note.t - 80) as f64).abs() / x;
+                2.0 / (2.0 + (-5.0 * (i_h - 0.75)).exp() + (-5.0 * (i_t - 0.75)).exp())
+            })
+            .collect();
+
+        let mut r = vec![0.0; len];
+        for i in 0..tail_seq.len().saturating_sub(1) {
+            let delta_r = 0.001 * (tail_seq[i + 1].t - tail_seq[i].t) as f64;
+            let r_val = 0.08 * delta_r.powf(-1.0 / 2.0) * x.powf(-1.0) * (1.0 + LAMBDA_4 * (i_vals[i] + i_vals[i + 1]));
+            r[tail_seq[i].t as usize..tail_seq[i + 1].t as usize].fill(r_val);
+        }
+        Self::smooth(&r, SmoothMode::Sum)
+    }
+
+    // .NET Array.BinarySearch by head time: which of several equal heads is found depends on the probing order
+    // Replaces zeros and NaN with the last valid value
+    fn forward_fill(values: &mut [f64]) {
+        let mut last = 0.0;
+        for value in values {
+            if !value.is_nan() && *value != 0.0 {
+                last = *value;
+            } else {
+                *value = last;
+            }
+        }
+    }
+
+    fn non_negative(mut values: Vec<f64>) -> Vec<f64> {
+        for value in &mut values {
+            *value = value.max(0.0);
+        }
+        values
+    }
+}
@@ -0,0 +1,26 @@
+use crate::note::Note;
+
+/// Deterministic pseudo-random map for tests and benches: `minutes` long, a step every 40-210 ms
+/// with up to `notes_per_step` notes on it, every third note an LN.
+pub fn synthetic_map(key_count: i32, minutes: i32, notes_per_step: i32, seed: u64) -> Vec<Note> {
+    let mut state = seed;
+    let mut next = move |bound: i32| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((state >> 33) % bound as u64) as i32
+    };
+    let mut free_at = vec![0; key_count as usize];
+    let mut notes = vec![];
+    let mut time = 0;
+    while time < minutes * 60_000 {
+        for _ in 0..notes_per_step {
+            let col = next(key_count);
+            if free_at[col as usize] <= time {
+                let tail = if next(3) == 0 { time + 100 + next(900) } else { -1 };
+                free_at[col as usize] = tail.max(time) + 30;
+                notes.push(Note::new(col, time, tail));
+            }
+        }
+        time += 40 + next(170);
+    }
+    notes
+}
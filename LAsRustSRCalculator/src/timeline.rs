@@ -0,0 +1,45 @@
+/// Smallest sampling interval in ms accepted by [`crate::sr::SRCalculator::calculate_strain_timeline`].
+pub const MIN_TIMELINE_INTERVAL: f64 = 1.0;
+
+/// Skill values at one point of a [`StrainTimeline`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SkillValues {
+    pub jack: f64,
+    pub cross_column: f64,
+    pub pressing_intensity: f64,
+    pub unevenness: f64,
+    pub release: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StrainPoint {
+    // Milliseconds from the start of the map
+    pub time: f64,
+    // Combined difficulty D, the value the SR is aggregated from
+    pub difficulty: f64,
+    // Only present when the timeline was requested with skills
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub skills: Option<SkillValues>,
+}
+
+/// Difficulty over time, for drawing a graph under an editor timeline.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct StrainTimeline {
+    // Sampling interval in ms, 0 when the points are the raw corners
+    pub interval: f64,
+    pub points: Vec<StrainPoint>,
+}
+
+impl StrainTimeline {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    /// The point with the highest difficulty.
+    pub fn peak(&self) -> Option<&StrainPoint> {
+        self.points.iter().fold(None, |best: Option<&StrainPoint>, point| match best {
+            Some(b) if b.difficulty >= point.difficulty => Some(b),
+            _ => Some(point),
+        })
+    }
+}
@@ -0,0 +1,232 @@
+/// A single line of the `[TimingPoints]` section.
+///
+/// Uninherited points (red lines) carry the beat length in milliseconds; inherited
+/// points (green lines) carry a negative slider velocity multiplier (`-100 / sv`).
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TimingPoint {
+    pub time: f64,
+    pub beat_length: f64,
+    pub meter: i32,
+    // 0 = beatmap default, 1 = normal, 2 = soft, 3 = drum
+    pub sample_set: i32,
+    pub sample_index: i32,
+    pub volume: i32,
+    pub uninherited: bool,
+    pub kiai: bool,
+    pub omit_first_barline: bool,
+}
+
+impl TimingPoint {
+    /// Parses one timing point line. Missing trailing fields take the osu!stable defaults.
+    pub fn parse(line: &str) -> Option<TimingPoint> {
+        let params: Vec<&str> = line.split(',').map(|p| p.trim()).collect();
+        if params.len() < 2 {
+            return None;
+        }
+
+        let time: f64 = params[0].parse().ok()?;
+        let beat_length: f64 = params[1].parse().ok()?;
+        if !time.is_finite() || !beat_length.is_finite() {
+            return None;
+        }
+
+        let field = |idx: usize, default: i32| -> i32 {
+            params.get(idx).and_then(|p| p.parse().ok()).unwrap_or(default)
+        };
+        let effects = field(7, 0);
+
+        Some(TimingPoint {
+            time,
+            beat_length,
+            meter: field(2, 4),
+            sample_set: field(3, 0),
+            sample_index: field(4, 0),
+            volume: field(5, 100),
+            uninherited: field(6, 1) == 1,
+            kiai: effects & 1 != 0,
+            omit_first_barline: effects & 8 != 0,
+        })
+    }
+
+    /// BPM of an uninherited point, `None` for inherited points.
+    pub fn bpm(&self) -> Option<f64> {
+        if self.uninherited && self.beat_length > 0.0 {
+            Some(60000.0 / self.beat_length)
+        } else {
+            None
+        }
+    }
+
+    /// Slider velocity multiplier of an inherited point (1.0 for uninherited points).
+    pub fn slider_velocity(&self) -> f64 {
+        if self.uninherited || self.beat_length >= 0.0 {
+            1.0
+        } else {
+            (-100.0 / self.beat_length).clamp(0.1, 10.0)
+        }
+    }
+}
+
+/// Timing points of a beatmap, split into BPM (uninherited) and SV (inherited) lines.
+/// Both lists are sorted by time.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ControlPointInfo {
+    pub timing_points: Vec<TimingPoint>,
+    pub inherited_points: Vec<TimingPoint>,
+}
+
+impl ControlPointInfo {
+    pub fn add(&mut self, point: TimingPoint) {
+        let list = if point.uninherited { &mut self.timing_points } else { &mut self.inherited_points };
+        // Stable sort by time so that later lines at the same time win
+        let idx = list.partition_point(|p| p.time <= point.time);
+        list.insert(idx, point);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timing_points.is_empty() && self.inherited_points.is_empty()
+    }
+
+    /// The uninherited point active at `time`. Before the first point the first one applies, as in osu!.
+    pub fn timing_point_at(&self, time: f64) -> Option<&TimingPoint> {
+        Self::active_at(&self.timing_points, time).or(self.timing_points.first())
+    }
+
+    pub fn bpm_at(&self, time: f64) -> Option<f64> {
+        self.timing_point_at(time).and_then(|p| p.bpm())
+    }
+
+    pub fn beat_length_at(&self, time: f64) -> Option<f64> {
+        self.timing_point_at(time).map(|p| p.beat_length)
+    }
+
+    /// Slider velocity multiplier at `time`. A new uninherited point resets the velocity to 1.0.
+    pub fn slider_velocity_at(&self, time: f64) -> f64 {
+        self.inherited_point_at(time).map_or(1.0, |p| p.slider_velocity())
+    }
+
+    /// Multiplier osu!stable applies to the beat length of mania and taiko sliders: `-beat_length / 100` of the
+    /// active green line, clamped to 0.1-100 in single precision. 1.0 when no green line is active.
+    pub fn legacy_bpm_multiplier_at(&self, time: f64) -> f64 {
+        match self.inherited_point_at(time) {
+            Some(p) if p.beat_length < 0.0 => (-p.beat_length as f32).clamp(10.0, 10000.0) as f64 / 100.0,
+            _ => 1.0,
+        }
+    }
+
+    pub fn kiai_at(&self, time: f64) -> bool {
+        let timing = Self::active_at(&self.timing_points, time);
+        let inherited = Self::active_at(&self.inherited_points, time);
+        match (timing, inherited) {
+            (Some(a), Some(b)) => if b.time >= a.time { b.kiai } else { a.kiai },
+            (Some(a), None) => a.kiai,
+            (None, Some(b)) => b.kiai,
+            (None, None) => false,
+        }
+    }
+
+    /// The BPM that is active for the longest time up to `end_time`
+    /// (usually the time of the last hit object).
+    pub fn dominant_bpm(&self, end_time: f64) -> Option<f64> {
+        let mut durations: Vec<(f64, f64)> = vec![];
+        for (i, point) in self.timing_points.iter().enumerate() {
+            if point.beat_length <= 0.0 {
+                continue;
+            }
+            // The first point extends back to the start of the map
+            let start = if i == 0 { 0.0 } else { point.time };
+            let end = self.timing_points.get(i + 1).map_or(end_time, |next| next.time);
+            let duration = (end.min(end_time) - start).max(0.0);
+
+            // Treat beat lengths within 1e-3 ms as the same BPM
+            match durations.iter_mut().find(|(beat_length, _)| (beat_length - point.beat_length).abs() < 1e-3) {
+                Some(entry) => entry.1 += duration,
+                None => durations.push((point.beat_length, duration)),
+            }
+        }
+
+        durations
+            .iter()
+            .fold(None, |best: Option<(f64, f64)>, &(beat_length, duration)| match best {
+                Some((_, best_duration)) if best_duration >= duration => best,
+                _ => Some((beat_length, duration)),
+            })
+            .map(|(beat_length, _)| 60000.0 / beat_length)
+    }
+
+    /// The green line active at `time`, unless a later red line has reset the velocity.
+    fn inherited_point_at(&self, time: f64) -> Option<&TimingPoint> {
+        let inherited = Self::active_at(&self.inherited_points, time)?;
+        match Self::active_at(&self.timing_points, time) {
+            Some(timing) if timing.time > inherited.time => None,
+            _ => Some(inherited),
+        }
+    }
+
+    fn active_at(points: &[TimingPoint], time: f64) -> Option<&TimingPoint> {
+        let idx = points.partition_point(|p| p.time <= time);
+        if idx == 0 { None } else { Some(&points[idx - 1]) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control_points(lines: &[&str]) -> ControlPointInfo {
+        let mut info = ControlPointInfo::default();
+        for line in lines {
+            info.add(TimingPoint::parse(line).unwrap());
+        }
+        info
+    }
+
+    #[test]
+    fn test_timing_queries() {
+        let info = control_points(&[
+            "1000,500,4,2,0,60,1,0",
+            "5000,-50,4,2,0,60,0,1",
+            "9000,250,3,1,1,70,1,0",
+            "20000,-200,4,1,1,70,0,0",
+        ]);
+
+        assert_eq!(info.timing_points.len(), 2);
+        assert_eq!(info.inherited_points.len(), 2);
+
+        // Before the first red line, the first red line applies
+        assert_eq!(info.bpm_at(0.0), Some(120.0));
+        assert_eq!(info.bpm_at(8999.0), Some(120.0));
+        assert_eq!(info.bpm_at(9000.0), Some(240.0));
+        assert_eq!(info.beat_length_at(30000.0), Some(250.0));
+        assert_eq!(info.timing_point_at(9500.0).unwrap().meter, 3);
+
+        assert_eq!(info.slider_velocity_at(4000.0), 1.0);
+        assert_eq!(info.slider_velocity_at(6000.0), 2.0);
+        // Red line resets SV
+        assert_eq!(info.slider_velocity_at(10000.0), 1.0);
+        assert_eq!(info.slider_velocity_at(25000.0), 0.5);
+        assert_eq!(info.legacy_bpm_multiplier_at(6000.0), 0.5);
+        assert_eq!(info.legacy_bpm_multiplier_at(10000.0), 1.0);
+        assert_eq!(info.legacy_bpm_multiplier_at(25000.0), 2.0);
+
+        assert!(!info.kiai_at(4000.0));
+        assert!(info.kiai_at(6000.0));
+        assert!(!info.kiai_at(9500.0));
+
+        // 120 BPM covers 0-9000, 240 BPM covers 9000-30000
+        assert_eq!(info.dominant_bpm(30000.0), Some(240.0));
+        assert_eq!(info.dominant_bpm(12000.0), Some(120.0));
+    }
+
+    #[test]
+    fn test_parse_legacy_timing_point() {
+        let point = TimingPoint::parse("80.9977,333.333").unwrap();
+        assert_eq!(point.meter, 4);
+        assert_eq!(point.volume, 100);
+        assert!(point.uninherited);
+        assert!((point.bpm().unwrap() - 180.0).abs() < 1e-3);
+
+        assert!(TimingPoint::parse("abc,333.333").is_none());
+        assert!(TimingPoint::parse("1000").is_none());
+    }
+}
@@ -10,8 +10,7 @@ namespace LAsOsuBeatmapParser.Analysis
     {
         /// <summary>
         ///     默认交叉矩阵数据，表示各键位两侧的权重分布
-        ///     索引0对应K=1，索引1对应K=2，以此类推
-        ///     null表示不支持该键数
+        ///     索引即键数K（索引0为占位），每个矩阵有K+1个元素
         /// </summary>
         private static readonly double[][] DefaultCrossMatrices =
         [
@@ -27,16 +26,21 @@ namespace LAsOsuBeatmapParser.Analysis
             [0.275, 0.45, 0.35, 0.25, 0.275, 0.275, 0.25, 0.35, 0.45, 0.275],
             [0.325, 0.55, 0.45, 0.35, 0.25, 0.05, 0.25, 0.35, 0.45, 0.55, 0.325],
             // Inferred matrices for K=11 to 18 based on user-specified patterns
-            [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1], // K=11 (odd, unsupported)
+            [0.325, 0.55, 0.45, 0.35, 0.25, 0.325, 0.325, 0.25, 0.35, 0.45, 0.55, 0.325], // K=11 (derived from K=10: centre split into two edge weights, as for 5K/7K/9K)
             [0.8, 0.8, 0.8, 0.6, 0.4, 0.2, 0.05, 0.2, 0.4, 0.6, 0.8, 0.8, 0.8], // K=12 (even, sides 3 columns higher)
-            [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1], // K=13 (odd, unsupported)
+            [0.8, 0.8, 0.8, 0.6, 0.4, 0.2, 0.8, 0.8, 0.2, 0.4, 0.6, 0.8, 0.8, 0.8], // K=13 (derived from K=12)
             [0.4, 0.4, 0.2, 0.2, 0.3, 0.3, 0.1, 0.1, 0.3, 0.3, 0.2, 0.2, 0.4, 0.4, 0.4], // K=14 (wave: low-low-high-high-low-low-high-high)
-            [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1], // K=15 (odd, unsupported)
+            [0.4, 0.4, 0.2, 0.2, 0.3, 0.3, 0.1, 0.4, 0.4, 0.3, 0.3, 0.2, 0.2, 0.4, 0.4, 0.4], // K=15 (derived from K=14)
             [0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.2, 0.1, 0.1, 0.2, 0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.4], // K=16 (wave: low-low-high-high-low-low-high-high-low-low-high-high)
-            [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1], // K=17 (odd, unsupported)
+            [0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.2, 0.1, 0.4, 0.4, 0.2, 0.4, 0.4, 0.2, 0.2, 0.4, 0.4, 0.4], // K=17 (derived from K=16)
             [0.4, 0.4, 0.2, 0.4, 0.2, 0.4, 0.2, 0.3, 0.1, 0.1, 0.3, 0.2, 0.4, 0.2, 0.4, 0.2, 0.4, 0.4, 0.4] // K=18 (wave: low-low-high-low-high-low-high-low-low-high-low-high-low-high)
         ];
 
+        /// <summary>
+        ///     支持的最大键数
+        /// </summary>
+        public static int MaxKeyCount => DefaultCrossMatrices.Length - 1;
+
         /// <summary>
         ///     自定义交叉矩阵，用于覆盖默认数据
         /// </summary>
@@ -49,13 +53,27 @@ namespace LAsOsuBeatmapParser.Analysis
         /// <param name="matrix">自定义矩阵数组，如果为null则清除自定义矩阵</param>
         public static void SetCustomMatrix(int K, double[]? matrix)
         {
-            if (K < 1 || K > DefaultCrossMatrices.Length)
-                throw new ArgumentOutOfRangeException(nameof(K), $"不支持的键数: {K}，支持范围: 1-{DefaultCrossMatrices.Length}");
+            if (K < 1 || K > MaxKeyCount)
+                throw new ArgumentOutOfRangeException(nameof(K), $"不支持的键数: {K}，支持范围: 1-{MaxKeyCount}");
+            if (matrix != null && matrix.Length != K + 1)
+                throw new ArgumentException($"{K}K的交叉矩阵需要{K + 1}个元素，实际为{matrix.Length}", nameof(matrix));
 
             if (matrix == null)
                 CustomMatrices.Remove(K);
             else
                 CustomMatrices[K] = matrix;
+
+            // 同步到Rust实现，未部署dll时只影响C#实现
+            try
+            {
+                SRCalculatorRust.SetCustomCrossMatrix(K, matrix);
+            }
+            catch (DllNotFoundException)
+            {
+            }
+            catch (EntryPointNotFoundException)
+            {
+            }
         }
 
         /// <summary>
@@ -66,7 +84,7 @@ namespace LAsOsuBeatmapParser.Analysis
         /// <returns>交叉矩阵数组，如果不支持返回null</returns>
         public static double[]? GetMatrix(int K)
         {
-            if (K < 1 || K > DefaultCrossMatrices.Length)
+            if (K < 1 || K > MaxKeyCount)
                 return null;
 
             return CustomMatrices.TryGetValue(K, out var customMatrix) ? customMatrix : DefaultCrossMatrices[K];
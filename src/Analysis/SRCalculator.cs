@@ -69,15 +69,15 @@ namespace LAsOsuBeatmapParser.Analysis
                 // 检查游戏模式
                 if (beatmap.Mode.Id != GameMode.Mania.Id)
                 {
-                    Console.Error.WriteLine($"[SR][ERROR] 文件: {filePath}, {SRErrorCodes.GetErrorMessage(-5.0)} (Mode: {beatmap.Mode.Id})");
-                    return -5.0;
+                    Console.Error.WriteLine($"[SR][ERROR] 文件: {filePath}, {SRErrorCodes.GetErrorMessage(-9.0)} (Mode: {beatmap.Mode.Id})");
+                    return -9.0;
                 }
 
-                // 检查是否有notes
+                // 检查是否有notes，与Rust实现一致返回-8而不是0
                 if (beatmap.HitObjects.Count == 0)
                 {
-                    Console.Error.WriteLine($"[SR][ERROR] 文件: {filePath}, 没有HitObjects");
-                    return 0.0;
+                    Console.Error.WriteLine($"[SR][ERROR] 文件: {filePath}, {SRErrorCodes.GetErrorMessage(-8.0)}");
+                    return -8.0;
                 }
 
                 return CalculateSR(beatmap, out _);
@@ -93,9 +93,13 @@ namespace LAsOsuBeatmapParser.Analysis
         ///     Calculates SR from raw beatmap content.
         /// </summary>
         /// <param name="content">String containing .osu file contents.</param>
-        /// <returns>Calculated SR value.</returns>
-        public double CalculateSRFromContentCS(string content)
+        /// <param name="useRust">Parse and calculate in Rust via <see cref="SRCalculatorRust.CalculateSR_FromContent" />.</param>
+        /// <returns>Calculated SR value or negative error code.</returns>
+        public double CalculateSRFromContentCS(string content, bool useRust = false)
         {
+            if (useRust)
+                return SRCalculatorRust.CalculateSR_FromContent(content);
+
             try
             {
                 var decoder = new LegacyBeatmapDecoder();
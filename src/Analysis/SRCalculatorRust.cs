@@ -2,11 +2,124 @@
 // See the LICENCE file in the repository root for full licence text.
 
 using System;
+using System.Collections.Generic;
 using System.Runtime.InteropServices;
 using System.Text;
 
 namespace LAsOsuBeatmapParser.Analysis
 {
+    /// <summary>
+    ///     单项技能在整张谱面上的统计值
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct SRSkillSummary
+    {
+        /// <summary>加权平均值</summary>
+        public double Mean;
+
+        /// <summary>加权93百分位</summary>
+        public double Percentile93;
+    }
+
+    /// <summary>
+    ///     SR及各技能分项，与Rust端 SrDifficultyAttributes 布局一致
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct SRDifficultyAttributes
+    {
+        public double StarRating;
+        public int    KeyCount;
+        public double OD;
+        public ulong  NoteCount;
+        public ulong  HoldCount;
+
+        /// <summary>纵连</summary>
+        public SRSkillSummary Jack;
+
+        /// <summary>跨列交互</summary>
+        public SRSkillSummary CrossColumn;
+
+        /// <summary>按压强度</summary>
+        public SRSkillSummary PressingIntensity;
+
+        /// <summary>不均匀度</summary>
+        public SRSkillSummary Unevenness;
+
+        /// <summary>面尾释放</summary>
+        public SRSkillSummary Release;
+
+        /// <summary>密度</summary>
+        public SRSkillSummary Density;
+
+        /// <summary>活跃键数</summary>
+        public SRSkillSummary ActiveKeys;
+    }
+
+    /// <summary>
+    ///     判定区间模型，与Rust端 calculate_sr_from_osu_file_with_mods 的参数一致
+    /// </summary>
+    public enum SRHitWindowModel
+    {
+        /// <summary>osu!stable: 64.5 - ceil(3 * OD)</summary>
+        Stable = 0,
+
+        /// <summary>osu!lazer: 64 - 3 * OD</summary>
+        Lazer = 1,
+
+        /// <summary>自定义300判定区间(毫秒)，忽略OD</summary>
+        Custom = 2
+    }
+
+    /// <summary>
+    ///     SR算法版本，与Rust端 SrAlgorithm::id 一致
+    /// </summary>
+    public enum SRAlgorithm
+    {
+        /// <summary>Star-Rating-Rebirth移植版（当前默认算法）</summary>
+        Rebirth = 0,
+
+        /// <summary>SR-V3.0，逐毫秒计算的旧版算法</summary>
+        V30 = 1
+    }
+
+    /// <summary>
+    ///     Rust端日志级别，与Rust log::LevelFilter 一致
+    /// </summary>
+    public enum SRLogLevel
+    {
+        Off = 0,
+        Error = 1,
+        Warn = 2,
+        Info = 3,
+        Debug = 4,
+        Trace = 5
+    }
+
+    /// <summary>
+    ///     成绩判定数，与Rust端 Judgements 布局一致
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct SRJudgements
+    {
+        /// <summary>320 (MAX)</summary>
+        public uint Perfect;
+
+        /// <summary>300</summary>
+        public uint Great;
+
+        /// <summary>200</summary>
+        public uint Good;
+
+        /// <summary>100</summary>
+        public uint Ok;
+
+        /// <summary>50</summary>
+        public uint Meh;
+
+        /// <summary>Miss</summary>
+        public uint Miss;
+    }
+
     /// <summary>
     ///     Rust实现的SR计算器
     /// </summary>
@@ -17,42 +130,360 @@ namespace LAsOsuBeatmapParser.Analysis
         [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true, CharSet = CharSet.Ansi)]
         private static extern double calculate_sr_from_osu_file(IntPtr pathPtr, UIntPtr len);
 
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern double calculate_sr_from_osu_file_with_rate(IntPtr pathPtr, UIntPtr len, double rate);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern double calculate_sr_from_osu_file_with_mods(IntPtr pathPtr, UIntPtr len, uint mods, int hitWindowModel, double greatWindow);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern double calculate_sr_from_osu_file_with_algorithm(IntPtr pathPtr, UIntPtr len, int algorithm, uint mods);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern double calculate_sr_from_osu_file_parallel(IntPtr pathPtr, UIntPtr len);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern int calculate_sr_batch_from_osu_files(IntPtr[] paths, UIntPtr[] lens, UIntPtr count, UIntPtr threads, [Out] double[] output);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern double calculate_pp_from_osu_file(IntPtr pathPtr, UIntPtr len, uint mods, in SRJudgements judgements);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern double calculate_sr_from_osu_content(IntPtr contentPtr, UIntPtr len);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern double calculate_attributes_from_osu_file(IntPtr pathPtr, UIntPtr len, out SRDifficultyAttributes attributes);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern IntPtr calculate_strain_timeline_from_osu_file_with_mods(IntPtr pathPtr, UIntPtr len, double interval, [MarshalAs(UnmanagedType.U1)] bool includeSkills, uint mods, int algorithm);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern void sr_free_string(IntPtr ptr);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern int set_custom_cross_matrix(int k, double[]? matrix, UIntPtr len);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern UIntPtr sr_last_error_message(byte[]? buffer, UIntPtr capacity);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        private delegate void SRLogCallback(int level, IntPtr target, IntPtr message, IntPtr fields);
+
+        [DllImport(DllName, CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        private static extern int sr_set_log_callback(SRLogCallback? callback, int maxLevel);
+
+        // Rust端持有回调指针，委托必须一直保持引用，防止被GC回收
+        private static SRLogCallback? _logCallback;
+
+        /// <summary>
+        ///     接收Rust端日志，handler参数依次为级别、消息和结构化字段JSON（path、kind、code等；
+        ///     非文件调用没有path，改用source标明来源，如content、batch）。
+        ///     handler为null时关闭日志（默认不输出任何日志）
+        /// </summary>
+        /// <param name="handler">日志处理函数</param>
+        /// <param name="maxLevel">最高日志级别</param>
+        /// <returns>成功返回0，日志级别非法返回-10，已安装其他日志器返回-12</returns>
+        public static int SetLogCallback(Action<SRLogLevel, string, string>? handler, SRLogLevel maxLevel = SRLogLevel.Warn)
+        {
+            SRLogCallback? callback = handler == null
+                ? null
+                : (level, target, message, fields) =>
+                    handler((SRLogLevel)level, Marshal.PtrToStringUTF8(message) ?? "", Marshal.PtrToStringUTF8(fields) ?? "{}");
+
+            int result = sr_set_log_callback(callback, (int)maxLevel);
+            if (result == 0)
+                _logCallback = callback;
+
+            return result;
+        }
+
+        /// <summary>
+        ///     获取当前线程上一次Rust调用的详细错误信息，成功时返回null
+        /// </summary>
+        /// <returns>错误信息</returns>
+        public static string? GetLastErrorMessage()
+        {
+            int length = (int)sr_last_error_message(null, UIntPtr.Zero);
+            if (length == 0)
+                return null;
+
+            var buffer = new byte[length + 1];
+            sr_last_error_message(buffer, (UIntPtr)buffer.Length);
+            return Encoding.UTF8.GetString(buffer, 0, length);
+        }
+
+        /// <summary>
+        ///     设置Rust端使用的自定义交叉矩阵，matrix为null时恢复默认
+        /// </summary>
+        /// <param name="K">键数</param>
+        /// <param name="matrix">长度为K+1的矩阵</param>
+        /// <returns>成功返回0，否则为负数错误码</returns>
+        public static int SetCustomCrossMatrix(int K, double[]? matrix)
+        {
+            int result = set_custom_cross_matrix(K, matrix, (UIntPtr)(matrix?.Length ?? 0));
+
+            if (result < 0)
+                Console.Error.WriteLine($"[SR][ERROR] 交叉矩阵 K={K}, 错误: {GetLastErrorMessage() ?? SRErrorCodes.GetErrorMessage(result)}");
+
+            return result;
+        }
+
         /// <summary>
         ///     文件解析SR算法，rust实现，失败返回负数错误码
         /// </summary>
         /// <param name="filePath"></param>
         /// <returns>SR值或负数错误码</returns>
         public static double CalculateSR_FromFile(string filePath)
+        {
+            return CallWithUtf8(filePath, $"文件: {filePath}", calculate_sr_from_osu_file);
+        }
+
+        /// <summary>
+        ///     指定倍速的文件解析SR算法（DT/NC为1.5，HT为0.75），判定区间保持真实时间，失败返回负数错误码
+        /// </summary>
+        /// <param name="filePath"></param>
+        /// <param name="rate">倍速</param>
+        /// <returns>SR值或负数错误码</returns>
+        public static double CalculateSR_FromFile(string filePath, double rate)
+        {
+            return CallWithUtf8(filePath, $"文件: {filePath}, 倍速: {rate}", (ptr, len) => calculate_sr_from_osu_file_with_rate(ptr, len, rate));
+        }
+
+        /// <summary>
+        ///     带mod的文件解析SR算法，mods为osu!的mod位（EZ=2, HR=16, DT=64, HT=256, NC=512, KeyCoop=1<<25, Mirror=1<<30），失败返回负数错误码
+        /// </summary>
+        /// <param name="filePath"></param>
+        /// <param name="mods">osu! mod位</param>
+        /// <param name="hitWindowModel">判定区间模型</param>
+        /// <param name="greatWindow">自定义300判定区间(毫秒)，仅Custom时使用</param>
+        /// <returns>SR值或负数错误码</returns>
+        public static double CalculateSR_FromFileWithMods(string filePath, uint mods, SRHitWindowModel hitWindowModel = SRHitWindowModel.Stable, double greatWindow = 0.0)
+        {
+            return CallWithUtf8(filePath, $"文件: {filePath}, mods: {mods}", (ptr, len) => calculate_sr_from_osu_file_with_mods(ptr, len, mods, (int)hitWindowModel, greatWindow));
+        }
+
+        /// <summary>
+        ///     按指定版本的SR算法计算，用于按原版本重算已保存的SR，失败返回负数错误码
+        /// </summary>
+        /// <param name="filePath"></param>
+        /// <param name="algorithm">SR算法版本</param>
+        /// <param name="mods">osu! mod位</param>
+        /// <returns>SR值或负数错误码</returns>
+        public static double CalculateSR_FromFileWithAlgorithm(string filePath, SRAlgorithm algorithm, uint mods = 0)
+        {
+            return CallWithUtf8(filePath, $"文件: {filePath}, 算法: {algorithm}", (ptr, len) => calculate_sr_from_osu_file_with_algorithm(ptr, len, (int)algorithm, mods));
+        }
+
+        /// <summary>
+        ///     单个谱面内部并行计算SR，结果与CalculateSR_FromFile相同。仅在有空闲核心时可能更快（单核下更慢），使用前请实测。失败返回负数错误码
+        /// </summary>
+        /// <param name="filePath"></param>
+        /// <returns>SR值或负数错误码</returns>
+        public static double CalculateSR_FromFileParallel(string filePath)
+        {
+            return CallWithUtf8(filePath, $"文件: {filePath}", calculate_sr_from_osu_file_parallel);
+        }
+
+        /// <summary>
+        ///     在Rust端并行计算多个文件的SR，只跨一次DLL边界。结果顺序与filePaths一致，失败的文件为负数错误码
+        /// </summary>
+        /// <param name="filePaths">文件路径列表</param>
+        /// <param name="threads">线程数，0为每个核心一个线程，超过核心数时按核心数计</param>
+        /// <returns>每个文件的SR值或负数错误码</returns>
+        public static double[] CalculateSR_Batch(IReadOnlyList<string> filePaths, int threads = 0)
+        {
+            var results = new double[filePaths.Count];
+            if (filePaths.Count == 0)
+                return results;
+
+            var paths = new IntPtr[filePaths.Count];
+            var lens  = new UIntPtr[filePaths.Count];
+
+            try
+            {
+                for (int i = 0; i < filePaths.Count; i++)
+                {
+                    byte[] pathBytes = Encoding.UTF8.GetBytes(filePaths[i] ?? string.Empty);
+                    paths[i] = Marshal.AllocHGlobal(Math.Max(pathBytes.Length, 1));
+                    Marshal.Copy(pathBytes, 0, paths[i], pathBytes.Length);
+                    lens[i] = (UIntPtr)pathBytes.Length;
+                }
+
+                int code = calculate_sr_batch_from_osu_files(paths, lens, (UIntPtr)filePaths.Count, (UIntPtr)Math.Max(threads, 0), results);
+
+                if (code < 0)
+                {
+                    string reason = GetLastErrorMessage() ?? SRErrorCodes.GetErrorMessage(code);
+                    Console.Error.WriteLine($"[SR][ERROR] 批量计算失败: {reason} (错误码: {code})");
+                    Array.Fill(results, (double)code);
+                    return results;
+                }
+
+                for (int i = 0; i < results.Length; i++)
+                {
+                    if (results[i] < 0.0)
+                        Console.Error.WriteLine($"[SR][ERROR] 文件: {filePaths[i]}, 错误: {SRErrorCodes.GetErrorMessage(results[i])} (错误码: {results[i]})");
+                }
+
+                return results;
+            }
+            catch (Exception ex)
+            {
+                Console.Error.WriteLine($"[SR][ERROR] 批量计算异常: {ex.Message}");
+                Array.Fill(results, -7.0); // SR计算内部错误
+                return results;
+            }
+            finally
+            {
+                foreach (IntPtr path in paths)
+                {
+                    if (path != IntPtr.Zero)
+                        Marshal.FreeHGlobal(path);
+                }
+            }
+        }
+
+        /// <summary>
+        ///     按osu! mania pp公式计算成绩的pp，SR按mods计算，失败返回负数错误码
+        /// </summary>
+        /// <param name="filePath"></param>
+        /// <param name="judgements">判定数</param>
+        /// <param name="mods">osu! mod位</param>
+        /// <returns>pp值或负数错误码</returns>
+        public static double CalculatePP_FromFile(string filePath, SRJudgements judgements, uint mods = 0)
+        {
+            return CallWithUtf8(filePath, $"文件: {filePath}", (ptr, len) => calculate_pp_from_osu_file(ptr, len, mods, in judgements));
+        }
+
+        /// <summary>
+        ///     内容解析SR算法，rust实现，无需写临时文件，失败返回负数错误码
+        /// </summary>
+        /// <param name="content">.osu文件内容</param>
+        /// <returns>SR值或负数错误码</returns>
+        public static double CalculateSR_FromContent(string content)
+        {
+            return CallWithUtf8(content, "内容解析", calculate_sr_from_osu_content, "内容为空");
+        }
+
+        /// <summary>
+        ///     文件解析SR及各技能分项，rust实现，失败返回null
+        /// </summary>
+        /// <param name="filePath"></param>
+        /// <returns>SR分项或null</returns>
+        public static SRDifficultyAttributes? CalculateAttributes_FromFile(string filePath)
+        {
+            SRDifficultyAttributes attributes = default;
+            double result = CallWithUtf8(filePath, $"文件: {filePath}", (ptr, len) => calculate_attributes_from_osu_file(ptr, len, out attributes));
+            return result < 0.0 ? null : attributes;
+        }
+
+        /// <summary>
+        ///     把text按UTF-8复制到非托管内存后调用Rust，负数结果和异常都会记录日志，调用后释放内存
+        /// </summary>
+        /// <param name="text">文件路径或.osu内容</param>
+        /// <param name="source">日志中的来源，如"文件: xxx"</param>
+        /// <param name="call">Rust函数，参数为UTF-8指针和字节长度</param>
+        /// <param name="emptyError">text为空时的日志</param>
+        /// <returns>Rust返回值，text为空时为-2，异常时为-7</returns>
+        private static double CallWithUtf8(string text, string source, Func<IntPtr, UIntPtr, double> call, string emptyError = "文件路径为空")
+        {
+            if (string.IsNullOrEmpty(text))
+            {
+                Console.Error.WriteLine($"[SR][ERROR] {emptyError}");
+                return -2.0;
+            }
+
+            IntPtr textPtr = IntPtr.Zero;
+
+            try
+            {
+                byte[] textBytes = Encoding.UTF8.GetBytes(text);
+                textPtr = Marshal.AllocHGlobal(textBytes.Length);
+                Marshal.Copy(textBytes, 0, textPtr, textBytes.Length);
+
+                double result = call(textPtr, (UIntPtr)textBytes.Length);
+
+                if (result < 0.0)
+                {
+                    string reason = GetLastErrorMessage() ?? SRErrorCodes.GetErrorMessage(result);
+                    Console.Error.WriteLine($"[SR][ERROR] {source}, 错误: {reason} (错误码: {result})");
+                }
+
+                return result;
+            }
+            catch (Exception ex)
+            {
+                Console.Error.WriteLine($"[SR][ERROR] {source}, 异常: {ex.Message}");
+                return -7.0; // SR计算内部错误
+            }
+            finally
+            {
+                if (textPtr != IntPtr.Zero)
+                    Marshal.FreeHGlobal(textPtr);
+            }
+        }
+
+        /// <summary>
+        ///     难度随时间变化的曲线（JSON），用于在编辑器时间轴下绘图，失败返回null
+        /// </summary>
+        /// <param name="filePath"></param>
+        /// <param name="interval">采样间隔(毫秒)，0表示不重采样，0到1之间的值视为非法参数</param>
+        /// <param name="includeSkills">是否包含Jbar/Xbar/Pbar/Abar/Rbar分项</param>
+        /// <returns>{"interval":..,"points":[{"time":..,"difficulty":..,"skills":{..}}]}</returns>
+        public static string? GetStrainTimelineJson(string filePath, double interval = 1000.0, bool includeSkills = false)
+        {
+            return GetStrainTimelineJson(filePath, interval, includeSkills, 0, SRAlgorithm.Rebirth);
+        }
+
+        /// <summary>
+        ///     应用mod和指定SR算法后的难度曲线（JSON），与CalculateSR_FromFileWithAlgorithm结果一致，失败返回null。
+        ///     时间为实际游玩时间，DT下曲线长度为原来的2/3
+        /// </summary>
+        /// <param name="filePath"></param>
+        /// <param name="interval">采样间隔(毫秒)，0表示不重采样，0到1之间的值视为非法参数</param>
+        /// <param name="includeSkills">是否包含Jbar/Xbar/Pbar/Abar/Rbar分项</param>
+        /// <param name="mods">osu! mod位</param>
+        /// <param name="algorithm">SR算法版本</param>
+        /// <returns>{"interval":..,"points":[{"time":..,"difficulty":..,"skills":{..}}]}</returns>
+        public static string? GetStrainTimelineJson(string filePath, double interval, bool includeSkills, uint mods, SRAlgorithm algorithm)
         {
             if (string.IsNullOrEmpty(filePath))
             {
                 Console.Error.WriteLine($"[SR][ERROR] 文件路径为空");
-                return -2.0;
+                return null;
             }
 
+            IntPtr pathPtr = IntPtr.Zero;
+            IntPtr jsonPtr = IntPtr.Zero;
+
             try
             {
                 byte[] pathBytes = Encoding.UTF8.GetBytes(filePath);
-                IntPtr pathPtr = Marshal.AllocHGlobal(pathBytes.Length);
+                pathPtr = Marshal.AllocHGlobal(pathBytes.Length);
                 Marshal.Copy(pathBytes, 0, pathPtr, pathBytes.Length);
 
-                double result = calculate_sr_from_osu_file(pathPtr, (UIntPtr)pathBytes.Length);
-
-                Marshal.FreeHGlobal(pathPtr);
+                jsonPtr = calculate_strain_timeline_from_osu_file_with_mods(pathPtr, (UIntPtr)pathBytes.Length, interval, includeSkills, mods, (int)algorithm);
 
-                // Check for error (negative values indicate errors)
-                if (result < 0.0)
+                if (jsonPtr == IntPtr.Zero)
                 {
-                    string reason = SRErrorCodes.GetErrorMessage(result);
-                    Console.Error.WriteLine($"[SR][ERROR] 文件: {filePath}, 错误: {reason} (错误码: {result})");
+                    Console.Error.WriteLine($"[SR][ERROR] 文件: {filePath}, 错误: {GetLastErrorMessage()}");
+                    return null;
                 }
 
-                return result;
+                return Marshal.PtrToStringUTF8(jsonPtr);
             }
             catch (Exception ex)
             {
                 Console.Error.WriteLine($"[SR][ERROR] 文件: {filePath}, 异常: {ex.Message}");
-                return -6.0; // SR计算失败
+                return null;
+            }
+            finally
+            {
+                // 必须由Rust端释放
+                if (jsonPtr != IntPtr.Zero)
+                    sr_free_string(jsonPtr);
+                if (pathPtr != IntPtr.Zero)
+                    Marshal.FreeHGlobal(pathPtr);
             }
         }
     }
@@ -8,16 +8,21 @@ namespace LAsOsuBeatmapParser.Analysis
     public static class SRErrorCodes
     {
         /// <summary>
-        ///     错误代码与消息映射
+        ///     错误代码与消息映射，与Rust端SrError::code()一一对应
         /// </summary>
         public static readonly Dictionary<double, string> ErrorMessages = new Dictionary<double, string>
         {
             [-2.0] = "路径字符串无效",
             [-3.0] = "文件打开失败",
             [-4.0] = "解析失败",
-            [-5.0] = "数据非法",
-            [-6.0] = "SR计算失败",
-            [-7.0] = "SR计算panic"
+            [-5.0] = "OD非法",
+            [-6.0] = "不支持的键数",
+            [-7.0] = "SR计算内部错误",
+            [-8.0] = "谱面没有notes",
+            [-9.0] = "不支持的游戏模式",
+            [-10.0] = "计算参数非法",
+            [-11.0] = "谱面过长（SR-V3.0最多30分钟）",
+            [-12.0] = "已安装其他日志器"
         };
 
         /// <summary>
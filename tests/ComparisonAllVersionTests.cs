@@ -83,6 +83,8 @@ namespace LAsOsuBeatmapParser.Tests
                 ("C# V3.0", bm => SRCalculatorV30.Instance.CalculateSR(bm, out _)),
                 ("C# V2.3", CalculateWithV23),
                 ("Rust FromFile", bm => SRCalculatorRust.CalculateSR_FromFile(SingleTestFile)),
+                ("Rust FromContent", bm => SRCalculator.Instance.CalculateSRFromContentCS(File.ReadAllText(SingleTestFile), useRust: true)),
+                ("Rust V3.0", bm => SRCalculatorRust.CalculateSR_FromFileWithAlgorithm(SingleTestFile, SRAlgorithm.V30)),
                 ("Python FromFile", bm =>
                     {
                         double? result = SRCalculatorPython.CalculateSR_FromFile(SingleTestFile);
@@ -142,6 +144,11 @@ namespace LAsOsuBeatmapParser.Tests
                 }
             }
 
+            // Rust的V3.0版本需与C#的V3.0结果一致
+            double csharpV30 = results.First(r => r.algorithm == "C# V3.0").sr;
+            double rustV30   = results.First(r => r.algorithm == "Rust V3.0").sr;
+            Assert.True(Math.Abs(csharpV30 - rustV30) < 1e-6, $"Rust V3.0 与 C# V3.0 结果不一致: {rustV30:F6} vs {csharpV30:F6}");
+
             _output.WriteLine("✅ 单一文件对比测试完成");
         }
 
@@ -172,6 +179,8 @@ namespace LAsOsuBeatmapParser.Tests
                 ("C# FromFile", (bm, fp) => CalculateSRFromFile(fp)),
                 ("C# FromContent", (bm, fp) => CalculateSRFromContent(File.ReadAllText(fp))),
                 ("Rust FromFile", (bm, fp) => SRCalculatorRust.CalculateSR_FromFile(fp)),
+                ("Rust FromContent", (bm, fp) => SRCalculator.Instance.CalculateSRFromContentCS(File.ReadAllText(fp), useRust: true)),
+                ("Rust V3.0", (bm, fp) => SRCalculatorRust.CalculateSR_FromFileWithAlgorithm(fp, SRAlgorithm.V30)),
                 ("Python FromFile", (bm, fp) => SRCalculatorPython.CalculateSR_FromFile(fp) ?? -1)
             };
 
@@ -3,6 +3,7 @@ using System.Collections.Generic;
 using System.Diagnostics;
 using System.IO;
 using System.Linq;
+using System.Text;
 using BenchmarkDotNet.Attributes;
 using LAsOsuBeatmapParser.Analysis;
 using LAsOsuBeatmapParser.Beatmaps;
@@ -98,6 +99,71 @@ namespace LAsOsuBeatmapParser.Tests
                     Assert.True(Math.Abs(csSr.Value - pySr.Value) < 0.001, $"C# SR {csSr.Value} vs Python {pySr.Value}");
             }
         }
+
+        /// <summary>
+        ///     11K/13K/15K/17K及18K的固定谱面，与Rust端test_odd_key_counts_above_10k使用相同的pattern和参考值，
+        ///     参考值由C#实现（PythonPortAlgorithm）验证，Rust实现需在0.001内一致
+        /// </summary>
+        [Fact]
+        public void Test_OddKeyCountsAbove10K_PinnedValues()
+        {
+            static (int Column, int Head, int Tail)[] Pattern(int k)
+            {
+                return Enumerable.Range(0, 600).Select(i => ((i * 7 + i / 3) % k, i * 90, i % 9 == 0 ? i * 90 + 250 : -1)).ToArray();
+            }
+
+            int[] columns = [0, 5, 11, 17];
+            var cases = new List<(string Name, int K, (int Column, int Head, int Tail)[] Notes, double Expected)>
+            {
+                ("11K", 11, Pattern(11), 3.596664668579102),
+                ("13K", 13, Pattern(13), 3.478764698265222),
+                ("15K", 15, Pattern(15), 3.7706354740072094),
+                ("17K", 17, Pattern(17), 2.7612356501878734),
+                ("18K max column", 18, Enumerable.Range(0, 600).Select(i => (i % 4 == 0 ? 17 : (i * 7 + i / 3) % 18, i * 90, i % 9 == 0 ? i * 90 + 250 : -1)).ToArray(), 3.4779772607656967),
+                ("18K sparse columns", 18, Enumerable.Range(0, 500).Select(i => (columns[(i * 3 + i / 5) % 4], i * 110, i % 7 == 0 ? i * 110 + 100 : -1)).ToArray(), 2.9005994824591403)
+            };
+
+            foreach ((string name, int k, (int Column, int Head, int Tail)[] notes, double expected) in cases)
+            {
+                string content = BuildManiaContent(k, notes);
+                double csSr   = SRCalculator.Instance.CalculateSRFromContentCS(content);
+                double rustSr = SRCalculator.Instance.CalculateSRFromContentCS(content, true);
+                _output.WriteLine($"{name} | C# {csSr:F6} | Rust {rustSr:F6} | 参考 {expected:F6}");
+
+                Assert.True(Math.Abs(csSr - expected) < 0.001, $"{name}: C# SR {csSr} vs {expected}");
+                Assert.True(Math.Abs(rustSr - expected) < 0.001, $"{name}: Rust SR {rustSr} vs {expected}");
+            }
+        }
+
+        private static string BuildManiaContent(int k, IEnumerable<(int Column, int Head, int Tail)> notes)
+        {
+            var content = new StringBuilder();
+            content.AppendLine("osu file format v14");
+            content.AppendLine();
+            content.AppendLine("[General]");
+            content.AppendLine("Mode: 3");
+            content.AppendLine();
+            content.AppendLine("[Difficulty]");
+            content.AppendLine("HPDrainRate:8");
+            content.AppendLine($"CircleSize:{k}");
+            content.AppendLine("OverallDifficulty:8");
+            content.AppendLine("ApproachRate:5");
+            content.AppendLine("SliderMultiplier:1.4");
+            content.AppendLine("SliderTickRate:1");
+            content.AppendLine();
+            content.AppendLine("[TimingPoints]");
+            content.AppendLine("0,500,4,1,0,100,1,0");
+            content.AppendLine();
+            content.AppendLine("[HitObjects]");
+
+            foreach ((int column, int head, int tail) in notes)
+            {
+                int x = (int)((column + 0.5) * 512 / k);
+                content.AppendLine(tail >= 0 ? $"{x},192,{head},128,0,{tail}:0:0:0:0:" : $"{x},192,{head},1,0,0:0:0:0:");
+            }
+
+            return content.ToString();
+        }
     }
 
     [MemoryDiagnoser]
@@ -231,6 +231,64 @@ namespace LAsOsuBeatmapParser.Tests
             }
         }
 
+        [Fact]
+        public void TestBatchMatchesSingleFile()
+        {
+            string[] files = MultipleTestFiles.Append(Path.Combine(TestResourceDir, "missing.osu")).ToArray();
+
+            double[] batch = SRCalculatorRust.CalculateSR_Batch(files, 2);
+
+            Assert.Equal(files.Length, batch.Length);
+            for (int i = 0; i < files.Length; i++)
+            {
+                _output.WriteLine($"{Path.GetFileName(files[i])}: SR = {batch[i]:F4}");
+                Assert.Equal(SRCalculatorRust.CalculateSR_FromFile(files[i]), batch[i]);
+            }
+
+            Assert.Equal(-3.0, batch[^1]);
+        }
+
+        [Fact]
+        public void TestLogCallbackReceivesErrors()
+        {
+            string missing = Path.Combine(TestResourceDir, "log-missing.osu");
+            var records = new List<(SRLogLevel Level, string Message, string Fields)>();
+
+            Assert.Equal(0, SRCalculatorRust.SetLogCallback((level, message, fields) =>
+            {
+                lock (records)
+                    records.Add((level, message, fields));
+            }, SRLogLevel.Error));
+
+            try
+            {
+                Assert.Equal(-3.0, SRCalculatorRust.CalculateSR_FromFile(missing));
+            }
+            finally
+            {
+                SRCalculatorRust.SetLogCallback(null);
+            }
+
+            lock (records)
+            {
+                var record = Assert.Single(records, r => r.Fields.Contains("log-missing.osu"));
+                _output.WriteLine($"{record.Level}: {record.Message} {record.Fields}");
+                Assert.Equal(SRLogLevel.Error, record.Level);
+                Assert.Contains("\"kind\":\"Io\"", record.Fields);
+            }
+        }
+
+        [Fact]
+        public void TestParallelMatchesSingleFile()
+        {
+            foreach (string file in MultipleTestFiles)
+            {
+                double parallel = SRCalculatorRust.CalculateSR_FromFileParallel(file);
+                _output.WriteLine($"{Path.GetFileName(file)}: SR = {parallel:F4}");
+                Assert.Equal(SRCalculatorRust.CalculateSR_FromFile(file), parallel);
+            }
+        }
+
         [Fact]
         public void TestBatchCalculateSRFromFolderCSharp()
         {